@@ -1,51 +1,91 @@
 use std::io::Write;
 use thatsit::{*, layouts::*, engines::tui::{*, crossterm::{QueueableCommand, cursor::*, style::*}}};
 
-pub const KEYS: [&'static str; 6] = [
-  "▀█ ",
-  "▀█ ",
-  "▀█ ",
-  "██ ",
-  "▄█ ",
-  "▄█ ",
-];
+/// Number of rows drawn; each row shows two notes using half blocks
+pub const ROWS: u16 = 12;
 
-pub struct PianoRoll(pub Vec<(usize, usize)>);
+/// Width of the keyboard at the left of the roll
+pub const KEYBOARD: u16 = 4;
+
+/// Notes that are black keys, within an octave
+const BLACK_KEYS: [usize; 5] = [1, 3, 6, 8, 10];
+
+/// A window of 24 notes over a number of steps, with an optional cursor.
+pub struct PianoRoll {
+    /// Notes to draw, as (step, MIDI note)
+    pub notes:  Vec<(usize, usize)>,
+    /// Number of steps to draw
+    pub steps:  usize,
+    /// Lowest note shown
+    pub offset: usize,
+    /// Highlighted step and note
+    pub cursor: Option<(usize, usize)>,
+}
+
+impl PianoRoll {
+
+    /// Glyph for two vertically stacked cells: top, bottom
+    fn half_blocks (top: bool, bottom: bool) -> &'static str {
+        match (top, bottom) {
+            (true,  true)  => "█",
+            (true,  false) => "▀",
+            (false, true)  => "▄",
+            (false, false) => " ",
+        }
+    }
+
+    fn is_white (note: usize) -> bool {
+        !BLACK_KEYS.contains(&(note % 12))
+    }
+
+    fn has_note (&self, step: usize, note: usize) -> bool {
+        self.notes.iter().any(|x|*x == (step, note))
+    }
+
+}
 
 impl<W: Write> Output<TUI<W>, [u16;2]> for PianoRoll {
 
     fn render (&self, engine: &mut TUI<W>) -> Result<Option<[u16;2]>> {
         let area = engine.area;
-        let grid = "█               █               █               █               ";
 
-        for i in 0..13 {
+        for row in 0..ROWS {
+            // Top row shows the highest notes
+            let low  = self.offset + 2 * (ROWS - 1 - row) as usize;
+            let high = low + 1;
+            // Label each C with its octave, counting middle C (60) as C4
+            let label = match [low, high].into_iter().find(|note|note % 12 == 0) {
+                Some(c) => format!("{:<3}", format!("C{}", c as isize / 12 - 1)),
+                None => "   ".to_string()
+            };
             engine.output
                 .queue(SetBackgroundColor(Color::AnsiValue(234)))?
+                .queue(SetForegroundColor(Color::AnsiValue(245)))?
+                .queue(MoveTo(area.x(), area.y() + row))?
+                .queue(Print(label))?
                 .queue(SetForegroundColor(Color::AnsiValue(255)))?
-                .queue(MoveTo(area.x(), area.y() + i))?
-                .queue(Print(KEYS[((i + 3) % 6) as usize]))?
-                .queue(SetForegroundColor(Color::AnsiValue(233)))?
-                .queue(MoveTo(area.x() + 3, area.y() + i))?
-                .queue(Print(grid))?;
+                .queue(Print(Self::half_blocks(Self::is_white(high), Self::is_white(low))))?;
+            for step in 0..self.steps {
+                let (top, bottom) = (self.has_note(step, high), self.has_note(step, low));
+                let cursor = self.cursor.filter(|(x, y)|*x == step && (*y == high || *y == low));
+                let glyph = if let Some((_, note)) = cursor {
+                    engine.output.queue(SetForegroundColor(Color::Yellow))?;
+                    Self::half_blocks(top || note == high, bottom || note == low)
+                } else if top || bottom {
+                    engine.output.queue(SetForegroundColor(Color::AnsiValue(200)))?;
+                    Self::half_blocks(top, bottom)
+                } else {
+                    // Mark the start of each bar
+                    engine.output.queue(SetForegroundColor(Color::AnsiValue(237)))?;
+                    if step % 16 == 0 { "▏" } else { " " }
+                };
+                engine.output
+                    .queue(MoveTo(area.x() + KEYBOARD + step as u16, area.y() + row))?
+                    .queue(Print(glyph))?;
+            }
         }
 
-        engine.output.queue(SetForegroundColor(Color::AnsiValue(200)))?;
-
-        for (time, pitch) in self.0.iter() {
-            let x = area.x() + 3 + *time as u16;
-            let y = area.y() + (*pitch as u16 + *time as u16) % 12;
-            let c = if pitch % 2 == 1 {"▀"} else {"▄"};
-            engine.output.queue(MoveTo(x, y))?.queue(Print(c))?;
-        }
-
-        engine.output
-            .queue(SetForegroundColor(Color::Green))?
-            //.queue(SetForegroundColor(Color::AnsiValue(200)))?
-            .queue(MoveTo(area.x() + 15, area.y() + 6))?.queue(Print("▀ ▄▄"))?
-            .queue(MoveTo(area.x() + 19, area.y() + 7))?.queue(Print("▄▄  ▄▄"))?
-            .queue(MoveTo(area.x() + 25, area.y() + 8))?.queue(Print("▄▄▄▄  "))?;
-
-        Ok::<Option<[u16;2]>, Box<dyn std::error::Error>>(Some(area.size()))
+        Ok::<Option<[u16;2]>, Box<dyn std::error::Error>>(Some([KEYBOARD + self.steps as u16, ROWS]))
     }
 
 }
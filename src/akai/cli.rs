@@ -30,7 +30,12 @@ pub enum AKAI {
         /// Write the disk image to a file
         #[clap(long)]
         export:  Option<std::path::PathBuf>,
-    }
+    },
+    /// Tools for the AKAI MPC2000
+    MPC2000 {
+        #[command(subcommand)]
+        command: crate::mpc2k::MPC2000CLI,
+    },
 }
 
 pub fn run_cli (device: &AKAI) {
@@ -65,6 +70,7 @@ pub fn run_cli (device: &AKAI) {
                 println!("No --export <PATH> specified, not writing.");
             }
         },
+        AKAI::MPC2000 { command } => crate::mpc2k::cli(command),
     }
 }
 
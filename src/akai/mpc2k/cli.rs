@@ -0,0 +1,172 @@
+use super::*;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(clap::Subcommand)]
+pub enum MPC2000CLI {
+
+    /// Manage program files
+    Program {
+        /// Import an existing .PGM file
+        #[clap(long)]
+        import: Option<PathBuf>,
+        /// Build a drum kit from a folder of WAVs, one per pad in alphabetical order
+        #[clap(long)]
+        kit:    Option<PathBuf>,
+        /// Assign a sample to a pad, e.g. A01=KICK
+        #[clap(long)]
+        assign: Vec<String>,
+        /// Set the program name
+        #[clap(long)]
+        name:   Option<String>,
+        /// Write the program to a file
+        #[clap(long)]
+        export: Option<PathBuf>,
+    },
+
+    /// Manage sequence files
+    Sequences {
+        /// Import a .SEQ file or all sequences from an .ALL file
+        #[clap(long)]
+        import:   PathBuf,
+        /// Write each sequence as a Standard MIDI File into this directory
+        #[clap(long)]
+        midi_out: Option<PathBuf>,
+    },
+
+}
+
+pub(crate) fn cli (command: &MPC2000CLI) {
+
+    match command {
+
+        MPC2000CLI::Program { import, kit, assign, name, export } => {
+
+            let mut program = Program::default();
+
+            if let Some(import) = import {
+                println!("Importing {import:?}");
+                program = match Program::read(&crate::read(import)) {
+                    Ok(program) => program,
+                    Err(error) => {
+                        println!("Could not read {import:?}: {error}");
+                        return
+                    }
+                };
+            }
+
+            if let Some(kit) = kit {
+                let mut paths = std::fs::read_dir(kit)
+                    .expect("could not read kit directory")
+                    .filter_map(|entry|entry.ok().map(|entry|entry.path()))
+                    .filter(|path|path.extension()
+                        .map(|ext|ext.to_string_lossy().to_lowercase() == "wav")
+                        .unwrap_or(false))
+                    .collect::<Vec<_>>();
+                paths.sort();
+                if paths.len() > PADS {
+                    println!("Found {} WAVs, only the first {PADS} will be used.", paths.len());
+                }
+                let samples = paths.iter()
+                    .filter_map(|path|path.file_stem())
+                    .map(|stem|stem.to_string_lossy().to_string())
+                    .collect::<Vec<_>>();
+                let kit_name = kit.file_name()
+                    .map(|name|name.to_string_lossy().to_string())
+                    .unwrap_or_else(||program.name.clone());
+                program = Program::kit(&kit_name, &samples);
+            }
+
+            for assignment in assign {
+                if let Some((pad, sample)) = assignment.split_once('=') {
+                    match Program::pad_index(pad) {
+                        Some(pad) => { program.assign(pad, sample); },
+                        None => println!("Ignoring invalid pad {pad:?}")
+                    }
+                } else {
+                    println!("Ignoring invalid assignment {assignment:?}, expected PAD=SAMPLE")
+                }
+            }
+
+            if let Some(name) = name {
+                program.name = name.chars().take(16).collect();
+            }
+
+            println!("{program}");
+
+            if let Some(path) = export {
+                std::fs::File::create(path)
+                    .unwrap()
+                    .write_all(program.write().as_slice())
+                    .unwrap();
+                println!("Wrote {path:?}");
+            } else {
+                println!("No --export <PATH> specified, not writing.");
+            }
+
+        },
+
+        MPC2000CLI::Sequences { import, midi_out } => {
+
+            let data = crate::read(import);
+            let is_all = import.extension()
+                .map(|ext|ext.to_string_lossy().to_lowercase() == "all")
+                .unwrap_or(false);
+            let sequences = if is_all {
+                Sequence::read_all(&data)
+            } else {
+                match Sequence::read(&data) {
+                    Ok(sequence) => vec![sequence],
+                    Err(error) => {
+                        println!("Could not read {import:?}: {error}");
+                        return
+                    }
+                }
+            };
+
+            for (index, sequence) in sequences.iter().enumerate() {
+                println!("{:>3} {sequence}", index + 1);
+            }
+
+            if let Some(dir) = midi_out {
+                std::fs::create_dir_all(dir).unwrap();
+                for (index, sequence) in sequences.iter().enumerate() {
+                    let path = dir.join(format!("{:02}_{}.mid", index + 1, crate::filename::sanitize(&sequence.name)));
+                    std::fs::File::create(&path)
+                        .unwrap()
+                        .write_all(&sequence.to_midi().write())
+                        .unwrap();
+                    println!("Wrote {path:?}");
+                }
+            }
+
+        },
+
+    }
+
+}
+
+impl std::fmt::Display for Program {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Program: {}", self.name)?;
+        for pad in 0..PADS {
+            if let Some(sample) = self.pad_sample(pad) {
+                let note = &self.notes[self.pad_note(pad).unwrap()];
+                write!(f, "\n  {} note {:>2} {:<16} tune {:>4} attack {:>3} decay {:>3}",
+                    Self::pad_name(pad), self.pads[pad], sample, note.tune, note.attack, note.decay
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Sequence {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let tracks = self.tracks.iter().enumerate()
+            .filter(|(index, _)|self.events.iter().any(|event|event.track as usize == *index))
+            .count();
+        write!(f, "{:<16} {:>5.1} BPM {:>3} bars {:>2} tracks {:>5} events",
+            self.name, self.tempo, self.bars.len(), tracks, self.events.len())
+    }
+}
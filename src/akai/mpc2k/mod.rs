@@ -2,3 +2,580 @@ use thatsit::*;
 
 opt_mod::optional_module_flat!("cli": cli);
 opt_mod::optional_module_flat!("tui": tui);
+opt_mod::module_flat!(sequence);
+
+/// File id at the start of every .PGM file
+pub const PGM_FILE_ID: [u8; 2] = [0x07, 0x04];
+/// Fixed bytes between the sample names and the program name
+pub const PGM_NAME_TAG: [u8; 2] = [0x1E, 0x00];
+/// Length of a name field: 16 characters and a null terminator
+pub const NAME_SIZE: usize = 17;
+/// Number of pads (4 banks of 16)
+pub const PADS: usize = 64;
+/// Number of programmable notes (35..=98)
+pub const NOTES: usize = 64;
+/// Lowest programmable note
+pub const FIRST_NOTE: u8 = 35;
+/// Note number meaning "off" in the "also play" and "mute assign" fields
+pub const NOTE_OFF: u8 = 34;
+/// Sample number meaning "no sample assigned"
+pub const NO_SAMPLE: u8 = 0xFF;
+/// Size of a note parameter record
+pub const NOTE_SIZE: usize = 25;
+/// Size of a mixer channel record
+pub const MIXER_SIZE: usize = 6;
+/// Size of the slider record
+pub const SLIDER_SIZE: usize = 10;
+
+/// An MPC2000 program (.PGM).
+///
+/// ```text
+/// 0x0000  2       file id (07 04)
+/// 0x0002  2       number of sample names (LE)
+/// 0x0004  17*N    sample names, 16 chars padded with spaces + 00
+///         2       1E 00
+///         17      program name
+///         1       MIDI program change (0 = off)
+///         25*64   note parameters for notes 35..98
+///         6*64    mixer settings for notes 35..98
+///         10      slider
+///         64      note assigned to each pad (A01..D16)
+/// ```
+#[derive(Debug, Clone)]
+pub struct Program {
+    /// Program name (up to 16 characters)
+    pub name:           String,
+    /// Names of the samples used by this program, indexed by `Note::sample`
+    pub samples:        Vec<String>,
+    /// MIDI program change number that selects this program (0 = off)
+    pub program_change: u8,
+    /// Per-note parameters for notes 35..98
+    pub notes:          Vec<Note>,
+    /// Per-note mixer settings for notes 35..98
+    pub mixer:          Vec<MixerChannel>,
+    /// Assignable slider
+    pub slider:         Slider,
+    /// Note played by each of the 64 pads
+    pub pads:           [u8; PADS],
+}
+
+impl Default for Program {
+    fn default () -> Self {
+        let mut pads = [0; PADS];
+        for (index, pad) in pads.iter_mut().enumerate() {
+            *pad = FIRST_NOTE + index as u8;
+        }
+        Self {
+            name:           "NewPgm-A".into(),
+            samples:        vec![],
+            program_change: 0,
+            notes:          vec![Note::default(); NOTES],
+            mixer:          vec![MixerChannel::default(); NOTES],
+            slider:         Slider::default(),
+            pads
+        }
+    }
+}
+
+impl Program {
+
+    /// Create an empty program
+    pub fn new (name: &str) -> Self {
+        Self { name: name.into(), ..Self::default() }
+    }
+
+    /// Create a program with one sample on each pad, in order
+    pub fn kit (name: &str, samples: &[String]) -> Self {
+        let mut program = Self::new(name);
+        for (pad, sample) in samples.iter().take(PADS).enumerate() {
+            program.assign(pad, sample);
+        }
+        program
+    }
+
+    /// Read a program from the contents of a .PGM file
+    pub fn read (raw: &[u8]) -> std::result::Result<Self, String> {
+        if raw.len() < 4 || raw[0x00..0x02] != PGM_FILE_ID {
+            return Err("not an MPC2000 program".into())
+        }
+        let mut program = Self::default();
+        let count = u16::from_le_bytes([raw[0x02], raw[0x03]]) as usize;
+        let size = Self::size_with(count);
+        if raw.len() < size {
+            return Err(format!("a program with {count} samples is {size} bytes long, found {}", raw.len()))
+        }
+        let mut index = 0x04;
+        for _ in 0..count {
+            program.samples.push(read_name(&raw[index..index+NAME_SIZE]));
+            index += NAME_SIZE;
+        }
+        if raw[index..index+2] != PGM_NAME_TAG {
+            return Err(format!("invalid program header, expected {PGM_NAME_TAG:02X?} at {index:#x}"))
+        }
+        index += 2;
+        program.name = read_name(&raw[index..index+NAME_SIZE]);
+        index += NAME_SIZE;
+        program.program_change = raw[index];
+        index += 1;
+        for note in program.notes.iter_mut() {
+            *note = Note::read(&raw[index..index+NOTE_SIZE]);
+            index += NOTE_SIZE;
+        }
+        for channel in program.mixer.iter_mut() {
+            *channel = MixerChannel::read(&raw[index..index+MIXER_SIZE]);
+            index += MIXER_SIZE;
+        }
+        program.slider = Slider::read(&raw[index..index+SLIDER_SIZE]);
+        index += SLIDER_SIZE;
+        program.pads.copy_from_slice(&raw[index..index+PADS]);
+        Ok(program)
+    }
+
+    /// Serialize the program into the contents of a .PGM file
+    pub fn write (&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(self.size());
+        raw.extend_from_slice(&PGM_FILE_ID);
+        raw.extend_from_slice(&(self.samples.len() as u16).to_le_bytes());
+        for sample in self.samples.iter() {
+            raw.extend_from_slice(&write_name(sample));
+        }
+        raw.extend_from_slice(&PGM_NAME_TAG);
+        raw.extend_from_slice(&write_name(&self.name));
+        raw.push(self.program_change);
+        for note in self.notes.iter() {
+            raw.extend_from_slice(&note.write());
+        }
+        for channel in self.mixer.iter() {
+            raw.extend_from_slice(&channel.write());
+        }
+        raw.extend_from_slice(&self.slider.write());
+        raw.extend_from_slice(&self.pads);
+        raw
+    }
+
+    /// Size of the serialized program in bytes
+    pub fn size (&self) -> usize {
+        Self::size_with(self.samples.len())
+    }
+
+    /// Size of a serialized program with this many sample names
+    fn size_with (samples: usize) -> usize {
+        4 + samples * NAME_SIZE + 2 + NAME_SIZE + 1
+            + NOTES * NOTE_SIZE + NOTES * MIXER_SIZE + SLIDER_SIZE + PADS
+    }
+
+    /// Index into `notes` and `mixer` of the note played by a pad
+    pub fn pad_note (&self, pad: usize) -> Option<usize> {
+        let note = *self.pads.get(pad)?;
+        if note >= FIRST_NOTE && ((note - FIRST_NOTE) as usize) < NOTES {
+            Some((note - FIRST_NOTE) as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Name of the sample played by a pad, if any
+    pub fn pad_sample (&self, pad: usize) -> Option<&str> {
+        let note = &self.notes[self.pad_note(pad)?];
+        if note.sample == NO_SAMPLE {
+            None
+        } else {
+            self.samples.get(note.sample as usize).map(|x|x.as_str())
+        }
+    }
+
+    /// Assign a sample to the note played by a pad, adding it to the sample list if needed
+    pub fn assign (&mut self, pad: usize, sample: &str) -> bool {
+        if let Some(note) = self.pad_note(pad) {
+            let name = sample.chars().take(16).collect::<String>();
+            let index = match self.samples.iter().position(|x|*x == name) {
+                Some(index) => index,
+                None => { self.samples.push(name); self.samples.len() - 1 }
+            };
+            self.notes[note].sample = index as u8;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Remove the sample from the note played by a pad
+    pub fn unassign (&mut self, pad: usize) -> bool {
+        if let Some(note) = self.pad_note(pad) {
+            self.notes[note].sample = NO_SAMPLE;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Name of a pad, e.g. "A01"
+    pub fn pad_name (pad: usize) -> String {
+        format!("{}{:02}", ['A', 'B', 'C', 'D'][(pad / 16) % 4], pad % 16 + 1)
+    }
+
+    /// Index of a pad by name, e.g. "A01" is 0 and "D16" is 63
+    pub fn pad_index (name: &str) -> Option<usize> {
+        let mut chars = name.chars();
+        let bank = match chars.next()?.to_ascii_uppercase() {
+            'A' => 0, 'B' => 1, 'C' => 2, 'D' => 3, _ => return None
+        };
+        let pad = chars.as_str().parse::<usize>().ok()?;
+        if (1..=16).contains(&pad) { Some(bank * 16 + pad - 1) } else { None }
+    }
+
+}
+
+/// Parameters of one of the 64 notes of a program.
+///
+/// ```text
+/// 00      sample number (FF = none)
+/// 01      sound generation mode (0=normal, 1=simult, 2=vel sw, 3=dcy sw)
+/// 02      velocity switch threshold 1
+/// 03      also play note 1 (34 = off)
+/// 04      velocity switch threshold 2
+/// 05      also play note 2 (34 = off)
+/// 06      voice overlap (0=poly, 1=mono, 2=note off)
+/// 07      mute assign 1 (34 = off)
+/// 08      mute assign 2 (34 = off)
+/// 09-0a   tune (signed, LE, -120..120)
+/// 0b      attack
+/// 0c      decay
+/// 0d      decay mode (0=end, 1=start)
+/// 0e      filter frequency
+/// 0f      filter resonance
+/// 10      filter envelope attack
+/// 11      filter envelope decay
+/// 12      filter envelope amount
+/// 13      velocity > level
+/// 14      velocity > attack
+/// 15      velocity > start
+/// 16      velocity > filter frequency
+/// 17      slider parameter (0=tune, 1=decay, 2=attack, 3=filter)
+/// 18      velocity > pitch
+/// ```
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub sample:           u8,
+    pub mode:             u8,
+    pub velocity_1:       u8,
+    pub also_play_1:      u8,
+    pub velocity_2:       u8,
+    pub also_play_2:      u8,
+    pub voice_overlap:    u8,
+    pub mute_1:           u8,
+    pub mute_2:           u8,
+    pub tune:             i16,
+    pub attack:           u8,
+    pub decay:            u8,
+    pub decay_mode:       u8,
+    pub filter_cutoff:    u8,
+    pub filter_resonance: u8,
+    pub filter_attack:    u8,
+    pub filter_decay:     u8,
+    pub filter_amount:    u8,
+    pub vel_to_level:     u8,
+    pub vel_to_attack:    u8,
+    pub vel_to_start:     u8,
+    pub vel_to_filter:    u8,
+    pub slider_parameter: u8,
+    pub vel_to_pitch:     u8,
+}
+
+impl Default for Note {
+    fn default () -> Self {
+        Self {
+            sample:           NO_SAMPLE,
+            mode:             0,
+            velocity_1:       44,
+            also_play_1:      NOTE_OFF,
+            velocity_2:       88,
+            also_play_2:      NOTE_OFF,
+            voice_overlap:    0,
+            mute_1:           NOTE_OFF,
+            mute_2:           NOTE_OFF,
+            tune:             0,
+            attack:           0,
+            decay:            5,
+            decay_mode:       0,
+            filter_cutoff:    100,
+            filter_resonance: 0,
+            filter_attack:    0,
+            filter_decay:     0,
+            filter_amount:    0,
+            vel_to_level:     100,
+            vel_to_attack:    0,
+            vel_to_start:     0,
+            vel_to_filter:    0,
+            slider_parameter: 0,
+            vel_to_pitch:     0,
+        }
+    }
+}
+
+impl Note {
+    pub fn read (raw: &[u8]) -> Self {
+        Self {
+            sample:           raw[0x00],
+            mode:             raw[0x01],
+            velocity_1:       raw[0x02],
+            also_play_1:      raw[0x03],
+            velocity_2:       raw[0x04],
+            also_play_2:      raw[0x05],
+            voice_overlap:    raw[0x06],
+            mute_1:           raw[0x07],
+            mute_2:           raw[0x08],
+            tune:             i16::from_le_bytes([raw[0x09], raw[0x0a]]),
+            attack:           raw[0x0b],
+            decay:            raw[0x0c],
+            decay_mode:       raw[0x0d],
+            filter_cutoff:    raw[0x0e],
+            filter_resonance: raw[0x0f],
+            filter_attack:    raw[0x10],
+            filter_decay:     raw[0x11],
+            filter_amount:    raw[0x12],
+            vel_to_level:     raw[0x13],
+            vel_to_attack:    raw[0x14],
+            vel_to_start:     raw[0x15],
+            vel_to_filter:    raw[0x16],
+            slider_parameter: raw[0x17],
+            vel_to_pitch:     raw[0x18],
+        }
+    }
+    pub fn write (&self) -> [u8; NOTE_SIZE] {
+        let tune = self.tune.to_le_bytes();
+        [
+            self.sample,
+            self.mode,
+            self.velocity_1,
+            self.also_play_1,
+            self.velocity_2,
+            self.also_play_2,
+            self.voice_overlap,
+            self.mute_1,
+            self.mute_2,
+            tune[0],
+            tune[1],
+            self.attack,
+            self.decay,
+            self.decay_mode,
+            self.filter_cutoff,
+            self.filter_resonance,
+            self.filter_attack,
+            self.filter_decay,
+            self.filter_amount,
+            self.vel_to_level,
+            self.vel_to_attack,
+            self.vel_to_start,
+            self.vel_to_filter,
+            self.slider_parameter,
+            self.vel_to_pitch,
+        ]
+    }
+}
+
+/// Mixer settings of one of the 64 notes of a program.
+///
+/// ```text
+/// 00      effects output (0=off, 1=M1, 2=M2, 3=R1, 4=R2)
+/// 01      stereo volume (0-100)
+/// 02      stereo pan (0-100, 50=center)
+/// 03      individual volume (0-100)
+/// 04      individual output (0=off, 1-8)
+/// 05      effects send level (0-100)
+/// ```
+#[derive(Debug, Clone)]
+pub struct MixerChannel {
+    pub fx_output:         u8,
+    pub volume:            u8,
+    pub pan:               u8,
+    pub individual_volume: u8,
+    pub individual_output: u8,
+    pub fx_send:           u8,
+}
+
+impl Default for MixerChannel {
+    fn default () -> Self {
+        Self {
+            fx_output:         0,
+            volume:            100,
+            pan:               50,
+            individual_volume: 100,
+            individual_output: 0,
+            fx_send:           0,
+        }
+    }
+}
+
+impl MixerChannel {
+    pub fn read (raw: &[u8]) -> Self {
+        Self {
+            fx_output:         raw[0x00],
+            volume:            raw[0x01],
+            pan:               raw[0x02],
+            individual_volume: raw[0x03],
+            individual_output: raw[0x04],
+            fx_send:           raw[0x05],
+        }
+    }
+    pub fn write (&self) -> [u8; MIXER_SIZE] {
+        [
+            self.fx_output,
+            self.volume,
+            self.pan,
+            self.individual_volume,
+            self.individual_output,
+            self.fx_send,
+        ]
+    }
+}
+
+/// Assignable slider settings of a program.
+///
+/// ```text
+/// 00      note (34 = off)
+/// 01-02   tune range low/high
+/// 03-04   decay range low/high
+/// 05-06   attack range low/high
+/// 07-08   filter range low/high
+/// 09      MIDI control change (0 = off)
+/// ```
+#[derive(Debug, Clone)]
+pub struct Slider {
+    pub note:        u8,
+    pub tune:        (u8, u8),
+    pub decay:       (u8, u8),
+    pub attack:      (u8, u8),
+    pub filter:      (u8, u8),
+    pub control:     u8,
+}
+
+impl Default for Slider {
+    fn default () -> Self {
+        Self {
+            note:    NOTE_OFF,
+            tune:    (0, 0),
+            decay:   (12, 45),
+            attack:  (0, 20),
+            filter:  (0, 0),
+            control: 0,
+        }
+    }
+}
+
+impl Slider {
+    pub fn read (raw: &[u8]) -> Self {
+        Self {
+            note:    raw[0x00],
+            tune:    (raw[0x01], raw[0x02]),
+            decay:   (raw[0x03], raw[0x04]),
+            attack:  (raw[0x05], raw[0x06]),
+            filter:  (raw[0x07], raw[0x08]),
+            control: raw[0x09],
+        }
+    }
+    pub fn write (&self) -> [u8; SLIDER_SIZE] {
+        [
+            self.note,
+            self.tune.0,   self.tune.1,
+            self.decay.0,  self.decay.1,
+            self.attack.0, self.attack.1,
+            self.filter.0, self.filter.1,
+            self.control,
+        ]
+    }
+}
+
+/// Read a space-padded, null-terminated name
+pub fn read_name (raw: &[u8]) -> String {
+    raw.iter()
+        .take_while(|x|**x != 0x00)
+        .map(|x|*x as char)
+        .collect::<String>()
+        .trim_end()
+        .into()
+}
+
+/// Write a name as 16 space-padded characters and a null terminator
+pub fn write_name (name: &str) -> [u8; NAME_SIZE] {
+    let mut raw = [0x20; NAME_SIZE];
+    for (index, c) in name.chars().filter(|c|c.is_ascii()).take(16).enumerate() {
+        raw[index] = c as u8;
+    }
+    raw[16] = 0x00;
+    raw
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A program with two samples on the first pads, changed from the defaults
+    /// so that every section of the file is checked
+    fn program () -> Program {
+        let mut program = Program::kit("TESTKIT", &["KICK".into(), "SNARE".into()]);
+        program.program_change = 5;
+        program.notes[1].tune = -120;
+        program.notes[1].decay_mode = 1;
+        program.mixer[1].pan = 20;
+        program.slider.note = 40;
+        program.pads[63] = 98;
+        program
+    }
+
+    #[test]
+    fn write_read_program () {
+        let raw = program().write();
+        assert_eq!(raw.len(), program().size());
+        assert_eq!(raw[0x00..0x02], PGM_FILE_ID);
+        assert_eq!(raw[0x02..0x04], [2, 0]);
+        assert_eq!(raw[0x04..0x04 + NAME_SIZE], *b"KICK            \0");
+        let read = Program::read(&raw).unwrap();
+        assert_eq!(read.name, "TESTKIT");
+        assert_eq!(read.samples, vec!["KICK", "SNARE"]);
+        assert_eq!(read.program_change, 5);
+        assert_eq!(read.notes[1].tune, -120);
+        assert_eq!(read.notes[1].decay_mode, 1);
+        assert_eq!(read.mixer[1].pan, 20);
+        assert_eq!(read.slider.note, 40);
+        assert_eq!(read.pads[63], 98);
+        assert_eq!(read.write(), raw);
+    }
+
+    #[test]
+    fn assign_pads () {
+        let mut program = program();
+        assert_eq!(program.pad_sample(0), Some("KICK"));
+        assert_eq!(program.pad_sample(1), Some("SNARE"));
+        assert_eq!(program.pad_sample(2), None);
+        // A sample that is already listed is reused
+        assert!(program.assign(2, "KICK"));
+        assert_eq!(program.samples.len(), 2);
+        assert!(program.assign(3, "A VERY LONG SAMPLE NAME"));
+        assert_eq!(program.samples[2], "A VERY LONG SAMP");
+        assert!(program.unassign(0));
+        assert_eq!(program.pad_sample(0), None);
+        // A pad that plays a note outside 35..98 can't be assigned
+        program.pads[4] = 0;
+        assert!(!program.assign(4, "KICK"));
+        assert_eq!(Program::pad_name(17), "B02");
+        assert_eq!(Program::pad_index("b02"), Some(17));
+        assert_eq!(Program::pad_index("D17"), None);
+        let read = Program::read(&program.write()).unwrap();
+        assert_eq!(read.pad_sample(2), Some("KICK"));
+        assert_eq!(read.pad_sample(3), Some("A VERY LONG SAMP"));
+    }
+
+    #[test]
+    fn read_errors () {
+        let raw = program().write();
+        assert!(Program::read(&raw[..2]).is_err());
+        assert!(Program::read(&raw[..raw.len() - 1]).is_err());
+        let mut wrong = raw.clone();
+        wrong[0x04 + 2 * NAME_SIZE] = 0;
+        assert!(Program::read(&wrong).is_err());
+        wrong[0x00] = 0;
+        assert!(Program::read(&wrong).is_err());
+    }
+
+}
@@ -0,0 +1,357 @@
+use crate::midi;
+
+/// File id at the start of a .SEQ file and of each sequence in an .ALL file
+pub const SEQ_FILE_ID: [u8; 2] = [0x10, 0x08];
+/// Ticks per quarter note
+pub const PPQN: u32 = 96;
+/// Number of tracks per sequence
+pub const TRACKS: usize = 64;
+/// Maximum number of bars per sequence
+pub const BARS: usize = 999;
+/// Offset of the bar list
+pub const BARS_OFFSET: usize = 0x0070;
+/// Offset of the track names
+pub const TRACKS_OFFSET: usize = 0x1470;
+/// Offset of the event list
+pub const EVENTS_OFFSET: usize = 0x1C10;
+/// Size of an event
+pub const EVENT_SIZE: usize = 8;
+/// Marks the end of the event list
+pub const EVENTS_END: [u8; EVENT_SIZE] = [0xFF; EVENT_SIZE];
+
+/// An MPC2000 sequence (.SEQ, or one of the sequences of an .ALL file),
+/// as described in docs/akai/mpc2k_sequence_format.md.
+///
+/// ```text
+/// 0x0000  2       file id (10 08)
+/// 0x0010  16      sequence name
+/// 0x0020  2       number of bars (LE)
+/// 0x0028  2       initial tempo * 10 (LE)
+/// 0x0070  4*999   bars: numerator, denominator, 2 unused bytes
+/// 0x1470  16*64   track names
+/// 0x1870  64      track MIDI output (0=off, 1-32 = 1A-16B)
+/// 0x18B0  64      track bus (0=MIDI, 1-4=DRUM1-4)
+/// 0x18F0  64      track program change (0=off, 1-128)
+/// 0x1930  64      track velocity ratio (1-200%)
+/// 0x1970  64      track status (0=unused, 1=on, 2=off)
+/// 0x1C10  8*N     events, terminated by FF FF FF FF FF FF FF FF
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Sequence {
+    /// Sequence name (up to 16 characters)
+    pub name:   String,
+    /// Initial tempo in beats per minute
+    pub tempo:  f32,
+    /// Time signature of each bar
+    pub bars:   Vec<Bar>,
+    /// Track metadata
+    pub tracks: Vec<Track>,
+    /// Events of all tracks, in order
+    pub events: Vec<Event>,
+}
+
+impl Sequence {
+
+    /// Read a sequence from the contents of a .SEQ file
+    pub fn read (raw: &[u8]) -> std::result::Result<Self, String> {
+        Ok(Self::read_at(raw, 0)?.0)
+    }
+
+    /// Read a sequence starting at an offset, returning it and the offset after its last event
+    pub fn read_at (raw: &[u8], offset: usize) -> std::result::Result<(Self, usize), String> {
+        let raw = raw.get(offset..).unwrap_or(&[]);
+        if raw.len() < EVENTS_OFFSET {
+            return Err(format!("a sequence is at least {EVENTS_OFFSET} bytes long, found {}", raw.len()))
+        }
+        if raw[0x00..0x02] != SEQ_FILE_ID {
+            return Err(format!("not an MPC2000 sequence, found file id {:02X?}", &raw[0x00..0x02]))
+        }
+        let mut sequence = Self {
+            name:  super::read_name(&raw[0x10..0x20]),
+            tempo: u16::from_le_bytes([raw[0x28], raw[0x29]]) as f32 / 10.0,
+            ..Self::default()
+        };
+        let bar_count  = u16::from_le_bytes([raw[0x20], raw[0x21]]) as usize;
+        for index in 0..bar_count.min(BARS) {
+            let start = BARS_OFFSET + index * 4;
+            sequence.bars.push(Bar { numerator: raw[start], denominator: raw[start + 1] });
+        }
+        for index in 0..TRACKS {
+            let start = TRACKS_OFFSET + index * 16;
+            sequence.tracks.push(Track {
+                name:           super::read_name(&raw[start..start+16]),
+                device:         raw[0x1870 + index],
+                bus:            raw[0x18B0 + index],
+                program:        raw[0x18F0 + index],
+                velocity_ratio: raw[0x1930 + index],
+                status:         raw[0x1970 + index],
+            });
+        }
+        let mut index = EVENTS_OFFSET;
+        while index + EVENT_SIZE <= raw.len() && raw[index..index+EVENT_SIZE] != EVENTS_END {
+            sequence.events.push(Event::read(&raw[index..index+EVENT_SIZE]));
+            index += EVENT_SIZE;
+        }
+        Ok((sequence, offset + index + EVENT_SIZE))
+    }
+
+    /// Read all sequences stored back to back in an .ALL file. The first sequence is
+    /// found after the file header by checking each candidate's whole sequence header;
+    /// each following sequence must start right after the end marker of the one before,
+    /// so the contents of event lists are never mistaken for a sequence.
+    pub fn read_all (raw: &[u8]) -> Vec<Self> {
+        let mut sequences = vec![];
+        let Some(mut index) = (0..raw.len()).find(|index|Self::is_sequence(raw, *index)) else {
+            return sequences
+        };
+        while Self::is_sequence(raw, index) {
+            let Ok((sequence, end)) = Self::read_at(raw, index) else {
+                break
+            };
+            if end > raw.len() {
+                // No end marker: the event list runs to the end of the file
+                sequences.push(sequence);
+                break
+            }
+            sequences.push(sequence);
+            index = end;
+        }
+        sequences
+    }
+
+    /// Whether a valid sequence header starts at an offset: the file id, a bar count
+    /// and tempo in range, a valid time signature for every bar, and valid track settings
+    pub fn is_sequence (raw: &[u8], offset: usize) -> bool {
+        let Some(raw) = raw.get(offset..) else { return false };
+        if raw.len() < EVENTS_OFFSET || raw[0x00..0x02] != SEQ_FILE_ID {
+            return false
+        }
+        let bar_count = u16::from_le_bytes([raw[0x20], raw[0x21]]) as usize;
+        let tempo     = u16::from_le_bytes([raw[0x28], raw[0x29]]);
+        let name_ok   = raw[0x10..0x20].iter().all(|c|*c == 0 || (0x20..0x7F).contains(c));
+        let bars_ok   = (0..bar_count).all(|index|{
+            let (numerator, denominator) = (raw[BARS_OFFSET + index * 4], raw[BARS_OFFSET + index * 4 + 1]);
+            (1..=32).contains(&numerator) && [1, 2, 4, 8, 16, 32].contains(&denominator)
+        });
+        let tracks_ok = (0..TRACKS).all(|index|{
+            raw[0x1870 + index] <= 32 && raw[0x18B0 + index] <= 4 &&
+            raw[0x1930 + index] <= 200 && raw[0x1970 + index] <= 2
+        });
+        name_ok && (1..=BARS).contains(&bar_count) && (300..=3000).contains(&tempo) && bars_ok && tracks_ok
+    }
+
+    /// Length of a bar in ticks
+    pub fn bar_length (bar: &Bar) -> u32 {
+        bar.numerator as u32 * PPQN * 4 / bar.denominator.max(1) as u32
+    }
+
+    /// Total length of the sequence in ticks
+    pub fn length (&self) -> u32 {
+        self.bars.iter().map(Self::bar_length).sum()
+    }
+
+    /// Render the sequence as a type-1 Standard MIDI File, with the tempo map in
+    /// the first track and one track for each sequencer track that has events.
+    pub fn to_midi (&self) -> midi::SMF {
+        let mut smf = midi::SMF::new(PPQN as u16);
+        let conductor = smf.track(&self.name);
+        conductor.add(0, midi::Event::tempo(self.tempo));
+        let mut time = 0;
+        let mut signature = (0, 0);
+        for bar in self.bars.iter() {
+            if (bar.numerator, bar.denominator) != signature {
+                signature = (bar.numerator, bar.denominator);
+                conductor.add(time, midi::Event::TimeSignature(bar.numerator, bar.denominator));
+            }
+            time += Self::bar_length(bar);
+        }
+        for event in self.events.iter() {
+            if let EventKind::Tempo(bpm) = event.kind {
+                conductor.add(event.tick, midi::Event::tempo(bpm));
+            }
+        }
+        for (index, track) in self.tracks.iter().enumerate() {
+            let events = self.events.iter()
+                .filter(|event|event.track as usize == index)
+                .filter(|event|!matches!(event.kind, EventKind::Tempo(_)))
+                .collect::<Vec<_>>();
+            if events.is_empty() {
+                continue
+            }
+            let channel = track.channel(index);
+            let name = if !track.name.is_empty() { track.name.clone() } else { format!("Track {:02}", index + 1) };
+            let output = smf.track(&name);
+            if track.program > 0 {
+                output.add(0, midi::Event::Program { channel, program: track.program - 1 });
+            }
+            for event in events {
+                match event.kind {
+                    EventKind::Note { note, duration, velocity } => {
+                        let velocity = (velocity as u32 * track.velocity_ratio() / 100)
+                            .clamp(1, 127) as u8;
+                        output.note(event.tick, duration as u32, channel, note, velocity);
+                    },
+                    EventKind::Program(program) => {
+                        output.add(event.tick, midi::Event::Program { channel, program });
+                    },
+                    EventKind::Control(control, value) => {
+                        output.add(event.tick, midi::Event::Control { channel, control, value });
+                    },
+                    EventKind::Pressure(value) => {
+                        output.add(event.tick, midi::Event::Pressure { channel, value });
+                    },
+                    EventKind::PitchBend(value) => {
+                        output.add(event.tick, midi::Event::PitchBend { channel, value });
+                    },
+                    EventKind::Tempo(_) | EventKind::Unknown(_) => {}
+                }
+            }
+        }
+        smf
+    }
+
+}
+
+/// Time signature of a bar
+#[derive(Debug, Clone, Copy)]
+pub struct Bar {
+    pub numerator:   u8,
+    pub denominator: u8,
+}
+
+/// Sequencer track metadata
+#[derive(Debug, Clone, Default)]
+pub struct Track {
+    /// Track name (up to 16 characters)
+    pub name:           String,
+    /// MIDI output: 0 = off, 1..=16 = port A channels, 17..=32 = port B channels
+    pub device:         u8,
+    /// 0 = MIDI, 1..=4 = DRUM1..DRUM4
+    pub bus:            u8,
+    /// Program change sent when the sequence starts: 0 = off, 1..=128
+    pub program:        u8,
+    /// Velocity scaling in percent (1-200); 0 counts as 100
+    pub velocity_ratio: u8,
+    /// 0 = unused, 1 = on, 2 = off
+    pub status:         u8,
+}
+
+impl Track {
+    /// Velocity scaling in percent. Tracks that were never set up store 0, which
+    /// leaves velocities as recorded.
+    pub fn velocity_ratio (&self) -> u32 {
+        match self.velocity_ratio {
+            0     => 100,
+            ratio => ratio as u32
+        }
+    }
+
+    /// MIDI channel to use when exporting: the track's output channel if set,
+    /// channel 10 for drum tracks, otherwise one channel per track.
+    pub fn channel (&self, index: usize) -> u8 {
+        if self.device > 0 {
+            (self.device - 1) % 16
+        } else if self.bus > 0 {
+            9
+        } else {
+            (index % 16) as u8
+        }
+    }
+}
+
+/// A sequencer event.
+///
+/// ```text
+/// 00-02   tick (LE, 96 PPQN)
+/// 03      track (0-63)
+/// 04      00-7F = note number, B0 = control change, C0 = program change,
+///         D0 = channel pressure, E0 = pitch bend, F0 = tempo change
+/// 05-06   note: duration in ticks (LE); control change: controller, value;
+///         program change/pressure: value; pitch bend: value (LE);
+///         tempo change: tempo * 10 (LE)
+/// 07      note: velocity
+/// ```
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub tick:  u32,
+    pub track: u8,
+    pub kind:  EventKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum EventKind {
+    Note { note: u8, duration: u16, velocity: u8 },
+    Control(u8, u8),
+    Program(u8),
+    Pressure(u8),
+    PitchBend(u16),
+    Tempo(f32),
+    Unknown([u8; 4]),
+}
+
+impl Event {
+    pub fn read (raw: &[u8]) -> Self {
+        let tick  = u32::from_le_bytes([raw[0x00], raw[0x01], raw[0x02], 0x00]);
+        let track = raw[0x03];
+        let word  = u16::from_le_bytes([raw[0x05], raw[0x06]]);
+        let kind  = match raw[0x04] {
+            note @ 0x00..=0x7F => EventKind::Note { note, duration: word, velocity: raw[0x07] },
+            0xB0 => EventKind::Control(raw[0x05], raw[0x06]),
+            0xC0 => EventKind::Program(raw[0x05]),
+            0xD0 => EventKind::Pressure(raw[0x05]),
+            0xE0 => EventKind::PitchBend(word),
+            0xF0 => EventKind::Tempo(word as f32 / 10.0),
+            _    => EventKind::Unknown([raw[0x04], raw[0x05], raw[0x06], raw[0x07]]),
+        };
+        Self { tick, track, kind }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A sequence of two bars with one note, whose event bytes contain the file id
+    fn sequence (name: &[u8]) -> Vec<u8> {
+        let mut raw = vec![0; EVENTS_OFFSET];
+        raw[0x00..0x02].copy_from_slice(&SEQ_FILE_ID);
+        raw[0x10..0x10 + name.len()].copy_from_slice(name);
+        raw[0x20] = 2;
+        raw[0x28..0x2A].copy_from_slice(&900u16.to_le_bytes());
+        raw[BARS_OFFSET..BARS_OFFSET + 6].copy_from_slice(&[4, 4, 0, 0, 3, 4]);
+        raw.extend([0x10, 0x08, 0x00, 0x00, 36, 48, 0, 100]);
+        raw.extend(EVENTS_END);
+        raw
+    }
+
+    #[test]
+    fn read_all () {
+        let mut all = b"MPC2KXL ALL \x10\x08\x00\x00".to_vec();
+        all.extend(sequence(b"FIRST"));
+        all.extend(sequence(b"SECOND"));
+        let sequences = Sequence::read_all(&all);
+        assert_eq!(sequences.len(), 2);
+        assert_eq!(sequences[0].name, "FIRST");
+        assert_eq!(sequences[1].name, "SECOND");
+        assert_eq!(sequences[1].events.len(), 1);
+        assert_eq!(sequences[1].events[0].tick, 0x0810);
+    }
+
+    #[test]
+    fn velocity_ratio () {
+        let sequence = Sequence::read(&sequence(b"RATIO")).unwrap();
+        assert_eq!(sequence.tracks[0].velocity_ratio, 0);
+        assert_eq!(sequence.tracks[0].velocity_ratio(), 100);
+    }
+
+    #[test]
+    fn read_errors () {
+        let raw = sequence(b"SHORT");
+        assert!(Sequence::read(&raw[..0x100]).is_err());
+        let mut wrong = raw.clone();
+        wrong[0x00] = 0x00;
+        assert!(Sequence::read(&wrong).is_err());
+        assert!(Sequence::read_at(&raw, raw.len() + 1).is_err());
+    }
+
+}
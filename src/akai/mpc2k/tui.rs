@@ -1,9 +1,261 @@
-use thatsit::{*, layouts::*};
+use std::io::Write;
+use super::*;
+use thatsit::{
+    *,
+    layouts::*,
+    engines::tui::{
+        *,
+        crossterm::{style::Color, event::{Event, KeyEvent, KeyCode}}
+    },
+    widgets::tui::*
+};
 
-pub struct MPC2000UI;
+/// UI for editing the pad assignments of an AKAI MPC2000 program
+#[derive(Debug, Default)]
+pub struct MPC2000UI {
+    /// File explorer for selecting a program
+    pub file_list: FileList,
+    /// Path of the currently loaded program
+    pub path:      Option<std::path::PathBuf>,
+    /// The currently loaded program
+    pub program:   Option<Program>,
+    /// The currently selected pad (0..64)
+    pub pad:       usize,
+    /// Whether the program was modified since it was loaded
+    pub dirty:     bool,
+    /// Why the last selected file could not be loaded
+    pub error:     Option<String>,
+}
 
 impl MPC2000UI {
+
+    /// Create a new program editor
     pub fn new () -> Self {
-        Self
+        let mut new = Self::default();
+        new.update();
+        new
+    }
+
+    /// Update the file list
+    pub fn update (&mut self) {
+        self.file_list.update();
+    }
+
+    /// Import a program from a file
+    pub fn import (&mut self, path: &std::path::Path) {
+        match Program::read(&crate::read(path)) {
+            Ok(program) => {
+                self.program = Some(program);
+                self.path    = Some(path.into());
+                self.pad     = 0;
+                self.dirty   = false;
+                self.error   = None;
+            },
+            Err(error) => {
+                self.error = Some(format!("Could not read {path:?}: {error}"));
+            }
+        }
+    }
+
+    /// Write the program back to the file it was loaded from
+    pub fn save (&mut self) -> Result<bool> {
+        if let (Some(program), Some(path)) = (&self.program, &self.path) {
+            std::fs::File::create(path)?.write_all(&program.write())?;
+            self.dirty = false;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Move the pad cursor within the current bank, or to the previous/next bank
+    pub fn select (&mut self, dx: isize, dy: isize) -> bool {
+        let bank = self.pad / 16;
+        let x = (self.pad % 4) as isize + dx;
+        let y = ((self.pad % 16) / 4) as isize + dy;
+        if x < 0 || x > 3 || y < 0 || y > 3 {
+            return false
+        }
+        self.pad = bank * 16 + (y * 4 + x) as usize;
+        true
+    }
+
+    /// Switch to the previous or next bank of 16 pads
+    pub fn select_bank (&mut self, delta: isize) -> bool {
+        let bank = (self.pad / 16) as isize + delta;
+        if bank < 0 || bank > 3 {
+            return false
+        }
+        self.pad = bank as usize * 16 + self.pad % 16;
+        true
+    }
+
+    /// Assign the previous or next sample from the program's sample list to the selected pad
+    pub fn cycle_sample (&mut self, delta: isize) -> bool {
+        let Some(program) = &mut self.program else { return false };
+        if let Some(note) = program.pad_note(self.pad) {
+            let count = program.samples.len() as isize;
+            if count == 0 {
+                return false
+            }
+            let current = program.notes[note].sample;
+            let next = if current == NO_SAMPLE {
+                if delta > 0 { 0 } else { count - 1 }
+            } else {
+                (current as isize + delta).rem_euclid(count)
+            };
+            program.notes[note].sample = next as u8;
+            self.dirty = true;
+            return true
+        }
+        false
+    }
+
+    /// Remove the sample from the selected pad
+    pub fn clear_sample (&mut self) -> bool {
+        let Some(program) = &mut self.program else { return false };
+        if program.unassign(self.pad) {
+            self.dirty = true;
+            return true
+        }
+        false
+    }
+
+    /// Format a pad of the grid
+    pub fn format_pad (program: &Program, pad: usize) -> String {
+        format!("{} {:<16}",
+            Program::pad_name(pad),
+            program.pad_sample(pad).unwrap_or("-")
+        )
     }
+
+    pub fn field <T, U> (
+        label: &str, value: impl std::fmt::Display
+    ) -> Fixed<T, U> {
+        let white = |s: String|s.with(Color::White).bold();
+        let green = |s: String|s.with(Color::Green);
+        Fixed::XY((10, 3), Layers::new()
+            .add(Columns::new()
+                .add((2, 1))
+                .add(label.to_string().style(&white)))
+            .add(format!(" {}", value.to_string())
+                .style(&green)
+                .border(Tall, Inset)))
+    }
+
+    pub fn layout_pads <T, U> (&self, program: &Program) -> Rows<T, U> {
+        let style1 = |s: String|s.on(Color::Yellow).with(Color::Black).bold();
+        let style2 = |s: String|s.with(Color::White);
+        let bank = self.pad / 16;
+        let mut rows = Rows::new()
+            .add(format!(" Bank {}", ['A', 'B', 'C', 'D'][bank]).with(Color::White).bold());
+        // The MPC2000 numbers pads from the bottom left, so draw the grid upside down
+        for y in (0..4).rev() {
+            let mut row = Columns::new();
+            for x in 0..4 {
+                let pad = bank * 16 + y * 4 + x;
+                row = row.add(Self::format_pad(program, pad).style(&if pad == self.pad {
+                    style1
+                } else {
+                    style2
+                })).add(1);
+            }
+            rows = rows.add(row);
+        }
+        rows
+    }
+
+    pub fn layout_note <T, U> (&self, program: &Program) -> Rows<T, U> {
+        let Some(index) = program.pad_note(self.pad) else {
+            return Rows::new().add(" This pad plays no note.")
+        };
+        let note  = &program.notes[index];
+        let mixer = &program.mixer[index];
+        Rows::new()
+            .add(Columns::new()
+                .add(Self::field("Pad",    Program::pad_name(self.pad)))
+                .add(Self::field("Note",   program.pads[self.pad]))
+                .add(Self::field("Mode",   note.mode)))
+            .add(Columns::new()
+                .add(Self::field("Tune",   note.tune))
+                .add(Self::field("Attack", note.attack))
+                .add(Self::field("Decay",  note.decay)))
+            .add(Columns::new()
+                .add(Self::field("Freq",   note.filter_cutoff))
+                .add(Self::field("Res",    note.filter_resonance))
+                .add(Self::field("Vel>Lv", note.vel_to_level)))
+            .add(Columns::new()
+                .add(Self::field("Volume", mixer.volume))
+                .add(Self::field("Pan",    mixer.pan))
+                .add(Self::field("Output", mixer.individual_output)))
+    }
+
+}
+
+impl<W: Write> Output<TUI<W>, [u16;2]> for MPC2000UI {
+
+    fn render (&self, engine: &mut TUI<W>) -> Result<Option<[u16;2]>> {
+        if let Some(program) = &self.program {
+            Rows::new()
+                .add(format!(" Program: {}{}", program.name, if self.dirty { " (modified)" } else { "" }))
+                .add(Columns::new()
+                    .border(Tall, Inset)
+                    .add(self.layout_pads(program))
+                    .add(2)
+                    .add(self.layout_note(program)))
+                .add(" Arrows: select pad │ PgUp/PgDn: bank │ Alt-Up/Down: sample │ Del: clear │ s: save")
+        } else {
+            Rows::new()
+                .add(" Select program:")
+                .add(Rows::new().border(Tall, Inset).add(&self.file_list))
+                .add(format!(" {}", self.error.as_deref().unwrap_or("")).with(Color::Red))
+        }.render(engine)
+    }
+
+}
+
+impl Input<TUIInputEvent, bool> for MPC2000UI {
+
+    fn handle (&mut self, event: TUIInputEvent) -> Result<Option<bool>> {
+        Ok(if self.program.is_some() {
+            if *event == key!(Alt-Up) {
+                self.cycle_sample(-1)
+            } else if *event == key!(Alt-Down) {
+                self.cycle_sample(1)
+            } else if *event == key!(Up) {
+                self.select(0, 1)
+            } else if *event == key!(Down) {
+                self.select(0, -1)
+            } else if *event == key!(Left) {
+                self.select(-1, 0)
+            } else if *event == key!(Right) {
+                self.select(1, 0)
+            } else if *event == key!(PageUp) {
+                self.select_bank(-1)
+            } else if *event == key!(PageDown) {
+                self.select_bank(1)
+            } else if *event == key!(Delete) {
+                self.clear_sample()
+            } else if let Event::Key(KeyEvent { code: KeyCode::Char('s'), .. }) = *event {
+                self.save()?
+            } else {
+                false
+            }
+        } else {
+            self.file_list.handle(event)? || if_key!(event => Enter => {
+                if let Some(FileEntry { path, is_dir, .. }) = self.file_list.selected() {
+                    if *is_dir {
+                        std::env::set_current_dir(path)?;
+                        self.update();
+                    } else {
+                        self.import(&std::path::PathBuf::from(path));
+                    }
+                    true
+                } else {
+                    false
+                }
+            })
+        })
+    }
+
 }
@@ -0,0 +1,33 @@
+//! File names made from names stored on the devices, which may contain
+//! characters that are not allowed in paths.
+
+/// Make a name safe to use as a file name: trim it, replace path separators and
+/// other characters that some file systems reject with `_`, and never return
+/// an empty name or one that refers to a directory (`.` or `..`)
+pub fn sanitize (name: &str) -> String {
+    let name: String = name.trim().trim_end_matches('\0').trim().chars()
+        .map(|c|if c.is_control() || "/\\:*?\"<>|".contains(c) { '_' } else { c })
+        .collect();
+    if name.is_empty() || name.chars().all(|c|c == '.') {
+        "_".repeat(name.len().max(1))
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sanitize_names () {
+        assert_eq!(sanitize(" Song One "), "Song One");
+        assert_eq!(sanitize("A/B\\C:D"), "A_B_C_D");
+        assert_eq!(sanitize("What?*"), "What__");
+        assert_eq!(sanitize("Name\0\0"), "Name");
+        assert_eq!(sanitize(".."), "__");
+        assert_eq!(sanitize("   "), "_");
+        assert_eq!(sanitize("v1.2"), "v1.2");
+    }
+
+}
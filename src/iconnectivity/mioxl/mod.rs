@@ -0,0 +1,666 @@
+//! Settings of the iConnectivity mioXL MIDI interface, and the messages that
+//! read and change them, as documented in docs/iconnectivity/sysex_format.md.
+
+use super::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Product ID of the mioXL
+pub const MIOXL_PRODUCT_ID: u16 = 0x13;
+/// Number of presets of the mioXL
+pub const MIOXL_PRESETS: u8 = 8;
+/// Most characters in a port or preset name
+pub const MIOXL_NAME_MAX: usize = 16;
+
+/// Information field: device name, which can be changed
+pub const INFO_DEVICE_NAME: u8 = 0x10;
+/// Information fields that are read from the device
+pub const INFO_FIELDS: [(u8, &str); 7] = [
+    (0x01, "Accessory name"),
+    (0x02, "Manufacturer"),
+    (0x03, "Model"),
+    (0x04, "Serial number"),
+    (0x05, "Firmware version"),
+    (0x06, "Hardware version"),
+    (INFO_DEVICE_NAME, "Device name"),
+];
+
+/// SAVE_RESTORE action: store the current settings into a preset
+pub const SAVE_PRESET: u8 = 0x01;
+/// SAVE_RESTORE action: load a preset into the current settings
+pub const RECALL_PRESET: u8 = 0x02;
+/// SAVE_RESTORE action: reset the current settings to the factory defaults
+pub const FACTORY_RESET: u8 = 0x03;
+
+/// Channel filter bit: note on and note off
+pub const FILTER_NOTES: u8 = 0x01;
+/// Channel filter bit: polyphonic aftertouch
+pub const FILTER_POLY_PRESSURE: u8 = 0x02;
+/// Channel filter bit: control change
+pub const FILTER_CONTROL: u8 = 0x04;
+/// Channel filter bit: program change
+pub const FILTER_PROGRAM: u8 = 0x08;
+/// Channel filter bit: channel aftertouch
+pub const FILTER_CHANNEL_PRESSURE: u8 = 0x10;
+/// Channel filter bit: pitch bend
+pub const FILTER_PITCH_BEND: u8 = 0x20;
+/// System filter bit: system exclusive
+pub const FILTER_SYSEX: u8 = 0x01;
+/// System filter bit: MIDI time code
+pub const FILTER_TIME_CODE: u8 = 0x02;
+/// System filter bit: song position pointer
+pub const FILTER_SONG_POSITION: u8 = 0x04;
+/// System filter bit: song select
+pub const FILTER_SONG_SELECT: u8 = 0x08;
+/// System filter bit: tune request
+pub const FILTER_TUNE_REQUEST: u8 = 0x10;
+/// System filter bit: clock, start, continue and stop
+pub const FILTER_CLOCK: u8 = 0x20;
+/// System filter bit: active sensing and reset
+pub const FILTER_ACTIVE_SENSING: u8 = 0x40;
+
+/// Everything that can be read from and written to a mioXL
+#[derive(Debug, Clone, PartialEq)]
+pub struct MioXL {
+    /// Product ID and serial number that messages are addressed to
+    pub device:   (u16, u64),
+    /// RET_DEVICE - protocol version
+    pub protocol: u8,
+    /// RET_INFO - information fields, by ID
+    pub info:     BTreeMap<u8, String>,
+    /// RET_MIDI_INFO - number of the preset that was last recalled
+    pub preset:   u8,
+    /// RET_MIDI_PORT_INFO - the ports, by ID
+    pub ports:    BTreeMap<u16, MioXLPort>,
+    /// RET_MIDI_PORT_ROUTE - the ports that each port's input is routed to
+    pub routes:   BTreeMap<u16, BTreeSet<u16>>,
+    /// RET_MIDI_PORT_FILTER - filters that differ from the default
+    pub filters:  BTreeMap<(u16, MioXLDirection), MioXLFilter>,
+    /// RET_MIDI_PORT_REMAP - channel remaps that differ from the default
+    pub remaps:   BTreeMap<(u16, MioXLDirection), [u8; 16]>,
+    /// RET_PRESET_NAME - names of the presets
+    pub presets:  BTreeMap<u8, String>,
+    /// Settings that were read from the device or changed since.
+    /// Only these are written back by `updates`.
+    pub known:    BTreeSet<MioXLSetting>,
+}
+
+/// A setting that `updates` writes to the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MioXLSetting {
+    DeviceName,
+    Port(u16),
+    Route(u16),
+    Filter(u16, MioXLDirection),
+    Remap(u16, MioXLDirection),
+    PresetName(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MioXLDirection {
+    Input  = 0,
+    Output = 1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MioXLPortType {
+    DIN       = 1,
+    USBDevice = 2,
+    USBHost   = 3,
+    Ethernet  = 4,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MioXLPort {
+    /// 0x00 - port ID, counting from 1
+    pub id:      u16,
+    /// 0x02
+    pub kind:    MioXLPortType,
+    /// 0x03 - jack number, counting from 1; several USB and Ethernet ports share a jack
+    pub jack:    u8,
+    /// 0x04 bit 0
+    pub input:   bool,
+    /// 0x04 bit 1
+    pub output:  bool,
+    /// 0x05 - length, then the characters
+    pub name:    String,
+}
+
+/// Which messages pass through an input or output of a port. A set bit blocks the messages.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MioXLFilter {
+    /// FILTER_SYSEX ... FILTER_ACTIVE_SENSING
+    pub system:      u8,
+    /// FILTER_NOTES ... FILTER_PITCH_BEND, for each channel
+    pub channels:    [u8; 16],
+    /// Controllers that are blocked on every channel
+    pub controllers: BTreeSet<u8>,
+}
+
+impl Default for MioXL {
+    fn default () -> Self {
+        Self {
+            device:   (MIOXL_PRODUCT_ID, 0),
+            protocol: 0,
+            info:     BTreeMap::new(),
+            preset:   0,
+            ports:    BTreeMap::new(),
+            routes:   BTreeMap::new(),
+            filters:  BTreeMap::new(),
+            remaps:   BTreeMap::new(),
+            presets:  BTreeMap::new(),
+            known:    BTreeSet::new(),
+        }
+    }
+}
+
+impl MioXL {
+
+    /// Model of a mioXL with the given serial number
+    pub fn new (serial: u64) -> Self {
+        Self { device: (MIOXL_PRODUCT_ID, serial), ..Self::default() }
+    }
+
+    /// A message to this mioXL
+    pub fn message (&self, command: u16, data: Vec<u8>) -> IConnectivitySysEx {
+        IConnectivitySysEx::new(self.device, command, data)
+    }
+
+    /// Messages that ask for the device information and the number of ports.
+    /// Send `port_queries` after their answers are applied.
+    pub fn queries (&self) -> Vec<IConnectivitySysEx> {
+        let mut queries = vec![self.message(GET_DEVICE, vec![])];
+        for (id, _) in INFO_FIELDS.iter() {
+            queries.push(self.message(GET_INFO, vec![*id]));
+        }
+        queries.push(self.message(GET_MIDI_INFO, vec![]));
+        for preset in 1..=MIOXL_PRESETS {
+            queries.push(self.message(GET_PRESET_NAME, vec![preset]));
+        }
+        queries
+    }
+
+    /// Messages that ask for the settings of every known port
+    pub fn port_queries (&self) -> Vec<IConnectivitySysEx> {
+        let mut queries = vec![];
+        for id in self.ports.keys() {
+            let port = write_u14(*id).to_vec();
+            queries.push(self.message(GET_MIDI_PORT_INFO, port.clone()));
+            queries.push(self.message(GET_MIDI_PORT_ROUTE, port.clone()));
+            for direction in [MioXLDirection::Input, MioXLDirection::Output] {
+                let port = [port.as_slice(), &[direction as u8]].concat();
+                queries.push(self.message(GET_MIDI_PORT_FILTER, port.clone()));
+                queries.push(self.message(GET_MIDI_PORT_REMAP, port));
+            }
+        }
+        queries
+    }
+
+    /// Update the model from a message sent by the device.
+    /// Returns false if the message is not an answer from this device.
+    pub fn apply (&mut self, message: &IConnectivitySysEx) -> std::result::Result<bool, String> {
+        if message.product != self.device.0 || (self.device.1 != 0 && message.serial != self.device.1) {
+            return Ok(false)
+        }
+        let data = message.data.as_slice();
+        let short = |size: usize|if data.len() < size {
+            Err(format!("command {:#04x} should have at least {size} bytes, found {}", message.command, data.len()))
+        } else {
+            Ok(())
+        };
+        match message.command {
+            RET_DEVICE => {
+                short(1)?;
+                self.device.1 = message.serial;
+                self.protocol = data[0];
+            },
+            RET_INFO => {
+                short(1)?;
+                self.info.insert(data[0], read_text(&data[1..]));
+                if data[0] == INFO_DEVICE_NAME {
+                    self.changed(MioXLSetting::DeviceName);
+                }
+            },
+            RET_MIDI_INFO => {
+                short(3)?;
+                let ports = read_u14(data);
+                self.preset = data[2];
+                // Placeholders, to be filled by port_queries. They are not known
+                // until read, so that updates don't overwrite the device with them.
+                for id in 1..=ports {
+                    self.ports.entry(id).or_insert_with(||MioXLPort::new(id));
+                }
+                self.ports.retain(|id, _|*id <= ports);
+                self.known.retain(|setting|match setting {
+                    MioXLSetting::Port(id) | MioXLSetting::Route(id) |
+                    MioXLSetting::Filter(id, _) | MioXLSetting::Remap(id, _) => *id <= ports,
+                    _ => true
+                });
+            },
+            RET_MIDI_PORT_INFO => {
+                let port = MioXLPort::read(data)?;
+                self.changed(MioXLSetting::Port(port.id));
+                self.ports.insert(port.id, port);
+            },
+            RET_MIDI_PORT_ROUTE => {
+                short(2)?;
+                let targets = read_bitmap(&data[2..]);
+                self.route_all(read_u14(data), targets);
+            },
+            RET_MIDI_PORT_FILTER => {
+                short(3)?;
+                let direction = MioXLDirection::read(data[2])?;
+                self.filter(read_u14(data), direction, MioXLFilter::read(&data[3..])?);
+            },
+            RET_MIDI_PORT_REMAP => {
+                short(3 + 16)?;
+                let direction = MioXLDirection::read(data[2])?;
+                let mut remap = [0; 16];
+                remap.copy_from_slice(&data[3..3+16]);
+                self.remap(read_u14(data), direction, remap);
+            },
+            RET_PRESET_NAME => {
+                short(1)?;
+                self.presets.insert(data[0], read_text(&data[1..]));
+                self.changed(MioXLSetting::PresetName(data[0]));
+            },
+            ACK => if let Some((command, error)) = message.ack() {
+                if error != 0 {
+                    return Err(format!("command {command:#04x} failed with error {error}"))
+                }
+            },
+            _ => return Ok(false)
+        }
+        Ok(true)
+    }
+
+    /// Messages that write the device name, ports, routes, filters, remaps
+    /// and preset names of the model to the device. Only the settings that
+    /// were read from the device or changed since are written, so that a
+    /// partial read doesn't reset the rest of the device to defaults.
+    pub fn updates (&self) -> Vec<IConnectivitySysEx> {
+        let mut updates = vec![];
+        let count = self.ports.keys().max().copied().unwrap_or(0);
+        for setting in self.known.iter() {
+            match *setting {
+                MioXLSetting::DeviceName => if let Some(name) = self.info.get(&INFO_DEVICE_NAME) {
+                    updates.push(self.message(SET_INFO, [&[INFO_DEVICE_NAME], write_text(name).as_slice()].concat()));
+                },
+                MioXLSetting::Port(id) => if let Some(port) = self.ports.get(&id) {
+                    updates.push(self.message(SET_MIDI_PORT_INFO, port.write()));
+                },
+                MioXLSetting::Route(id) => {
+                    let targets = self.routes.get(&id).cloned().unwrap_or_default();
+                    updates.push(self.message(SET_MIDI_PORT_ROUTE, [
+                        write_u14(id).as_slice(), &write_bitmap(&targets, count)
+                    ].concat()));
+                },
+                MioXLSetting::Filter(id, direction) => {
+                    let filter = self.filters.get(&(id, direction)).cloned().unwrap_or_default();
+                    updates.push(self.message(SET_MIDI_PORT_FILTER, [
+                        write_u14(id).as_slice(), &[direction as u8], &filter.write()
+                    ].concat()));
+                },
+                MioXLSetting::Remap(id, direction) => {
+                    let remap = self.remaps.get(&(id, direction)).unwrap_or(&IDENTITY_REMAP);
+                    updates.push(self.message(SET_MIDI_PORT_REMAP, [
+                        write_u14(id).as_slice(), &[direction as u8], remap
+                    ].concat()));
+                },
+                MioXLSetting::PresetName(preset) => if let Some(name) = self.presets.get(&preset) {
+                    updates.push(self.message(SET_PRESET_NAME, [&[preset], write_text(name).as_slice()].concat()));
+                },
+            }
+        }
+        updates
+    }
+
+    /// Mark a setting as changed, after editing `info`, `ports` or `presets` directly,
+    /// so that `updates` writes it
+    pub fn changed (&mut self, setting: MioXLSetting) {
+        self.known.insert(setting);
+    }
+
+    /// Message that stores the current settings into a preset (1-8)
+    pub fn save_preset (&self, preset: u8) -> std::result::Result<IConnectivitySysEx, String> {
+        Self::check_preset(preset)?;
+        Ok(self.message(SAVE_RESTORE, vec![SAVE_PRESET, preset]))
+    }
+
+    /// Message that loads a preset (1-8) into the current settings
+    pub fn recall_preset (&self, preset: u8) -> std::result::Result<IConnectivitySysEx, String> {
+        Self::check_preset(preset)?;
+        Ok(self.message(SAVE_RESTORE, vec![RECALL_PRESET, preset]))
+    }
+
+    fn check_preset (preset: u8) -> std::result::Result<(), String> {
+        if (1..=MIOXL_PRESETS).contains(&preset) {
+            Ok(())
+        } else {
+            Err(format!("no preset {preset}, the mioXL has presets 1 to {MIOXL_PRESETS}"))
+        }
+    }
+
+    /// Name of an information field
+    pub fn info_name (id: u8) -> Option<&'static str> {
+        INFO_FIELDS.iter().find(|(field, _)|*field == id).map(|(_, name)|*name)
+    }
+
+    /// Whether the input of a port is routed to another port
+    pub fn routed (&self, from: u16, to: u16) -> bool {
+        self.routes.get(&from).map(|targets|targets.contains(&to)).unwrap_or(false)
+    }
+
+    /// Connect or disconnect the input of a port to another port
+    pub fn route (&mut self, from: u16, to: u16, connect: bool) {
+        let mut targets = self.routes.get(&from).cloned().unwrap_or_default();
+        if connect {
+            targets.insert(to);
+        } else {
+            targets.remove(&to);
+        }
+        self.route_all(from, targets);
+    }
+
+    /// Replace the ports that the input of a port is routed to
+    pub fn route_all (&mut self, from: u16, targets: BTreeSet<u16>) {
+        self.changed(MioXLSetting::Route(from));
+        if targets.is_empty() {
+            self.routes.remove(&from);
+        } else {
+            self.routes.insert(from, targets);
+        }
+    }
+
+    /// Replace the filter of the input or output of a port
+    pub fn filter (&mut self, port: u16, direction: MioXLDirection, filter: MioXLFilter) {
+        self.changed(MioXLSetting::Filter(port, direction));
+        if filter == MioXLFilter::default() {
+            self.filters.remove(&(port, direction));
+        } else {
+            self.filters.insert((port, direction), filter);
+        }
+    }
+
+    /// Replace the channel remap of the input or output of a port
+    pub fn remap (&mut self, port: u16, direction: MioXLDirection, remap: [u8; 16]) {
+        self.changed(MioXLSetting::Remap(port, direction));
+        if remap == IDENTITY_REMAP {
+            self.remaps.remove(&(port, direction));
+        } else {
+            self.remaps.insert((port, direction), remap);
+        }
+    }
+
+}
+
+/// Channel remap that leaves every channel in place
+pub const IDENTITY_REMAP: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+impl MioXLDirection {
+    pub fn read (byte: u8) -> std::result::Result<Self, String> {
+        match byte {
+            0 => Ok(Self::Input),
+            1 => Ok(Self::Output),
+            _ => Err(format!("unknown port direction {byte}"))
+        }
+    }
+}
+
+impl MioXLPortType {
+    pub fn read (byte: u8) -> std::result::Result<Self, String> {
+        match byte {
+            1 => Ok(Self::DIN),
+            2 => Ok(Self::USBDevice),
+            3 => Ok(Self::USBHost),
+            4 => Ok(Self::Ethernet),
+            _ => Err(format!("unknown port type {byte}"))
+        }
+    }
+}
+
+impl MioXLPort {
+
+    /// A port that is known only by its ID, until its info is read
+    pub fn new (id: u16) -> Self {
+        Self { id, kind: MioXLPortType::DIN, jack: 0, input: true, output: true, name: String::new() }
+    }
+
+    /// Read the payload of RET_MIDI_PORT_INFO
+    pub fn read (data: &[u8]) -> std::result::Result<Self, String> {
+        if data.len() < 5 {
+            return Err(format!("port info should have at least 5 bytes, found {}", data.len()))
+        }
+        Ok(Self {
+            id:     read_u14(data),
+            kind:   MioXLPortType::read(data[2])?,
+            jack:   data[3],
+            input:  data[4] & 0x01 != 0,
+            output: data[4] & 0x02 != 0,
+            name:   read_text(&data[5..]),
+        })
+    }
+
+    /// Write the payload of SET_MIDI_PORT_INFO
+    pub fn write (&self) -> Vec<u8> {
+        let mut data = write_u14(self.id).to_vec();
+        data.push(self.kind as u8);
+        data.push(self.jack);
+        data.push(self.input as u8 | (self.output as u8) << 1);
+        data.extend(write_text(&self.name));
+        data
+    }
+
+}
+
+impl MioXLFilter {
+
+    /// Read a filter from the payload of RET_MIDI_PORT_FILTER, after the port and direction:
+    /// system bits, channel bits of each channel, number of blocked controllers and their numbers
+    pub fn read (data: &[u8]) -> std::result::Result<Self, String> {
+        if data.len() < 18 || data.len() < 18 + data[17] as usize {
+            return Err(format!("filter is too short ({} bytes)", data.len()))
+        }
+        let mut channels = [0; 16];
+        channels.copy_from_slice(&data[1..17]);
+        Ok(Self {
+            system:      data[0],
+            channels,
+            controllers: data[18..18 + data[17] as usize].iter().copied().collect(),
+        })
+    }
+
+    /// Write a filter into the payload of SET_MIDI_PORT_FILTER
+    pub fn write (&self) -> Vec<u8> {
+        let mut data = vec![self.system];
+        data.extend(self.channels);
+        data.push(self.controllers.len() as u8);
+        data.extend(self.controllers.iter());
+        data
+    }
+
+    /// Block or pass messages of some kinds (FILTER_NOTES etc.) on a channel (0-15)
+    pub fn block (&mut self, channel: usize, kinds: u8, block: bool) {
+        if block {
+            self.channels[channel] |= kinds;
+        } else {
+            self.channels[channel] &= !kinds;
+        }
+    }
+
+    /// Whether messages of a kind (FILTER_NOTES etc.) are blocked on a channel (0-15)
+    pub fn blocks (&self, channel: usize, kind: u8) -> bool {
+        self.channels[channel] & kind != 0
+    }
+
+}
+
+/// Read a name: its length, then its characters
+pub fn read_text (data: &[u8]) -> String {
+    let length = data.first().copied().unwrap_or(0) as usize;
+    let text = data.get(1..(1 + length).min(data.len())).unwrap_or(&[]);
+    String::from_utf8_lossy(text).into()
+}
+
+/// Write a name as its length, then its characters, dropping non-ASCII characters
+/// and cutting it to MIOXL_NAME_MAX characters
+pub fn write_text (text: &str) -> Vec<u8> {
+    let text = text.bytes()
+        .filter(|byte|byte.is_ascii() && !byte.is_ascii_control())
+        .take(MIOXL_NAME_MAX)
+        .collect::<Vec<_>>();
+    [&[text.len() as u8], text.as_slice()].concat()
+}
+
+/// Read a set of port IDs from a bitmap of 7 ports per byte, starting from port 1
+pub fn read_bitmap (data: &[u8]) -> BTreeSet<u16> {
+    let mut ports = BTreeSet::new();
+    for (index, byte) in data.iter().enumerate() {
+        for bit in 0..7 {
+            if byte & (1 << bit) != 0 {
+                ports.insert((index * 7 + bit + 1) as u16);
+            }
+        }
+    }
+    ports
+}
+
+/// Write a set of port IDs as a bitmap of 7 ports per byte, long enough for `count` ports
+pub fn write_bitmap (ports: &BTreeSet<u16>, count: u16) -> Vec<u8> {
+    let mut data = vec![0; (count as usize).div_ceil(7)];
+    for port in ports.iter().filter(|port|**port >= 1 && **port <= count) {
+        let index = *port as usize - 1;
+        data[index / 7] |= 1 << (index % 7);
+    }
+    data
+}
+
+#[cfg(test)]
+mod test {
+    //! No captured mioXL traffic or published example messages are available
+    //! to these tests. Every message below is synthetic: assembled byte by byte
+    //! from docs/iconnectivity/sysex_format.md, with checksums computed by hand,
+    //! for a made-up device with serial number 0x1234. They check that reading
+    //! and writing agree with that page, not that the page agrees with a device.
+
+    use super::*;
+
+    /// Frame a payload from the made-up mioXL with serial number 0x1234,
+    /// to compare with messages assembled byte by byte below.
+    fn from_device (command: u16, data: &[u8]) -> IConnectivitySysEx {
+        IConnectivitySysEx::new((MIOXL_PRODUCT_ID, 0x1234), command, data.to_vec())
+    }
+
+    #[test]
+    fn port_info () {
+        // Port 3: DIN jack 3, input and output enabled, named "Synth"
+        let raw = [
+            0xF0, 0x00, 0x01, 0x73, 0x7E,
+            0x00, 0x13, 0x00, 0x00, 0x00, 0x24, 0x34, 0x00, 0x00, 0x00, 0x29, 0x00, 0x0B,
+            0x00, 0x03, 0x01, 0x03, 0x03, 0x05, b'S', b'y', b'n', b't', b'h',
+            0x3C, 0xF7
+        ];
+        let message = IConnectivitySysEx::read(&raw).unwrap();
+        assert_eq!(message, from_device(RET_MIDI_PORT_INFO, &raw[18..29]));
+        let mut mioxl = MioXL::new(0x1234);
+        assert_eq!(mioxl.apply(&message), Ok(true));
+        let port = &mioxl.ports[&3];
+        assert_eq!(port.kind, MioXLPortType::DIN);
+        assert_eq!(port.jack, 3);
+        assert!(port.input && port.output);
+        assert_eq!(port.name, "Synth");
+        assert_eq!(mioxl.message(SET_MIDI_PORT_INFO, port.write()).data, raw[18..29]);
+    }
+
+    #[test]
+    fn routing () {
+        // Port 1 routed to ports 2, 8 and 10, on a device with 10 ports
+        let data = [0x00, 0x01, 0b0000_0010, 0b0000_0101];
+        let mut mioxl = MioXL::new(0x1234);
+        mioxl.apply(&from_device(RET_MIDI_INFO, &[0x00, 0x0A, 0x01])).unwrap();
+        assert_eq!(mioxl.ports.len(), 10);
+        assert_eq!(mioxl.preset, 1);
+        // Ports that were only counted are not written back
+        assert!(mioxl.updates().is_empty());
+        mioxl.apply(&from_device(RET_MIDI_PORT_ROUTE, &data)).unwrap();
+        assert!(mioxl.routed(1, 2) && mioxl.routed(1, 8) && mioxl.routed(1, 10));
+        assert!(!mioxl.routed(1, 3) && !mioxl.routed(2, 1));
+        let updates = mioxl.updates();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].command, SET_MIDI_PORT_ROUTE);
+        assert_eq!(updates[0].data, data);
+        mioxl.route(1, 8, false);
+        mioxl.route(1, 3, true);
+        assert_eq!(write_bitmap(&mioxl.routes[&1], 10), vec![0b0000_0110, 0b0000_0100]);
+        // Changing a route that was not read writes only that route
+        mioxl.route(2, 1, true);
+        let updates = mioxl.updates();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[1].data, [0x00, 0x02, 0b0000_0001, 0b0000_0000]);
+    }
+
+    #[test]
+    fn filters_and_remaps () {
+        // Output of port 2 blocks clock, and notes and pitch bend on channel 10,
+        // and controllers 1 and 64
+        let mut data = vec![0x00, 0x02, 0x01, FILTER_CLOCK];
+        let mut channels = [0; 16];
+        channels[9] = FILTER_NOTES | FILTER_PITCH_BEND;
+        data.extend(channels);
+        data.extend([0x02, 0x01, 0x40]);
+        let mut mioxl = MioXL::new(0x1234);
+        mioxl.apply(&from_device(RET_MIDI_PORT_FILTER, &data)).unwrap();
+        let filter = &mioxl.filters[&(2, MioXLDirection::Output)];
+        assert!(filter.blocks(9, FILTER_NOTES) && !filter.blocks(9, FILTER_CONTROL) && !filter.blocks(0, FILTER_NOTES));
+        assert_eq!(filter.controllers, BTreeSet::from([1, 64]));
+        assert_eq!(filter.write(), data[3..]);
+        // Input of port 2 sends channel 1 to channel 3, and channel 3 to channel 1
+        let mut remap = IDENTITY_REMAP;
+        remap.swap(0, 2);
+        mioxl.apply(&from_device(RET_MIDI_PORT_REMAP, &[&[0x00, 0x02, 0x00], &remap[..]].concat())).unwrap();
+        assert_eq!(mioxl.remaps[&(2, MioXLDirection::Input)][0], 2);
+        // An answer with the defaults clears them
+        mioxl.apply(&from_device(RET_MIDI_PORT_REMAP, &[&[0x00, 0x02, 0x00], &IDENTITY_REMAP[..]].concat())).unwrap();
+        assert!(mioxl.remaps.is_empty());
+        // Both were read, so both are written back, the remap as the default
+        let updates = mioxl.updates();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].data, data);
+        assert_eq!(updates[1].data, [&[0x00, 0x02, 0x00], &IDENTITY_REMAP[..]].concat());
+        assert!(mioxl.apply(&from_device(RET_MIDI_PORT_FILTER, &data[..10])).is_err());
+    }
+
+    #[test]
+    fn device_and_presets () {
+        let mut mioxl = MioXL::new(0);
+        let queries = mioxl.queries();
+        assert_eq!(queries[0].write().unwrap(), [
+            0xF0, 0x00, 0x01, 0x73, 0x7E,
+            0x00, 0x13, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+            0x6C, 0xF7
+        ]);
+        assert_eq!(queries.len(), 1 + INFO_FIELDS.len() + 1 + MIOXL_PRESETS as usize);
+        // The first answer tells the serial number of the device
+        mioxl.apply(&from_device(RET_DEVICE, &[0x02, 0x01])).unwrap();
+        assert_eq!(mioxl.device, (MIOXL_PRODUCT_ID, 0x1234));
+        assert_eq!(mioxl.protocol, 2);
+        assert_eq!(mioxl.apply(&IConnectivitySysEx::new((0x0E, 0x1234), RET_DEVICE, vec![1])), Ok(false));
+        mioxl.apply(&from_device(RET_INFO, &[INFO_DEVICE_NAME, 0x04, b'S', b't', b'a', b'g'])).unwrap();
+        mioxl.apply(&from_device(RET_PRESET_NAME, &[0x03, 0x04, b'L', b'i', b'v', b'e'])).unwrap();
+        assert_eq!(mioxl.info[&INFO_DEVICE_NAME], "Stag");
+        assert_eq!(mioxl.presets[&3], "Live");
+        let updates = mioxl.updates();
+        assert_eq!(updates[0].data, [INFO_DEVICE_NAME, 0x04, b'S', b't', b'a', b'g']);
+        assert_eq!(updates[1].data, [0x03, 0x04, b'L', b'i', b'v', b'e']);
+        assert_eq!(mioxl.recall_preset(3).unwrap().write().unwrap(), [
+            0xF0, 0x00, 0x01, 0x73, 0x7E,
+            0x00, 0x13, 0x00, 0x00, 0x00, 0x24, 0x34, 0x00, 0x00, 0x00, 0x11, 0x00, 0x02,
+            0x02, 0x03,
+            0x7D, 0xF7
+        ]);
+        assert!(mioxl.recall_preset(0).is_err());
+        assert!(mioxl.save_preset(MIOXL_PRESETS + 1).is_err());
+        assert!(mioxl.apply(&from_device(ACK, &[0x00, 0x11, 0x02])).is_err());
+        assert_eq!(mioxl.apply(&from_device(ACK, &[0x00, 0x11, 0x00])), Ok(true));
+    }
+
+}
@@ -1,12 +1,18 @@
 use thatsit::*;
 
+opt_mod::module_flat!(sysex);
+opt_mod::module_flat!(mioxl);
+
+#[derive(Default)]
 pub struct MioXLUI {
+    /// Settings of the connected mioXL
+    pub mioxl: MioXL,
 }
 
 impl MioXLUI {
     pub fn new () -> Self {
         //menu.add("Edit preset".into(), ())
             //.add("Route MIDI".into(),  ());
-        Self {}
+        Self { mioxl: MioXL::default() }
     }
 }
@@ -0,0 +1,288 @@
+//! Framing of the system exclusive messages shared by iConnectivity devices,
+//! as documented in docs/iconnectivity/sysex_format.md.
+
+/// Start of every message: SysEx start, iConnectivity's manufacturer ID and message class
+pub const SYSEX_HEADER: [u8; 5] = [0xF0, 0x00, 0x01, 0x73, 0x7E];
+/// End of every message
+pub const SYSEX_END: u8 = 0xF7;
+/// Size of the fields between the header and the payload:
+/// product ID, serial number, transaction ID, command ID and payload length
+pub const SYSEX_FIELDS_SIZE: usize = 13;
+/// Most bytes in a payload, as the length is a 14-bit number
+pub const SYSEX_DATA_MAX: usize = 0x3FFF;
+/// Product ID and serial number that address any device
+pub const ANY_DEVICE: (u16, u64) = (0, 0);
+
+/// Ask for the protocol version of a device
+pub const GET_DEVICE: u16 = 0x01;
+/// Protocol version and mode of a device
+pub const RET_DEVICE: u16 = 0x02;
+/// Ask for the commands that a device supports
+pub const GET_COMMAND_LIST: u16 = 0x03;
+/// Commands that a device supports
+pub const RET_COMMAND_LIST: u16 = 0x04;
+/// Ask for the information fields that a device has
+pub const GET_INFO_LIST: u16 = 0x05;
+/// Information fields that a device has
+pub const RET_INFO_LIST: u16 = 0x06;
+/// Ask for an information field
+pub const GET_INFO: u16 = 0x07;
+/// An information field
+pub const RET_INFO: u16 = 0x08;
+/// Change an information field
+pub const SET_INFO: u16 = 0x09;
+/// Restart a device
+pub const RESET: u16 = 0x10;
+/// Save, recall or reset the settings of a device
+pub const SAVE_RESTORE: u16 = 0x11;
+/// Ask for the MIDI capabilities of a device
+pub const GET_MIDI_INFO: u16 = 0x20;
+/// MIDI capabilities of a device
+pub const RET_MIDI_INFO: u16 = 0x21;
+/// Ask for the type and name of a MIDI port
+pub const GET_MIDI_PORT_INFO: u16 = 0x28;
+/// Type and name of a MIDI port
+pub const RET_MIDI_PORT_INFO: u16 = 0x29;
+/// Change the name of a MIDI port or enable it
+pub const SET_MIDI_PORT_INFO: u16 = 0x2A;
+/// Ask for the input or output filter of a MIDI port
+pub const GET_MIDI_PORT_FILTER: u16 = 0x2D;
+/// Input or output filter of a MIDI port
+pub const RET_MIDI_PORT_FILTER: u16 = 0x2E;
+/// Change the input or output filter of a MIDI port
+pub const SET_MIDI_PORT_FILTER: u16 = 0x2F;
+/// Ask for the input or output channel remap of a MIDI port
+pub const GET_MIDI_PORT_REMAP: u16 = 0x30;
+/// Input or output channel remap of a MIDI port
+pub const RET_MIDI_PORT_REMAP: u16 = 0x31;
+/// Change the input or output channel remap of a MIDI port
+pub const SET_MIDI_PORT_REMAP: u16 = 0x32;
+/// Ask for the ports that a MIDI port is routed to
+pub const GET_MIDI_PORT_ROUTE: u16 = 0x34;
+/// Ports that a MIDI port is routed to
+pub const RET_MIDI_PORT_ROUTE: u16 = 0x35;
+/// Change the ports that a MIDI port is routed to
+pub const SET_MIDI_PORT_ROUTE: u16 = 0x36;
+/// Ask for the name of a preset
+pub const GET_PRESET_NAME: u16 = 0x38;
+/// Name of a preset
+pub const RET_PRESET_NAME: u16 = 0x39;
+/// Rename a preset
+pub const SET_PRESET_NAME: u16 = 0x3A;
+/// Answer to a command that has no other answer
+pub const ACK: u16 = 0x3F;
+
+/// A message of the iConnectivity protocol.
+///
+/// ```text
+/// F0 00 01 73 7E  header
+/// 2               product ID (7 bits per byte, MSB first)
+/// 5               serial number (7 bits per byte, MSB first)
+/// 2               transaction ID, returned as-is in the answer
+/// 2               command ID
+/// 2               payload length
+/// N               payload
+/// 1               checksum of everything from the product ID to the end of the payload
+/// F7
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct IConnectivitySysEx {
+    pub product:     u16,
+    pub serial:      u64,
+    pub transaction: u16,
+    pub command:     u16,
+    pub data:        Vec<u8>,
+}
+
+impl IConnectivitySysEx {
+
+    /// Create a message to a device
+    pub fn new ((product, serial): (u16, u64), command: u16, data: Vec<u8>) -> Self {
+        Self { product, serial, transaction: 0, command, data }
+    }
+
+    /// Parse a message, checking its framing and checksum
+    pub fn read (raw: &[u8]) -> std::result::Result<Self, String> {
+        if raw.len() < SYSEX_HEADER.len() + SYSEX_FIELDS_SIZE + 2 {
+            return Err(format!("message is too short ({} bytes)", raw.len()))
+        }
+        if raw[..SYSEX_HEADER.len()] != SYSEX_HEADER {
+            return Err("not an iConnectivity message".into())
+        }
+        if raw[raw.len() - 1] != SYSEX_END {
+            return Err("message is not terminated".into())
+        }
+        let body = &raw[SYSEX_HEADER.len()..raw.len() - 2];
+        if let Some(byte) = body.iter().find(|byte|**byte > 0x7F) {
+            return Err(format!("data byte {byte:#04x} is out of range"))
+        }
+        let length = read_u14(&body[11..13]) as usize;
+        if body.len() != SYSEX_FIELDS_SIZE + length {
+            return Err(format!("payload should be {length} bytes, found {}", body.len() - SYSEX_FIELDS_SIZE))
+        }
+        let expected = checksum(body);
+        let found = raw[raw.len() - 2];
+        if found != expected {
+            return Err(format!("checksum should be {expected:#04x}, found {found:#04x}"))
+        }
+        Ok(Self {
+            product:     read_u14(&body[0..2]),
+            serial:      read_u35(&body[2..7]),
+            transaction: read_u14(&body[7..9]),
+            command:     read_u14(&body[9..11]),
+            data:        body[SYSEX_FIELDS_SIZE..].to_vec(),
+        })
+    }
+
+    /// Serialize the message. Fails if a field does not fit in its 7-bit bytes.
+    pub fn write (&self) -> std::result::Result<Vec<u8>, String> {
+        if self.data.len() > SYSEX_DATA_MAX {
+            return Err(format!("payload is too long ({} bytes)", self.data.len()))
+        }
+        if let Some((index, byte)) = self.data.iter().enumerate().find(|(_, byte)|**byte > 0x7F) {
+            return Err(format!("payload byte {index} ({byte:#04x}) is out of range"))
+        }
+        for (name, value) in [("product ID", self.product), ("transaction ID", self.transaction), ("command", self.command)] {
+            if value > 0x3FFF {
+                return Err(format!("{name} {value:#x} is out of range"))
+            }
+        }
+        if self.serial > 0x7_FFFF_FFFF {
+            return Err(format!("serial number {:#x} is out of range", self.serial))
+        }
+        let mut body = vec![];
+        body.extend(write_u14(self.product));
+        body.extend(write_u35(self.serial));
+        body.extend(write_u14(self.transaction));
+        body.extend(write_u14(self.command));
+        body.extend(write_u14(self.data.len() as u16));
+        body.extend(self.data.iter());
+        let mut raw = SYSEX_HEADER.to_vec();
+        raw.push(checksum(&body));
+        raw.splice(SYSEX_HEADER.len()..SYSEX_HEADER.len(), body);
+        raw.push(SYSEX_END);
+        Ok(raw)
+    }
+
+    /// Product ID and serial number of the device that sent the message
+    pub fn device (&self) -> (u16, u64) {
+        (self.product, self.serial)
+    }
+
+    /// For an ACK message, the command that it answers and the error code (0 for success)
+    pub fn ack (&self) -> Option<(u16, u8)> {
+        (self.command == ACK && self.data.len() >= 3).then(||(read_u14(&self.data), self.data[2]))
+    }
+
+}
+
+/// The byte that makes the 7-bit sum of the message body zero
+pub fn checksum (body: &[u8]) -> u8 {
+    let sum = body.iter().fold(0u8, |sum, byte|sum.wrapping_add(*byte));
+    0u8.wrapping_sub(sum) & 0x7F
+}
+
+/// Read a 14-bit number from two bytes, MSB first
+pub fn read_u14 (raw: &[u8]) -> u16 {
+    (raw[0] as u16 & 0x7F) << 7 | raw[1] as u16 & 0x7F
+}
+
+/// Write a 14-bit number as two bytes, MSB first
+pub fn write_u14 (value: u16) -> [u8; 2] {
+    [(value >> 7) as u8 & 0x7F, value as u8 & 0x7F]
+}
+
+/// Read a 35-bit number from five bytes, MSB first
+pub fn read_u35 (raw: &[u8]) -> u64 {
+    raw[..5].iter().fold(0, |value, byte|value << 7 | *byte as u64 & 0x7F)
+}
+
+/// Write a 35-bit number as five bytes, MSB first
+pub fn write_u35 (value: u64) -> [u8; 5] {
+    let mut raw = [0; 5];
+    for (index, byte) in raw.iter_mut().enumerate() {
+        *byte = (value >> (7 * (4 - index))) as u8 & 0x7F;
+    }
+    raw
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Asking every connected device for its protocol version. The checksum of an
+    /// otherwise empty body with command 0x01 is 0x7F.
+    const GET_DEVICE_BROADCAST: [u8; 20] = [
+        0xF0, 0x00, 0x01, 0x73, 0x7E,
+        0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00,
+        0x00, 0x01,
+        0x00, 0x00,
+        0x7F,
+        0xF7
+    ];
+
+    /// A device answering with protocol version 1 in application mode
+    const RET_DEVICE_ANSWER: [u8; 22] = [
+        0xF0, 0x00, 0x01, 0x73, 0x7E,
+        0x00, 0x13,
+        0x00, 0x00, 0x01, 0x02, 0x03,
+        0x00, 0x05,
+        0x00, 0x02,
+        0x00, 0x02,
+        0x01, 0x01,
+        0x5C,
+        0xF7
+    ];
+
+    #[test]
+    fn write_broadcast () {
+        let message = IConnectivitySysEx::new(ANY_DEVICE, GET_DEVICE, vec![]);
+        assert_eq!(message.write().unwrap(), GET_DEVICE_BROADCAST);
+        assert_eq!(IConnectivitySysEx::read(&GET_DEVICE_BROADCAST).unwrap(), message);
+    }
+
+    #[test]
+    fn read_answer () {
+        let message = IConnectivitySysEx::read(&RET_DEVICE_ANSWER).unwrap();
+        assert_eq!(message.product, 0x13);
+        assert_eq!(message.serial, 1 << 14 | 2 << 7 | 3);
+        assert_eq!(message.transaction, 5);
+        assert_eq!(message.command, RET_DEVICE);
+        assert_eq!(message.data, vec![0x01, 0x01]);
+        assert_eq!(message.write().unwrap(), RET_DEVICE_ANSWER);
+    }
+
+    #[test]
+    fn reject_unwritable () {
+        let message = |command: u16, data: Vec<u8>|IConnectivitySysEx::new(ANY_DEVICE, command, data);
+        assert!(message(GET_DEVICE, vec![0x80]).write().unwrap_err().contains("payload byte 0"));
+        assert!(message(GET_DEVICE, vec![0; SYSEX_DATA_MAX + 1]).write().is_err());
+        assert!(message(0x4000, vec![]).write().is_err());
+        let mut wide = message(GET_DEVICE, vec![]);
+        wide.serial = 1 << 35;
+        assert!(wide.write().is_err());
+    }
+
+    #[test]
+    fn reject_broken () {
+        let mut raw = RET_DEVICE_ANSWER.to_vec();
+        raw[20] = 0x5D;
+        assert!(IConnectivitySysEx::read(&raw).unwrap_err().contains("checksum"));
+        let mut raw = RET_DEVICE_ANSWER.to_vec();
+        raw.remove(19);
+        assert!(IConnectivitySysEx::read(&raw).unwrap_err().contains("payload"));
+        assert!(IConnectivitySysEx::read(&GET_DEVICE_BROADCAST[..19]).is_err());
+        assert!(IConnectivitySysEx::read(&[0xF0, 0x41, 0x10, 0x42, 0x12, 0xF7]).is_err());
+    }
+
+    #[test]
+    fn numbers () {
+        assert_eq!(write_u14(0x3FFF), [0x7F, 0x7F]);
+        assert_eq!(read_u14(&write_u14(1234)), 1234);
+        assert_eq!(write_u35(0x7_FFFF_FFFF), [0x7F; 5]);
+        assert_eq!(read_u35(&write_u35(0x1_2345_6789)), 0x1_2345_6789);
+    }
+
+}
@@ -7,18 +7,17 @@ pub enum Korg {
         command: crate::electribe2::Electribe2CLI,
     },
 
-    ///// Tools for the Korg Triton
-    //Triton {
-        ///// Import an existing disk image
-        //#[clap(long)]
-        //command: Triton,
-    //},
+    /// Tools for the Korg Triton
+    Triton {
+        #[command(subcommand)]
+        command: crate::triton::Triton,
+    },
 
 }
 
 pub fn run_cli (device: &Korg) {
     match device {
         Korg::Electribe2 { command } => crate::electribe2::cli(command),
-        //Korg::Triton{ command } => triton::cli(command),
+        Korg::Triton { command } => crate::triton::cli(command),
     }
 }
@@ -1,4 +1,5 @@
 use crate::electribe2::*;
+use std::io::Write;
 
 #[derive(clap::Subcommand)]
 pub enum Electribe2CLI {
@@ -8,22 +9,180 @@ pub enum Electribe2CLI {
         /// Import an existing e2sSample.all pattern bundle.
         #[clap(long)]
         import: Option<std::path::PathBuf>,
-        /// Add a pattern
+        /// Add a pattern from a .e2spat file
         #[clap(long)]
-        add:    Vec<std::path::PathBuf>,
+        add:     Vec<std::path::PathBuf>,
+        /// Put the added patterns into the bundle starting from this slot, instead of into
+        /// the first init patterns
+        #[clap(long)]
+        slot:    Option<usize>,
         /// Pick a pattern by number and append it to a new pattern bundle.
         #[clap(long)]
-        pick:   Option<Vec<usize>>,
+        pick:    Option<Vec<usize>>,
+        /// Extract a pattern by number into a .e2spat file (see --to)
+        #[clap(long)]
+        extract: Option<usize>,
+        /// Where to write the extracted pattern
+        #[clap(long)]
+        to:      Option<std::path::PathBuf>,
+        /// Show the motion sequences of a pattern by number
+        #[clap(long)]
+        motion:  Option<usize>,
+        /// Write the motion sequences shown with --motion as MIDI CCs to this file
+        #[clap(long)]
+        motion_midi: Option<std::path::PathBuf>,
+        /// Copy the motion sequences from one pattern to another: --copy-motion FROM TO
+        #[clap(long, num_args = 2)]
+        copy_motion: Option<Vec<usize>>,
+        /// Import the notes of a Standard MIDI File into a pattern (see --into and --map)
+        #[clap(long)]
+        midi_in: Option<std::path::PathBuf>,
+        /// Pattern number to import the MIDI file into; the first init pattern if omitted
+        #[clap(long)]
+        into:    Option<usize>,
+        /// Map MIDI notes to a part, by channel or track name: --map 1=ch10 --map 2=Bass.
+        /// Without mappings, channels 1-16 go into parts 1-16.
+        #[clap(long)]
+        map:     Vec<String>,
+        /// Replace the patterns of the imported bundle with ones from a JSON file
+        /// (or YAML, by extension)
+        #[clap(long)]
+        load_json: Option<std::path::PathBuf>,
+        /// Write the patterns to a JSON file (or YAML, by extension)
+        #[clap(long)]
+        dump_json: Option<std::path::PathBuf>,
+        /// Write each pattern that has notes as a Standard MIDI File into this directory
+        #[clap(long)]
+        midi_out: Option<std::path::PathBuf>,
+        /// Write the pattern bundle to a file
+        #[clap(long)]
+        export:  Option<std::path::PathBuf>,
     },
 
     /// Manage sample files
     Samples {
         /// Import an existing e2sSample.all sample bundle.
         #[clap(long)]
-        import: Option<String>,
-        /// Add a sample
+        import: Option<std::path::PathBuf>,
+        /// Add a sample from a 16-bit WAV file, numbered from 501 upwards
+        #[clap(long)]
+        add:    Vec<std::path::PathBuf>,
+        /// Slice the added samples into this many equal parts, or at transients with "auto"
+        /// or "auto:N" (at most N slices)
+        #[clap(long)]
+        slice:  Option<String>,
+        /// Slices played by the steps in slice mode, numbered from 1, e.g. 1,3,2,4
+        #[clap(long, value_delimiter = ',', requires = "slice")]
+        slice_steps: Vec<u8>,
+        /// Extract the user samples as WAV files into this directory
+        #[clap(long)]
+        extract: Option<std::path::PathBuf>,
+        /// Extract factory samples too
+        #[clap(long)]
+        all:    bool,
+        /// Keep the korg chunk in the extracted WAV files
+        #[clap(long)]
+        keep_korg: bool,
+        /// Write the sample bundle to a file
+        #[clap(long)]
+        export: Option<std::path::PathBuf>,
+    },
+
+    /// Change many patterns of a bundle at once
+    Edit {
+        /// The e2sSample.all pattern bundle
+        #[clap(long)]
+        import:    std::path::PathBuf,
+        /// Patterns to change, e.g. 1-10,15 or all
+        #[clap(long, default_value = "all")]
+        select:    String,
+        /// Set the tempo of the selected patterns, from 20 to 300 BPM
+        #[clap(long)]
+        bpm:       Option<f32>,
+        /// Set the key of the selected patterns, e.g. D#
+        #[clap(long)]
+        key:       Option<Key>,
+        /// Set the scale of the selected patterns, e.g. Dorian
         #[clap(long)]
-        add: Vec<std::path::PathBuf>,
+        scale:     Option<Scale>,
+        /// Set the level of the selected patterns, from 0 to 127
+        #[clap(long)]
+        level:     Option<u8>,
+        /// Rename the selected patterns, e.g. "Set A {n}" or "{name} v2"
+        #[clap(long)]
+        rename:    Option<String>,
+        /// Copy a part into the selected patterns: --copy-part PATTERN:PART
+        #[clap(long)]
+        copy_part: Option<String>,
+        /// Move a pattern to another position: --move FROM TO
+        #[clap(long = "move", num_args = 2)]
+        move_to:   Option<Vec<usize>>,
+        /// Switch the places of two patterns: --swap A B
+        #[clap(long, num_args = 2)]
+        swap:      Option<Vec<usize>>,
+        /// Write the changed pattern bundle to a file
+        #[clap(long)]
+        export:    Option<std::path::PathBuf>,
+    },
+
+    /// Show the differences between two pattern bundles, or two patterns of one bundle
+    Diff {
+        /// The first e2sSample.all pattern bundle
+        a:        std::path::PathBuf,
+        /// The second e2sSample.all pattern bundle; if omitted, compare patterns within the first
+        b:        Option<std::path::PathBuf>,
+        /// Compare two patterns by number: --patterns A B
+        #[clap(long, num_args = 2)]
+        patterns: Option<Vec<usize>>,
+    },
+
+    /// Cross-reference a pattern bundle with a sample bundle
+    Library {
+        /// The e2sSample.all pattern bundle
+        #[clap(long)]
+        patterns: std::path::PathBuf,
+        /// The e2sSample.all sample bundle
+        #[clap(long)]
+        samples:  std::path::PathBuf,
+        /// Remove a user sample by number
+        #[clap(long)]
+        remove:   Vec<u16>,
+        /// Remove all user samples that no pattern plays
+        #[clap(long)]
+        remove_unused: bool,
+        /// Remove samples even if patterns play them, resetting those parts to oscillator 1
+        #[clap(long)]
+        force:    bool,
+        /// Swap the numbers of two samples: --swap A B
+        #[clap(long, num_args = 2)]
+        swap:     Option<Vec<u16>>,
+        /// Number the user samples consecutively from 501
+        #[clap(long)]
+        compact:  bool,
+        /// Write the renumbered pattern bundle to a file
+        #[clap(long)]
+        export_patterns: Option<std::path::PathBuf>,
+        /// Write the changed sample bundle to a file
+        #[clap(long)]
+        export_samples:  Option<std::path::PathBuf>,
+    },
+
+    /// Convert a pattern bundle between the electribe and the electribe sampler
+    Convert {
+        /// The pattern bundle to convert
+        import:  std::path::PathBuf,
+        /// Device to convert for: synth (e2) or sampler (e2s)
+        #[clap(long)]
+        to:      Electribe2Device,
+        /// Table of oscillator numbers, one FROM=TO pair per line
+        #[clap(long)]
+        table:   Option<std::path::PathBuf>,
+        /// Use the table from right to left
+        #[clap(long)]
+        reverse: bool,
+        /// Write the converted pattern bundle to a file
+        #[clap(long)]
+        export:  Option<std::path::PathBuf>,
     }
 
 }
@@ -32,41 +191,608 @@ pub(crate) fn cli (command: &Electribe2CLI) {
 
     match command {
 
-        Electribe2CLI::Patterns { import, add, pick } => {
+        Electribe2CLI::Patterns {
+            import, add, slot, pick, extract, to, motion, motion_midi, copy_motion,
+            midi_in, into, map, load_json, dump_json, midi_out, export
+        } => {
+
+            let mut bundle = Electribe2PatternBank::empty();
 
             if let Some(import) = import {
                 let data = crate::read(import);
-                let mut bundle = Electribe2PatternBank::read(&data);
+                bundle = Electribe2PatternBank::read(&data);
+                if bundle.device.is_none() {
+                    bundle.device = Electribe2Device::from_path(import);
+                }
+                match bundle.device {
+                    Some(device) => println!("Patterns for the {device}"),
+                    None => println!("Patterns for the electribe or electribe sampler")
+                }
                 for (index, pattern) in bundle.patterns.iter().enumerate() {
                     println!("{:>3} {}", index+1, pattern.name);
                 }
+            }
+
+            if let Some(path) = load_json {
+                if import.is_none() {
+                    println!("--load-json needs a bundle from the device to load into, see --import.");
+                    return
+                }
+                let Ok(text) = String::from_utf8(crate::read(path)) else {
+                    println!("{path:?} is not valid UTF-8.");
+                    return
+                };
+                if let Err(error) = bundle.load(&text, is_yaml(path)) {
+                    println!("Could not load {path:?}: {error}");
+                    return
+                }
+                println!("Loaded {} patterns from {path:?}", bundle.patterns.len());
+            }
+
+            if let Some(pick) = pick {
+                let mut new_bundle = Electribe2PatternBank::empty_like(&bundle);
+                for index in pick {
+                    let Some(pattern) = index.checked_sub(1).and_then(|index|bundle.patterns.get(index)) else {
+                        println!("No pattern {index} in this bundle, pick from 1 to {}.", bundle.patterns.len());
+                        return
+                    };
+                    new_bundle.patterns.push(pattern.clone());
+                }
+                bundle = new_bundle;
+            }
+
+            if let Some(copy) = copy_motion {
+                let (from, to) = (copy[0].saturating_sub(1), copy[1].saturating_sub(1));
+                if from >= bundle.patterns.len() || to >= bundle.patterns.len() {
+                    println!("No such pattern.");
+                    return
+                }
+                let source = bundle.patterns[from].clone();
+                let mut copied = 0;
+                for part in 0..16 {
+                    copied += bundle.patterns[to].copy_motion(&source, part);
+                }
+                println!("Copied {copied} motion sequences from pattern {} to {}", from + 1, to + 1);
+            }
+
+            if let Some(index) = motion {
+                let Some(pattern) = bundle.patterns.get(index.saturating_sub(1)) else {
+                    println!("No pattern {index} in this bundle.");
+                    return
+                };
+                println!("Motion sequences of {}:", pattern.name);
+                for (slot, motion) in pattern.motion_slots() {
+                    println!("{:>3} {motion}", slot + 1);
+                }
+                if let Some(path) = motion_midi {
+                    std::fs::File::create(path)
+                        .unwrap()
+                        .write_all(pattern.motion_to_midi().write().as_slice())
+                        .unwrap();
+                    println!("Wrote {path:?}");
+                }
+            }
+
+            if let Some(index) = extract {
+                let Some(pattern) = bundle.patterns.get(index.saturating_sub(1)) else {
+                    println!("No pattern {index} in this bundle.");
+                    return
+                };
+                let Some(to) = to else {
+                    println!("No --to <PATH> specified, not extracting.");
+                    return
+                };
+                std::fs::File::create(to)
+                    .unwrap()
+                    .write_all(pattern.write_file(&bundle.header).as_slice())
+                    .unwrap();
+                println!("Wrote {} to {to:?}", pattern.name);
+            }
+
+            for (offset, path) in add.iter().enumerate() {
+                let pattern = match Electribe2Pattern::read_file(&crate::read(path)) {
+                    Ok(pattern) => pattern,
+                    Err(error) => {
+                        println!("Could not read {path:?}: {error}");
+                        continue
+                    }
+                };
+                if let Some(slot) = slot {
+                    let index = slot.saturating_sub(1) + offset;
+                    if index >= PATTERN_COUNT {
+                        println!("Slot {} is out of range, ignoring {path:?}", index + 1);
+                        continue
+                    }
+                    while bundle.patterns.len() <= index {
+                        bundle.patterns.push(Electribe2Pattern::init());
+                    }
+                    println!("Putting {path:?} ({}) in slot {}", pattern.name, index + 1);
+                    bundle.patterns[index] = pattern;
+                } else if let Some(index) = bundle.free_slot() {
+                    println!("Putting {path:?} ({}) in slot {}", pattern.name, index + 1);
+                    if index == bundle.patterns.len() {
+                        bundle.patterns.push(pattern);
+                    } else {
+                        bundle.patterns[index] = pattern;
+                    }
+                } else {
+                    println!("No free slot for {path:?}, use --slot to replace a pattern");
+                }
+            }
+
+            if let Some(path) = midi_in {
+                let smf = match crate::midi::SMF::read(&crate::read(path)) {
+                    Ok(smf) => smf,
+                    Err(error) => {
+                        println!("Could not read {path:?}: {error}");
+                        return
+                    }
+                };
+                let mut mapping = vec![];
+                for entry in map {
+                    let parsed = entry.split_once('=')
+                        .and_then(|(part, source)|part.parse::<usize>().ok().map(|part|(part, source)));
+                    match parsed {
+                        Some((part, source)) if (1..=16).contains(&part) =>
+                            mapping.push((part - 1, Electribe2MidiSource::parse(source))),
+                        _ => println!("Ignoring invalid mapping {entry:?}, expected PART=chN or PART=TRACK")
+                    }
+                }
+                if map.is_empty() {
+                    for channel in 0..16 {
+                        mapping.push((channel as usize, Electribe2MidiSource::Channel(channel)));
+                    }
+                }
+                let index = match into.map(|into|into.saturating_sub(1)).or_else(||bundle.free_slot()) {
+                    Some(index) => index,
+                    None => {
+                        println!("No free slot to import into, use --into to replace a pattern.");
+                        return
+                    }
+                };
+                if index >= PATTERN_COUNT {
+                    println!("Pattern {} is out of range.", index + 1);
+                    return
+                }
+                while bundle.patterns.len() <= index {
+                    bundle.patterns.push(Electribe2Pattern::init());
+                }
+                let pattern = &mut bundle.patterns[index];
+                if into.is_none() {
+                    if let Some(stem) = path.file_stem() {
+                        pattern.name = stem.to_string_lossy().chars().take(16).collect();
+                    }
+                }
+                let tempo = smf.tracks.iter().flat_map(|track|track.events.iter()).find_map(|(_, event)|
+                    if let crate::midi::Event::Tempo(tempo) = event { Some(*tempo) } else { None }
+                );
+                if let Some(tempo) = tempo {
+                    pattern.bpm = (60_000_000.0 / tempo as f32 * 10.0).round() / 10.0;
+                }
+                for (part, source) in mapping.iter() {
+                    let notes = source.notes(&smf);
+                    if notes.is_empty() {
+                        continue
+                    }
+                    let placed = pattern.import_midi(*part, &notes, smf.ppqn);
+                    println!("Part {:>2}: placed {placed} of {} notes from {source:?}", part + 1, notes.len());
+                }
+                println!("Imported {path:?} into pattern {} ({})", index + 1, pattern.name);
+            }
+
+            if pick.is_some() || !add.is_empty() || midi_in.is_some() || load_json.is_some() {
+                println!();
+                for (index, pattern) in bundle.patterns.iter().enumerate() {
+                    println!("{:>3} {}", index+1, pattern.name);
+                }
+            }
+
+            if let Some(path) = dump_json {
+                std::fs::File::create(path)
+                    .unwrap()
+                    .write_all(bundle.dump(is_yaml(path)).as_bytes())
+                    .unwrap();
+                println!("Wrote {path:?}");
+            }
+
+            if let Some(dir) = midi_out {
+                std::fs::create_dir_all(dir).unwrap();
+                for (index, pattern) in bundle.patterns.iter().enumerate() {
+                    let has_notes = pattern.parts.iter()
+                        .any(|part|part.steps.iter().any(|step|step.notes().count() > 0));
+                    if !has_notes {
+                        continue
+                    }
+                    let path = dir.join(format!("{:03}_{}.mid", index + 1, crate::filename::sanitize(&pattern.name)));
+                    std::fs::File::create(&path)
+                        .unwrap()
+                        .write_all(pattern.to_midi().write().as_slice())
+                        .unwrap();
+                    println!("Wrote {path:?}");
+                }
+            }
+
+            if let Some(path) = export {
+                if bundle.patterns.len() > PATTERN_COUNT {
+                    println!("Too many patterns ({}), the maximum is {PATTERN_COUNT}.", bundle.patterns.len());
+                    return
+                }
+                std::fs::File::create(path)
+                    .unwrap()
+                    .write_all(bundle.write().as_slice())
+                    .unwrap();
+                println!("Wrote {path:?}");
+            }
+
+        },
+
+        Electribe2CLI::Samples { import, add, slice, slice_steps, extract, all, keep_korg, export } => {
+
+            let mut bank = Electribe2SampleBank::default();
+
+            if let Some(import) = import {
+                bank = match Electribe2SampleBank::read(&crate::read(import)) {
+                    Ok(bank) => bank,
+                    Err(error) => {
+                        println!("Could not read {import:?}: {error}");
+                        return
+                    }
+                };
+                for sample in bank.samples.iter() {
+                    println!("{sample}");
+                }
+                println!("{} samples", bank.samples.len());
+            }
 
-                if let Some(pick) = pick {
-                    let mut new_bundle = Electribe2PatternBank::empty();
-                    for index in pick {
-                        new_bundle.patterns.push(
-                            bundle.patterns.get(*index-1).unwrap().clone()
-                        );
+            for path in add.iter() {
+                let name = path.file_stem()
+                    .map(|stem|stem.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let mut sample = match Electribe2Sample::from_wav(&name, &crate::read(path)) {
+                    Ok(sample) => sample,
+                    Err(error) => {
+                        println!("Could not add {path:?}: {error}");
+                        continue
+                    }
+                };
+                if let Some(slice) = slice {
+                    let count = if let Some(max) = slice.strip_prefix("auto") {
+                        let max = max.strip_prefix(':').and_then(|max|max.parse().ok()).unwrap_or(SLICES);
+                        sample.slice_transients(max)
+                    } else if let Ok(count) = slice.parse::<usize>() {
+                        sample.slice_equal(count)
+                    } else {
+                        println!("Invalid --slice {slice:?}, expected a number, \"auto\" or \"auto:N\"");
+                        return
+                    };
+                    println!("Cut {path:?} into {count} slices");
+                    if !slice_steps.is_empty() {
+                        let steps = slice_steps.iter()
+                            .map(|slice|slice.checked_sub(1).ok_or_else(||"slices are numbered from 1".to_string()))
+                            .collect::<std::result::Result<Vec<_>, _>>();
+                        if let Err(error) = steps.and_then(|steps|sample.set_slice_steps(&steps)) {
+                            println!("Invalid --slice-steps for {path:?}: {error}");
+                            return
+                        }
+                    }
+                }
+                match bank.add(sample) {
+                    Some(_) => println!("Adding {path:?} as {}", bank.samples.last().unwrap()),
+                    None => {
+                        println!("No free sample numbers left, not adding {path:?}");
+                        break
                     }
-                    println!("");
-                    for (index, pattern) in new_bundle.patterns.iter().enumerate() {
-                        println!("{:>3} {}", index+1, pattern.name);
+                }
+            }
+
+            if let Some(dir) = extract {
+                std::fs::create_dir_all(dir).unwrap();
+                for sample in bank.samples.iter() {
+                    if sample.number < USER_SAMPLES_START && !all {
+                        continue
                     }
+                    let path = dir.join(sample.file_name());
+                    std::fs::File::create(&path)
+                        .unwrap()
+                        .write_all(sample.to_wav(*keep_korg).as_slice())
+                        .unwrap();
+                    println!("Wrote {path:?}");
                 }
             }
 
+            let used = bank.user_memory();
+            println!("User sample memory: {:.2} of {:.2} MB",
+                used as f32 / 1048576.0, USER_SAMPLES_MEMORY as f32 / 1048576.0);
+
+            if let Some(path) = export {
+                if used > USER_SAMPLES_MEMORY {
+                    println!("User samples take {used} bytes, the maximum is {USER_SAMPLES_MEMORY}. Not writing.");
+                    return
+                }
+                std::fs::File::create(path)
+                    .unwrap()
+                    .write_all(bank.write().as_slice())
+                    .unwrap();
+                println!("Wrote {path:?}");
+            }
+
+        },
+
+        Electribe2CLI::Edit {
+            import, select, bpm, key, scale, level, rename, copy_part, move_to, swap, export
+        } => {
+
+            if let Some(bpm) = bpm.filter(|bpm|!(BPM_MIN..=BPM_MAX).contains(bpm)) {
+                println!("Invalid --bpm {bpm}, expected {BPM_MIN} to {BPM_MAX}");
+                return
+            }
+            if let Some(level) = level.filter(|level|*level > 127) {
+                println!("Invalid --level {level}, expected 0 to 127");
+                return
+            }
+
+            let mut bundle = Electribe2PatternBank::read(&crate::read(import));
+            let selection = match parse_selection(select, bundle.patterns.len()) {
+                Ok(selection) => selection,
+                Err(error) => {
+                    println!("Invalid --select {select:?}: {error}");
+                    return
+                }
+            };
+
+            let source = match copy_part {
+                Some(copy_part) => {
+                    let parsed = copy_part.split_once(':')
+                        .and_then(|(pattern, part)|Some((pattern.parse::<usize>().ok()?, part.parse::<usize>().ok()?)));
+                    match parsed {
+                        Some((pattern, part)) if (1..=bundle.patterns.len()).contains(&pattern) && (1..=16).contains(&part) =>
+                            Some((bundle.patterns[pattern - 1].clone(), part - 1)),
+                        _ => {
+                            println!("Invalid --copy-part {copy_part:?}, expected PATTERN:PART");
+                            return
+                        }
+                    }
+                },
+                None => None
+            };
+
+            for index in selection.iter() {
+                let pattern = &mut bundle.patterns[*index];
+                if let Some(bpm) = bpm {
+                    pattern.bpm = *bpm;
+                }
+                if let Some(key) = key {
+                    pattern.key = *key;
+                }
+                if let Some(scale) = scale {
+                    pattern.scale = *scale;
+                }
+                if let Some(level) = level {
+                    pattern.level = *level;
+                }
+                if let Some(template) = rename {
+                    pattern.rename(template, index + 1);
+                }
+                if let Some((source, part)) = &source {
+                    pattern.copy_part(source, *part);
+                }
+            }
+            println!("Changed {} patterns", selection.len());
+
+            if let Some(move_to) = move_to {
+                if !bundle.move_pattern(move_to[0].saturating_sub(1), move_to[1].saturating_sub(1)) {
+                    println!("No such pattern.");
+                    return
+                }
+            }
+            if let Some(swap) = swap {
+                if !bundle.swap(swap[0].saturating_sub(1), swap[1].saturating_sub(1)) {
+                    println!("No such pattern.");
+                    return
+                }
+            }
+
+            for (index, pattern) in bundle.patterns.iter().enumerate() {
+                println!("{:>3} {:<16} {:>5.1} {:<3} {}", index+1, pattern.name, pattern.bpm, pattern.key, pattern.scale);
+            }
+
+            if let Some(path) = export {
+                std::fs::File::create(path)
+                    .unwrap()
+                    .write_all(bundle.write().as_slice())
+                    .unwrap();
+                println!("Wrote {path:?}");
+            } else {
+                println!("No --export <PATH> specified, not writing.");
+            }
+
         },
 
-        Electribe2CLI::Samples { import, add } => {
+        Electribe2CLI::Diff { a, b, patterns } => {
+
+            let first = Electribe2PatternBank::read(&crate::read(a));
+            let lines = match (b, patterns) {
+                (_, Some(patterns)) => {
+                    let other;
+                    let second = match b {
+                        Some(b) => { other = Electribe2PatternBank::read(&crate::read(b)); &other },
+                        None => &first
+                    };
+                    let pattern_a = first.patterns.get(patterns[0].saturating_sub(1));
+                    let pattern_b = second.patterns.get(patterns[1].saturating_sub(1));
+                    let (Some(pattern_a), Some(pattern_b)) = (pattern_a, pattern_b) else {
+                        println!("No such pattern.");
+                        return
+                    };
+                    pattern_a.diff(pattern_b)
+                },
+                (Some(b), None) => first.diff(&Electribe2PatternBank::read(&crate::read(b))),
+                (None, None) => {
+                    println!("Specify a second bundle, or --patterns A B to compare within one.");
+                    return
+                }
+            };
+            if lines.is_empty() {
+                println!("No differences.");
+            }
+            for line in lines {
+                println!("{line}");
+            }
+
+        },
+
+        Electribe2CLI::Library {
+            patterns, samples, remove, remove_unused, force, swap, compact, export_patterns, export_samples
+        } => {
+
+            let mut patterns = Electribe2PatternBank::read(&crate::read(patterns));
+            let mut samples  = match Electribe2SampleBank::read(&crate::read(samples)) {
+                Ok(bank) => bank,
+                Err(error) => {
+                    println!("Could not read {samples:?}: {error}");
+                    return
+                }
+            };
+
+            let mut removed = remove.clone();
+            if *remove_unused {
+                removed.extend(samples.unused(&patterns).iter().map(|sample|sample.number));
+            }
+            for number in removed {
+                let users = patterns.sample_usage().remove(&number).unwrap_or_default();
+                if !users.is_empty() && !*force {
+                    println!("Not removing sample {number}, it is played by {}. Use --force to remove it anyway.",
+                        format_usage(&users));
+                    continue
+                }
+                match samples.remove(number) {
+                    Some(sample) => println!("Removed {sample}"),
+                    None => println!("No sample {number} in this bundle.")
+                }
+                // Reset the parts before compacting, or they would play whichever sample takes the number
+                if !users.is_empty() {
+                    patterns.reset_sample(number);
+                    println!("Reset {} to oscillator 1", format_usage(&users));
+                }
+            }
+
+            let swapped = match swap {
+                Some(swap) => samples.swap(swap[0], swap[1]),
+                None => Default::default()
+            };
+            let compacted = if *compact { samples.compact() } else { Default::default() };
+            let renumbered = chain_renumbering(&swapped, &compacted);
+            if !renumbered.is_empty() {
+                let changed = patterns.renumber_samples(&renumbered);
+                println!("Renumbered {} samples, updated {changed} parts", renumbered.len());
+            }
+
+            println!("User samples:");
+            let usage = patterns.sample_usage();
+            for sample in samples.samples.iter().filter(|sample|sample.number >= USER_SAMPLES_START) {
+                let users = usage.get(&sample.number)
+                    .map(|users|format_usage(users))
+                    .unwrap_or_else(||"unused".into());
+                println!("{:>3} {:<16} {:<8} {users}", sample.number, sample.name, sample.category_name());
+            }
+            for number in samples.missing(&patterns) {
+                let users = format_usage(&usage[&number]);
+                println!("{number:>3} {:<16} {:<8} {users}", "(missing)", "");
+            }
+
+            if let Some(path) = export_patterns {
+                std::fs::File::create(path)
+                    .unwrap()
+                    .write_all(patterns.write().as_slice())
+                    .unwrap();
+                println!("Wrote {path:?}");
+            }
+            if let Some(path) = export_samples {
+                std::fs::File::create(path)
+                    .unwrap()
+                    .write_all(samples.write().as_slice())
+                    .unwrap();
+                println!("Wrote {path:?}");
+            }
+
+        },
+
+        Electribe2CLI::Convert { import, to, table, reverse, export } => {
+
+            let mut bundle = Electribe2PatternBank::read(&crate::read(import));
+            if bundle.device.is_none() {
+                bundle.device = Electribe2Device::from_path(import);
+            }
+            if bundle.device == Some(*to) {
+                println!("{import:?} is already for the {to}.");
+            }
+
+            let mut oscillators = match table {
+                Some(path) => {
+                    let Ok(text) = String::from_utf8(crate::read(path)) else {
+                        println!("{path:?} is not valid UTF-8.");
+                        return
+                    };
+                    match parse_oscillator_table(&text) {
+                        Ok(table) => table,
+                        Err(error) => {
+                            println!("Could not read {path:?}: {error}");
+                            return
+                        }
+                    }
+                },
+                None => Default::default()
+            };
+            if *reverse {
+                oscillators = reverse_oscillator_table(&oscillators);
+            }
+
+            let unmapped = bundle.convert(*to, &oscillators);
+            println!("Converted {} patterns for the {to}", bundle.patterns.len());
+            if !unmapped.is_empty() {
+                println!("Parts with no oscillator in the table:");
+                for (pattern, part, number) in unmapped {
+                    println!("{:>3}:{:<2} {:<16} {number:>3}",
+                        pattern + 1, part + 1, bundle.patterns[pattern].name);
+                }
+            }
+
+            if let Some(path) = export {
+                std::fs::File::create(path)
+                    .unwrap()
+                    .write_all(bundle.write().as_slice())
+                    .unwrap();
+                println!("Wrote {path:?}");
+            }
+
         }
 
     }
 
 }
 
+impl std::fmt::Display for Electribe2Sample {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:>3} {:<16} {:<8} {:>5}Hz {} {:>6.2}s {}{}",
+            self.number,
+            self.name,
+            self.category_name(),
+            self.sample_rate,
+            if self.stereo { "stereo" } else { "mono  " },
+            self.duration(),
+            if self.one_shot { "one-shot" } else { "loop" },
+            if self.boost { " +12dB" } else { "" },
+        )?;
+        if self.slice_count() > 0 {
+            write!(f, " {} slices", self.slice_count())?;
+        }
+        Ok(())
+    }
+}
+
 impl std::fmt::Display for Electribe2Pattern {
     fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{:<30}", self.name)?;
+        write!(f, "{:<16} {:>5.1} BPM {} {} {}", self.name, self.bpm, self.key, self.scale, self.beats)?;
         for part in self.parts.iter() {
             write!(f, "\n  {}", part)?;
         }
@@ -74,9 +800,19 @@ impl std::fmt::Display for Electribe2Pattern {
     }
 }
 
+impl std::fmt::Display for Electribe2Motion {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Part {:>2} {:<12}", self.part, self.parameter_name())?;
+        for value in self.steps.iter() {
+            write!(f, " {value:>3}")?;
+        }
+        Ok(())
+    }
+}
+
 impl std::fmt::Display for Electribe2Part {
     fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.sample)?;
+        write!(f, "{} {} {} {}", self.sample, self.voice_assign, self.filter_type, self.ifx_type)?;
         for step in self.steps.iter() {
             write!(f, "\n    {}", step)?;
         }
@@ -0,0 +1,195 @@
+//! Telling apart patterns of the electribe (synth) and the electribe sampler,
+//! and converting patterns between the two.
+//!
+//! Both devices use the same pattern layout, with 16 parts per pattern at the same
+//! offsets; parts are not counted or numbered differently, so patterns are read and
+//! written the same way for both. The part's oscillator number picks a synth
+//! oscillator on the electribe, and a preset or user sample on the sampler,
+//! so a pattern only sounds right on the other device once its oscillator numbers
+//! are mapped to equivalent ones.
+
+use super::*;
+use std::collections::BTreeMap;
+
+/// Which device a pattern bank was made on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Electribe2Device {
+    /// electribe (synth), whose parts play oscillators
+    Synth,
+    /// electribe sampler (e2s), whose parts play preset and user samples
+    Sampler,
+}
+
+impl Electribe2Device {
+
+    /// Guess the device from a file name: single patterns are .e2pat on the synth and
+    /// .e2spat on the sampler, and the sampler's files are prefixed with e2s.
+    pub fn from_path (path: &std::path::Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.starts_with("e2s") || name.contains("sampler") || name.ends_with(".e2spat") {
+            Some(Self::Sampler)
+        } else if name.starts_with("electribe") || name.ends_with(".e2pat") {
+            Some(Self::Synth)
+        } else {
+            None
+        }
+    }
+
+    /// File extension of a single pattern
+    pub fn pattern_extension (&self) -> &'static str {
+        match self {
+            Self::Synth   => "e2pat",
+            Self::Sampler => "e2spat",
+        }
+    }
+
+    /// Whether parts can play user samples (501-999)
+    pub fn has_user_samples (&self) -> bool {
+        *self == Self::Sampler
+    }
+
+}
+
+impl std::fmt::Display for Electribe2Device {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Synth   => write!(f, "electribe"),
+            Self::Sampler => write!(f, "electribe sampler"),
+        }
+    }
+}
+
+impl std::str::FromStr for Electribe2Device {
+    type Err = String;
+    fn from_str (text: &str) -> Result<Self, Self::Err> {
+        match text.trim().to_lowercase().as_str() {
+            "synth" | "e2" | "electribe" => Ok(Self::Synth),
+            "sampler" | "e2s" => Ok(Self::Sampler),
+            _ => Err(format!("unknown device {text:?}, expected synth or sampler"))
+        }
+    }
+}
+
+impl Electribe2PatternBank {
+
+    /// Guess the device from the patterns: only the sampler plays user samples.
+    /// Returns None when nothing tells the devices apart. Nothing in the content is known
+    /// to mark a bank as the synth's: oscillator numbers up to 500 are valid on both,
+    /// so the synth is only recognized by file name, see `Electribe2Device::from_path`.
+    pub fn detect_device (&self) -> Option<Electribe2Device> {
+        let user_samples = self.patterns.iter()
+            .any(|pattern|pattern.parts.iter().any(|part|part.uses_user_sample()));
+        if user_samples {
+            Some(Electribe2Device::Sampler)
+        } else {
+            None
+        }
+    }
+
+    /// Convert the patterns for another device, mapping the oscillator numbers
+    /// of all parts through a table of displayed numbers. Returns the patterns and parts
+    /// (both zero-based), with their oscillator numbers, that play notes but have
+    /// no entry in the table, or that play a user sample the target device lacks.
+    pub fn convert (&mut self, to: Electribe2Device, table: &BTreeMap<u16, u16>) -> Vec<(usize, usize, u16)> {
+        let mut unmapped = vec![];
+        for (pattern_index, pattern) in self.patterns.iter_mut().enumerate() {
+            for (part_index, part) in pattern.parts.iter_mut().enumerate() {
+                let number = part.sample_number();
+                if let Some(mapped) = table.get(&number) {
+                    part.set_sample_number(*mapped);
+                } else {
+                    let plays = part.steps.iter().any(|step|step.notes().count() > 0);
+                    if plays || (part.uses_user_sample() && !to.has_user_samples()) {
+                        unmapped.push((pattern_index, part_index, number));
+                    }
+                }
+            }
+        }
+        self.device = Some(to);
+        unmapped
+    }
+
+}
+
+/// Parse a table of oscillator numbers, one `FROM=TO` pair per line
+/// (commas or spaces also separate the numbers; `#` starts a comment)
+pub fn parse_oscillator_table (text: &str) -> std::result::Result<BTreeMap<u16, u16>, String> {
+    let mut table = BTreeMap::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue
+        }
+        let numbers = line
+            .split(|c: char|c == '=' || c == ',' || c.is_whitespace())
+            .filter(|x|!x.is_empty())
+            .map(|x|x.parse::<u16>())
+            .collect::<Vec<_>>();
+        match numbers.as_slice() {
+            [Ok(from), Ok(to)] if (1..=USER_SAMPLES_END).contains(from) && (1..=USER_SAMPLES_END).contains(to) => {
+                table.insert(*from, *to);
+            },
+            _ => return Err(format!("line {}: invalid entry {line:?}, expected FROM=TO from 1 to {USER_SAMPLES_END}", index + 1))
+        }
+    }
+    Ok(table)
+}
+
+/// Swap the sides of a table, to convert in the other direction
+pub fn reverse_oscillator_table (table: &BTreeMap<u16, u16>) -> BTreeMap<u16, u16> {
+    table.iter().map(|(from, to)|(*to, *from)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_path () {
+        let device = |name: &str|Electribe2Device::from_path(std::path::Path::new(name));
+        assert_eq!(device("/media/SD/KORG/electribe/e2sSample.all"), Some(Electribe2Device::Sampler));
+        assert_eq!(device("Pattern 001.E2SPAT"), Some(Electribe2Device::Sampler));
+        assert_eq!(device("electribe_patterns.all"), Some(Electribe2Device::Synth));
+        assert_eq!(device("Pattern 001.e2pat"), Some(Electribe2Device::Synth));
+        assert_eq!(device("backup.all"), None);
+        assert_eq!("E2S".parse::<Electribe2Device>(), Ok(Electribe2Device::Sampler));
+        assert!("e3".parse::<Electribe2Device>().is_err());
+    }
+
+    #[test]
+    fn oscillator_table () {
+        let table = parse_oscillator_table("# synth = sampler\n1=501\n\n2, 502 # comment\n 3 503\n").unwrap();
+        assert_eq!(table, BTreeMap::from([(1, 501), (2, 502), (3, 503)]));
+        assert_eq!(reverse_oscillator_table(&table), BTreeMap::from([(501, 1), (502, 2), (503, 3)]));
+        assert_eq!(parse_oscillator_table(""), Ok(BTreeMap::new()));
+        assert!(parse_oscillator_table("1=501\nbad\n").unwrap_err().starts_with("line 2:"));
+        assert!(parse_oscillator_table("1=2=3").is_err());
+        assert!(parse_oscillator_table("0=501").is_err());
+        assert!(parse_oscillator_table("1=1000").is_err());
+    }
+
+    #[test]
+    fn convert () {
+        let mut bank = Electribe2PatternBank { patterns: vec![Electribe2Pattern::init(); 2], ..Default::default() };
+        assert_eq!(bank.detect_device(), None);
+        bank.patterns[0].parts[0].set_sample_number(501);
+        bank.patterns[0].parts[0].steps[0].set_note(0, Some(60));
+        // Plays a user sample that the synth lacks, even without notes
+        bank.patterns[0].parts[1].set_sample_number(600);
+        // Plays notes on an oscillator that is not in the table
+        bank.patterns[1].parts[3].set_sample_number(40);
+        bank.patterns[1].parts[3].steps[5].set_note(0, Some(48));
+        assert_eq!(bank.detect_device(), Some(Electribe2Device::Sampler));
+        let table = BTreeMap::from([(501, 12)]);
+        let unmapped = bank.convert(Electribe2Device::Synth, &table);
+        assert_eq!(unmapped, vec![(0, 1, 600), (1, 3, 40)]);
+        assert_eq!(bank.patterns[0].parts[0].sample_number(), 12);
+        assert_eq!(bank.patterns[0].parts[1].sample_number(), 600);
+        assert_eq!(bank.device, Some(Electribe2Device::Synth));
+        // Converting back restores the original numbers
+        let unmapped = bank.convert(Electribe2Device::Sampler, &reverse_oscillator_table(&table));
+        assert_eq!(unmapped, vec![(1, 3, 40)]);
+        assert_eq!(bank.patterns[0].parts[0].sample_number(), 501);
+        assert_eq!(Electribe2Device::Sampler.pattern_extension(), "e2spat");
+    }
+}
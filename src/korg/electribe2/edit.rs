@@ -0,0 +1,453 @@
+//! Bulk editing and comparison of patterns.
+
+use super::*;
+
+/// Gate given to a step when its first note is entered: one full step
+pub const STEP_GATE: u8 = 24;
+/// Velocity given to a step when its first note is entered
+pub const STEP_VELOCITY: u8 = 100;
+
+/// Slowest tempo of a pattern
+pub const BPM_MIN: f32 = 20.0;
+/// Fastest tempo of a pattern
+pub const BPM_MAX: f32 = 300.0;
+
+/// Parse a selection of pattern numbers such as `1-10,15`, or `all`,
+/// into zero-based indices below `count`
+pub fn parse_selection (text: &str, count: usize) -> std::result::Result<Vec<usize>, String> {
+    if text.trim() == "all" {
+        return Ok((0..count).collect())
+    }
+    let mut selection = vec![];
+    for range in text.split(',').map(|range|range.trim()).filter(|range|!range.is_empty()) {
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (start.trim().parse::<usize>(), end.trim().parse::<usize>()),
+            None => (range.parse::<usize>(), range.parse::<usize>())
+        };
+        match (start, end) {
+            (Ok(start), Ok(end)) if start >= 1 && start <= end => {
+                selection.extend((start - 1)..end.min(count));
+            },
+            _ => return Err(format!("invalid range {range:?}"))
+        }
+    }
+    selection.sort();
+    selection.dedup();
+    Ok(selection)
+}
+
+impl Electribe2PatternBank {
+
+    /// Switch the places of two patterns
+    pub fn swap (&mut self, a: usize, b: usize) -> bool {
+        if a < self.patterns.len() && b < self.patterns.len() {
+            self.patterns.swap(a, b);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Move a pattern to another position, shifting the ones in between
+    pub fn move_pattern (&mut self, from: usize, to: usize) -> bool {
+        if from < self.patterns.len() && to < self.patterns.len() {
+            let pattern = self.patterns.remove(from);
+            self.patterns.insert(to, pattern);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Describe the differences between two banks, pattern by pattern
+    pub fn diff (&self, other: &Self) -> Vec<String> {
+        let mut lines = vec![];
+        for index in 0..self.patterns.len().max(other.patterns.len()) {
+            match (self.patterns.get(index), other.patterns.get(index)) {
+                (Some(a), Some(b)) => {
+                    let changes = a.diff(b);
+                    if !changes.is_empty() {
+                        lines.push(format!("Pattern {} ({}):", index + 1, a.name));
+                        lines.extend(changes.into_iter().map(|change|format!("  {change}")));
+                    }
+                },
+                (Some(a), None) => lines.push(format!("Pattern {} ({}): only in first", index + 1, a.name)),
+                (None, Some(b)) => lines.push(format!("Pattern {} ({}): only in second", index + 1, b.name)),
+                (None, None) => {}
+            }
+        }
+        lines
+    }
+
+}
+
+impl Electribe2Pattern {
+
+    /// Rename the pattern from a template, where `{n}` is replaced with the
+    /// pattern number and `{name}` with the current name
+    pub fn rename (&mut self, template: &str, number: usize) {
+        self.name = template
+            .replace("{n}", &number.to_string())
+            .replace("{name}", self.name.trim())
+            .chars()
+            .take(16)
+            .collect();
+    }
+
+    /// Replace a part with the same part of another pattern, including its motion sequences.
+    /// Returns the number of motion sequences copied.
+    pub fn copy_part (&mut self, from: &Self, part: usize) -> usize {
+        self.parts[part] = from.parts[part].clone();
+        self.copy_motion(from, part as u8)
+    }
+
+    /// Names and values of the pattern's parameters
+    pub fn fields (&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("Name",      self.name.clone()),
+            ("BPM",       format!("{:.1}", self.bpm)),
+            ("Swing",     self.swing.to_string()),
+            ("Length",    self.length.to_string()),
+            ("Beats",     self.beats.to_string()),
+            ("Key",       self.key.to_string()),
+            ("Scale",     self.scale.to_string()),
+            ("Chord set", self.chord_set.to_string()),
+            ("Level",     self.level.to_string()),
+            ("Gate arp",  self.gate_arp.to_string()),
+            ("MFX",       self.mfx_type.to_string()),
+            ("Alt 13/14", self.alt_13_14.to_string()),
+            ("Alt 15/16", self.alt_15_16.to_string()),
+        ]
+    }
+
+    /// Step a parameter, by its label in `fields`, up or down within its valid range.
+    /// Returns whether the value changed; the name is not a steppable parameter.
+    pub fn adjust (&mut self, field: &str, delta: isize) -> bool {
+        match field {
+            "BPM" => {
+                let bpm = (self.bpm + delta as f32).clamp(BPM_MIN, BPM_MAX);
+                let changed = bpm != self.bpm;
+                self.bpm = bpm;
+                changed
+            },
+            "Swing"     => adjust_signed(&mut self.swing, delta, -48, 48),
+            "Length"    => adjust(&mut self.length,    delta, 1, 4),
+            "Beats"     => adjust_named(&mut self.beats,    delta, Beats::NAMES),
+            "Key"       => adjust_named(&mut self.key,      delta, Key::NAMES),
+            "Scale"     => adjust_named(&mut self.scale,    delta, Scale::NAMES),
+            "Chord set" => adjust(&mut self.chord_set, delta, 0, 4),
+            "Level"     => adjust(&mut self.level,     delta, 0, 127),
+            "Gate arp"  => adjust(&mut self.gate_arp,  delta, 0, 127),
+            "MFX"       => adjust_named(&mut self.mfx_type, delta, MasterFx::NAMES),
+            "Alt 13/14" => adjust(&mut self.alt_13_14, delta, 0, 1),
+            "Alt 15/16" => adjust(&mut self.alt_15_16, delta, 0, 1),
+            _ => false
+        }
+    }
+
+    /// Set the name, keeping the first 16 printable ASCII characters
+    pub fn set_name (&mut self, name: &str) {
+        self.name = name.chars().filter(|c|c.is_ascii() && !c.is_ascii_control()).take(16).collect();
+    }
+
+    /// Describe the differences between two patterns: parameters, part parameters,
+    /// steps and motion sequences
+    pub fn diff (&self, other: &Self) -> Vec<String> {
+        let mut lines = diff_fields(&self.fields(), &other.fields());
+        for (index, (a, b)) in self.parts.iter().zip(other.parts.iter()).enumerate() {
+            for change in diff_fields(&a.fields(), &b.fields()) {
+                lines.push(format!("Part {}: {change}", index + 1));
+            }
+            for (step, (x, y)) in a.steps.iter().zip(b.steps.iter()).enumerate() {
+                let (x, y) = (x.describe(), y.describe());
+                if x != y {
+                    lines.push(format!("Part {} step {}: {x} -> {y}", index + 1, step + 1));
+                }
+            }
+        }
+        for (slot, (a, b)) in self.motion.iter().zip(other.motion.iter()).enumerate() {
+            if (a.part, a.parameter) != (b.part, b.parameter) {
+                lines.push(format!("Motion {}: part {} {} -> part {} {}",
+                    slot + 1, a.part, a.parameter_name(), b.part, b.parameter_name()));
+            } else if a.steps != b.steps {
+                let changed = a.steps.iter().zip(b.steps.iter()).filter(|(x, y)|x != y).count();
+                lines.push(format!("Motion {}: {changed} steps changed", slot + 1));
+            }
+        }
+        lines
+    }
+
+}
+
+impl Electribe2Part {
+
+    /// Names and values of the part's parameters
+    pub fn fields (&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("Last step",   self.last_step.to_string()),
+            ("Voice",       self.voice_assign.to_string()),
+            ("Priority",    self.priority.to_string()),
+            ("Motion seq",  self.motion_seq.to_string()),
+            ("Trigger vel", self.trigger_vel.to_string()),
+            ("Scale",       self.scale.to_string()),
+            ("Sample",      self.sample.to_string()),
+            ("Osc edit",    self.osc.to_string()),
+            ("Filter",      self.filter_type.to_string()),
+            ("Cutoff",      self.filter_cutoff.to_string()),
+            ("Resonance",   self.filter_resonance.to_string()),
+            ("Filter EG",   self.filter_envelope.to_string()),
+            ("Mod",         self.modulation_type.to_string()),
+            ("Mod speed",   self.modulation_speed.to_string()),
+            ("Mod depth",   self.modulation_depth.to_string()),
+            ("Attack",      self.attack.to_string()),
+            ("Decay",       self.decay.to_string()),
+            ("Level",       self.level.to_string()),
+            ("Pan",         self.pan.to_string()),
+            ("Amp EG",      self.amp_eg.to_string()),
+            ("MFX on",      self.mfx_on.to_string()),
+            ("Groove",      self.groove_type.to_string()),
+            ("Groove amt",  self.groove_depth.to_string()),
+            ("IFX on",      self.ifx_on.to_string()),
+            ("IFX",         self.ifx_type.to_string()),
+            ("IFX param",   self.ifx_param.to_string()),
+            ("Pitch",       self.pitch.to_string()),
+            ("Glide",       self.glide.to_string()),
+        ]
+    }
+
+    /// Step a parameter, by its label in `fields`, up or down within its valid range.
+    /// Returns whether the value changed.
+    pub fn adjust (&mut self, field: &str, delta: isize) -> bool {
+        match field {
+            "Last step"   => adjust(&mut self.last_step,        delta, 1, 16),
+            "Voice"       => adjust_named(&mut self.voice_assign, delta, VoiceAssign::NAMES),
+            "Priority"    => adjust(&mut self.priority,         delta, 0, 1),
+            "Motion seq"  => adjust_named(&mut self.motion_seq, delta, MotionSeqMode::NAMES),
+            "Trigger vel" => adjust(&mut self.trigger_vel,      delta, 0, 1),
+            "Scale"       => adjust(&mut self.scale,            delta, 0, 1),
+            "Sample"      => {
+                let number = (self.sample_number() as isize + delta).clamp(1, USER_SAMPLES_END as isize) as u16;
+                let changed = number != self.sample_number();
+                self.set_sample_number(number);
+                changed
+            },
+            "Osc edit"    => adjust(&mut self.osc,              delta, 0, 127),
+            "Filter"      => adjust_named(&mut self.filter_type, delta, Filter::NAMES),
+            "Cutoff"      => adjust(&mut self.filter_cutoff,    delta, 0, 127),
+            "Resonance"   => adjust(&mut self.filter_resonance, delta, 0, 127),
+            "Filter EG"   => adjust(&mut self.filter_envelope,  delta, 0, 127),
+            "Mod"         => adjust(&mut self.modulation_type.0, delta, 0, MODULATION_TYPES - 1),
+            "Mod speed"   => adjust(&mut self.modulation_speed, delta, 0, 127),
+            "Mod depth"   => adjust(&mut self.modulation_depth, delta, 0, 127),
+            "Attack"      => adjust(&mut self.attack,           delta, 0, 127),
+            "Decay"       => adjust(&mut self.decay,            delta, 0, 127),
+            "Level"       => adjust(&mut self.level,            delta, 0, 127),
+            "Pan"         => adjust(&mut self.pan,              delta, 0, 127),
+            "Amp EG"      => adjust(&mut self.amp_eg,           delta, 0, 1),
+            "MFX on"      => adjust(&mut self.mfx_on,           delta, 0, 1),
+            "Groove"      => adjust(&mut self.groove_type.0,    delta, 0, GROOVE_TYPES - 1),
+            "Groove amt"  => adjust(&mut self.groove_depth,     delta, 0, 127),
+            "IFX on"      => adjust(&mut self.ifx_on,           delta, 0, 1),
+            "IFX"         => adjust_named(&mut self.ifx_type,   delta, InsertFx::NAMES),
+            "IFX param"   => adjust(&mut self.ifx_param,        delta, 0, 127),
+            "Pitch"       => adjust(&mut self.pitch,            delta, 0, 127),
+            "Glide"       => adjust(&mut self.glide,            delta, 0, 127),
+            _ => false
+        }
+    }
+
+}
+
+impl Electribe2Step {
+
+    /// MIDI note in one of the four note slots (0-3), if any
+    pub fn note (&self, slot: usize) -> Option<u8> {
+        [self.note_1, self.note_2, self.note_3, self.note_4].get(slot)
+            .filter(|note|**note > 0)
+            .map(|note|note - 1)
+    }
+
+    /// Whether the step plays a MIDI note
+    pub fn has_note (&self, note: u8) -> bool {
+        self.notes().any(|x|x == note)
+    }
+
+    /// Set or clear the MIDI note in one of the four note slots (0-3).
+    /// Entering the first note gives the step a default gate and velocity.
+    pub fn set_note (&mut self, slot: usize, note: Option<u8>) {
+        let value = note.map(|note|note.min(127) + 1).unwrap_or(0);
+        match slot {
+            0 => self.note_1 = value,
+            1 => self.note_2 = value,
+            2 => self.note_3 = value,
+            3 => self.note_4 = value,
+            _ => return
+        }
+        if self.notes().count() > 0 {
+            if self.empty == 0 {
+                self.gate     = if self.gate == 0 { STEP_GATE } else { self.gate };
+                self.velocity = if self.velocity == 0 { STEP_VELOCITY } else { self.velocity };
+            }
+            self.empty = 1;
+        } else {
+            self.empty = 0;
+        }
+    }
+
+    /// Remove a note from the step if it plays it, otherwise add it to the first free slot.
+    /// Returns false if the note could not be added because all four slots are taken.
+    pub fn toggle_note (&mut self, note: u8) -> bool {
+        if let Some(slot) = (0..4).find(|slot|self.note(*slot) == Some(note)) {
+            self.set_note(slot, None);
+            true
+        } else if let Some(slot) = (0..4).find(|slot|self.note(*slot).is_none()) {
+            self.set_note(slot, Some(note));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Short description of the step's notes, gate and velocity
+    pub fn describe (&self) -> String {
+        if self.notes().count() == 0 {
+            return "-".into()
+        }
+        let notes = self.notes().map(|note|note.to_string()).collect::<Vec<_>>().join(",");
+        format!("notes {notes} gate {} velocity {}", self.gate, self.velocity)
+    }
+
+}
+
+/// Describe the fields whose values differ
+fn diff_fields (a: &[(&'static str, String)], b: &[(&'static str, String)]) -> Vec<String> {
+    a.iter().zip(b.iter())
+        .filter(|((_, x), (_, y))|x != y)
+        .map(|((label, x), (_, y))|format!("{label}: {x} -> {y}"))
+        .collect()
+}
+
+/// Step a value within a range. Returns whether it changed.
+fn adjust (value: &mut u8, delta: isize, min: u8, max: u8) -> bool {
+    let new = (*value as isize + delta).clamp(min as isize, max as isize) as u8;
+    let changed = new != *value;
+    *value = new;
+    changed
+}
+
+/// Step a value that is stored as a signed byte within a range
+fn adjust_signed (value: &mut u8, delta: isize, min: i8, max: i8) -> bool {
+    let new = (*value as i8 as isize + delta).clamp(min as isize, max as isize) as i8 as u8;
+    let changed = new != *value;
+    *value = new;
+    changed
+}
+
+/// Step a named parameter through its named values
+fn adjust_named <T: Copy + From<u8> + Into<u8>> (value: &mut T, delta: isize, names: &[&str]) -> bool {
+    let mut raw: u8 = (*value).into();
+    let changed = adjust(&mut raw, delta, 0, names.len() as u8 - 1);
+    *value = T::from(raw);
+    changed
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_selection () {
+        assert_eq!(super::parse_selection("all", 3), Ok(vec![0, 1, 2]));
+        assert_eq!(super::parse_selection(" 4-6, 1 ,5", 250), Ok(vec![0, 3, 4, 5]));
+        // Ranges end at the last pattern
+        assert_eq!(super::parse_selection("248-300", 250), Ok(vec![247, 248, 249]));
+        assert_eq!(super::parse_selection("251", 250), Ok(vec![]));
+        assert_eq!(super::parse_selection("", 250), Ok(vec![]));
+        assert!(super::parse_selection("0", 250).is_err());
+        assert!(super::parse_selection("6-4", 250).is_err());
+        assert!(super::parse_selection("1,x", 250).is_err());
+    }
+
+    #[test]
+    fn diff () {
+        let a = Electribe2Pattern::init();
+        let mut b = Electribe2Pattern::init();
+        assert_eq!(a.diff(&b), Vec::<String>::new());
+        b.bpm = 128.0;
+        b.parts[1].filter_cutoff = 50;
+        b.parts[0].steps[2].set_note(0, Some(60));
+        b.motion[4] = Electribe2Motion { part: 2, parameter: 6, steps: vec![0; 64] };
+        assert_eq!(a.diff(&b), vec![
+            "BPM: 120.0 -> 128.0",
+            "Part 1 step 3: - -> notes 60 gate 24 velocity 100",
+            "Part 2: Cutoff: 0 -> 50",
+            "Motion 5: part 0 None -> part 2 Cutoff",
+        ]);
+        let mut c = b.clone();
+        c.motion[4].steps[7] = 90;
+        assert_eq!(b.diff(&c), vec!["Motion 5: 1 steps changed"]);
+        let first  = Electribe2PatternBank { patterns: vec![a.clone(), a.clone()], ..Default::default() };
+        let second = Electribe2PatternBank { patterns: vec![a.clone(), b], ..Default::default() };
+        let lines = first.diff(&second);
+        assert_eq!(lines[0], "Pattern 2 (Init Pattern):");
+        assert_eq!(lines[1], "  BPM: 120.0 -> 128.0");
+        assert_eq!(lines.len(), 5);
+        let third = Electribe2PatternBank { patterns: vec![a], ..Default::default() };
+        assert_eq!(first.diff(&third), vec!["Pattern 2 (Init Pattern): only in first"]);
+    }
+
+    #[test]
+    fn reorder_and_rename () {
+        let mut bank = Electribe2PatternBank { patterns: vec![Electribe2Pattern::init(); 3], ..Default::default() };
+        for (index, pattern) in bank.patterns.iter_mut().enumerate() {
+            pattern.rename("{name} {n}", index + 1);
+        }
+        assert_eq!(bank.patterns[2].name, "Init Pattern 3");
+        assert!(bank.swap(0, 2));
+        assert!(!bank.swap(0, 3));
+        assert!(bank.move_pattern(0, 1));
+        let names = bank.patterns.iter().map(|pattern|pattern.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["Init Pattern 2", "Init Pattern 3", "Init Pattern 1"]);
+        bank.patterns[0].set_name("A very long name\twith a tab");
+        assert_eq!(bank.patterns[0].name, "A very long name");
+    }
+
+    #[test]
+    fn adjust () {
+        let mut pattern = Electribe2Pattern::init();
+        assert!(pattern.adjust("BPM", 500));
+        assert_eq!(pattern.bpm, BPM_MAX);
+        assert!(!pattern.adjust("BPM", 1));
+        assert!(pattern.adjust("Swing", -100));
+        assert_eq!(pattern.swing as i8, -48);
+        assert!(pattern.adjust("Key", 13));
+        assert_eq!(pattern.key, Key::B);
+        assert!(!pattern.adjust("Name", 1));
+        let part = &mut pattern.parts[0];
+        assert!(!part.adjust("Filter", -1));
+        assert!(part.adjust("Sample", 2000));
+        assert_eq!(part.sample_number(), USER_SAMPLES_END);
+        assert!(part.adjust("Mod", 100));
+        assert_eq!(part.modulation_type, Modulation(MODULATION_TYPES - 1));
+    }
+
+    #[test]
+    fn step_notes () {
+        let mut step = Electribe2Step::default();
+        assert!(step.toggle_note(60));
+        assert_eq!((step.empty, step.gate, step.velocity), (1, STEP_GATE, STEP_VELOCITY));
+        assert!(step.toggle_note(64));
+        assert!(step.toggle_note(67));
+        assert!(step.toggle_note(71));
+        assert!(!step.toggle_note(72));
+        assert_eq!(step.note(3), Some(71));
+        assert!(step.toggle_note(60));
+        assert_eq!(step.note(0), None);
+        assert!(step.has_note(64));
+        for note in [64, 67, 71] {
+            step.toggle_note(note);
+        }
+        assert_eq!(step.empty, 0);
+        assert_eq!(step.describe(), "-");
+    }
+}
@@ -1,5 +1,13 @@
 opt_mod::optional_module_flat!("cli": cli);
 opt_mod::optional_module_flat!("tui": tui);
+opt_mod::module_flat!(smf);
+opt_mod::module_flat!(samples);
+opt_mod::module_flat!(slices);
+opt_mod::module_flat!(usage);
+opt_mod::module_flat!(edit);
+opt_mod::module_flat!(params);
+opt_mod::module_flat!(text);
+opt_mod::module_flat!(device);
 
 /// Pattern start tag
 const PTST: [u8; 4] = [80, 84, 83, 84];
@@ -7,66 +15,122 @@ const PTST: [u8; 4] = [80, 84, 83, 84];
 const PTED: [u8; 4] = [80, 84, 69, 68];
 const PATTERNS_OFFSET: usize = 0x10100;
 const PATTERN_SIZE: usize = 0x4000;
+/// Size of the header of a single-pattern file (.e2pat/.e2spat)
+const PATTERN_FILE_HEADER: usize = 0x0100;
+/// Size of a single-pattern file (.e2pat/.e2spat)
+const PATTERN_FILE_SIZE: usize = PATTERN_FILE_HEADER + PATTERN_SIZE;
 const PARTS_OFFSET: usize = 0x0800;
 const PART_SIZE: usize = 0x0330;
-const STEPS_OFFSET: usize = 0x001e;
+const STEPS_OFFSET: usize = 0x0030;
 const STEP_SIZE: usize = 0x000c;
-
-#[derive(Debug, Default)]
-pub struct Electribe2SampleBank {
-}
+const PATTERN_COUNT: usize = 250;
+const MOTION_OFFSET: usize = 0x0100;
+const MOTION_STEPS_OFFSET: usize = 0x0130;
+const MOTION_SLOTS: usize = 24;
+const MOTION_STEPS: usize = 64;
 
 #[derive(Debug, Default)]
 pub struct Electribe2PatternBank {
-    pub patterns: Vec<Electribe2Pattern>
+    /// 0x00000..0x10100 - file header, preserved as-is
+    pub header:   Vec<u8>,
+    /// 250 patterns of 0x4000 bytes each
+    pub patterns: Vec<Electribe2Pattern>,
+    /// Anything after the last pattern, preserved as-is
+    pub footer:   Vec<u8>,
+    /// Device the patterns were made on, if known
+    pub device:   Option<Electribe2Device>,
 }
 
 impl Electribe2PatternBank {
     /// Create an empty pattern bundle
     pub fn empty () -> Self {
-        Self { patterns: Vec::with_capacity(250) }
+        Self {
+            header:   vec![0; PATTERNS_OFFSET],
+            patterns: Vec::with_capacity(PATTERN_COUNT),
+            footer:   vec![],
+            device:   None
+        }
+    }
+    /// Create an empty pattern bundle with the header of an existing one
+    pub fn empty_like (other: &Self) -> Self {
+        Self { header: other.header.clone(), footer: other.footer.clone(), device: other.device, ..Self::empty() }
+    }
+    /// Index of the first slot that is free to take a new pattern: an init pattern
+    /// without notes, or a slot past the last pattern
+    pub fn free_slot (&self) -> Option<usize> {
+        self.patterns.iter().position(Electribe2Pattern::is_init)
+            .or_else(||(self.patterns.len() < PATTERN_COUNT).then_some(self.patterns.len()))
     }
     /// Read a pattern bundle
     pub fn read (raw: &[u8]) -> Self {
         let mut patterns = vec![];
-        for index in 0..250 {
+        for index in 0..PATTERN_COUNT {
             let start = PATTERNS_OFFSET + index * PATTERN_SIZE;
             let end   = start + PATTERN_SIZE;
             let pattern = Electribe2Pattern::read(&raw[start..end]);
             patterns.push(pattern);
         }
-        Self { patterns }
+        let end = PATTERNS_OFFSET + PATTERN_COUNT * PATTERN_SIZE;
+        let mut bank = Self {
+            header: raw[..PATTERNS_OFFSET].to_vec(),
+            patterns,
+            footer: raw[end..].to_vec(),
+            device: None
+        };
+        bank.device = bank.detect_device();
+        bank
+    }
+    /// Write a pattern bundle. Empty slots at the end are filled with init patterns.
+    pub fn write (&self) -> Vec<u8> {
+        assert!(self.patterns.len() <= PATTERN_COUNT,
+            "a pattern bundle can hold at most {PATTERN_COUNT} patterns, got {}", self.patterns.len());
+        let mut raw = Vec::with_capacity(PATTERNS_OFFSET + PATTERN_COUNT * PATTERN_SIZE + self.footer.len());
+        raw.extend_from_slice(&self.header);
+        raw.resize(PATTERNS_OFFSET, 0);
+        for index in 0..PATTERN_COUNT {
+            raw.extend_from_slice(&match self.patterns.get(index) {
+                Some(pattern) => pattern.write(),
+                None => Electribe2Pattern::init().write()
+            });
+        }
+        raw.extend_from_slice(&self.footer);
+        raw
     }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Electribe2Pattern {
+    /// 0x0000..0x4000 - the original pattern block, so that unknown bytes survive a rewrite
+    #[serde(skip)]
+    pub raw:       Vec<u8>,
     /// 0x0010..0x0020 - name
     pub name:      String,
-    /// 0x0012..0x0013 - bpm
+    /// 0x0022..0x0023 - bpm, stored as bpm * 10
     pub bpm:       f32,
-    /// 0x0014 - swing
+    /// 0x0024 - swing
     pub swing:     u8,
-    /// 0x0015 - length
+    /// 0x0025 - length in bars (1-4)
     pub length:    u8,
-    /// 0x0016 - beats
-    pub beats:     u8,
-    /// 0x0017 - key
-    pub key:       u8,
-    /// 0x0018 - scale
-    pub scale:     u8,
-    /// 0x0019 - chord set
+    /// 0x0026 - beat: 0 = 16th, 1 = 32nd, 2 = 8th triplet, 3 = 16th triplet
+    pub beats:     Beats,
+    /// 0x0027 - key
+    pub key:       Key,
+    /// 0x0028 - scale
+    pub scale:     Scale,
+    /// 0x0029 - chord set
     pub chord_set: u8,
-    /// 0x001a - level
+    /// 0x002a - level, stored as 127 - level
     pub level:     u8,
-    /// 0x0021 - gate arp
+    /// 0x0031 - gate arp
     pub gate_arp:  u8,
-    /// 0x002d - master fx type
-    pub mfx_type:  u8,
-    /// 0x0034 - alt 13/14
+    /// 0x003d - master fx type
+    pub mfx_type:  MasterFx,
+    /// 0x0044 - alt 13/14
     pub alt_13_14: u8,
-    /// 0x0035 - alt 15/16
+    /// 0x0045 - alt 15/16
     pub alt_15_16: u8,
+    /// 0x0100..0x0730 - 24 motion sequence slots
+    pub motion:    Vec<Electribe2Motion>,
     /// 0x0800..0x0b30 - one track (816 bytes)
     pub parts:     Vec<Electribe2Part>
 }
@@ -74,48 +138,243 @@ pub struct Electribe2Pattern {
 impl Electribe2Pattern {
     pub fn read (raw: &[u8]) -> Self {
         assert_eq!(&raw[0x0000..0x0004], &PTST);
-        let mut pattern = Self::default();
+        let mut pattern = Self { raw: raw.to_vec(), ..Self::default() };
         let name_raw = raw[0x0010..0x0020].iter()
             .map(|x|if *x == 0 { 32 } else { *x })
             .collect::<Vec<u8>>();
         pattern.name = String::from_utf8(name_raw)
             .expect("invalid pattern name").trim().into();
-        pattern.bpm    = f32::from(raw[0x0012] as u16 + 0x100u16 * raw[0x0013] as u16) / 256.0;
-        pattern.swing  = raw[0x0014];
-        pattern.length = raw[0x0015];
-        pattern.beats  = raw[0x0016];
-        pattern.key    = raw[0x0017];
-        pattern.scale  = raw[0x0018];
+        pattern.bpm       = f32::from(u16::from_le_bytes([raw[0x0022], raw[0x0023]])) / 10.0;
+        pattern.swing     = raw[0x0024];
+        pattern.length    = raw[0x0025];
+        pattern.beats     = raw[0x0026].into();
+        pattern.key       = raw[0x0027].into();
+        pattern.scale     = raw[0x0028].into();
+        pattern.chord_set = raw[0x0029];
+        pattern.level     = 127u8.saturating_sub(raw[0x002a]);
+        pattern.gate_arp  = raw[0x0031];
+        pattern.mfx_type  = raw[0x003d].into();
+        pattern.alt_13_14 = raw[0x0044];
+        pattern.alt_15_16 = raw[0x0045];
+        for slot in 0..MOTION_SLOTS {
+            pattern.motion.push(Electribe2Motion::read(raw, slot));
+        }
         for i in 0..16 {
-            let start = PARTS_OFFSET + i * 0x330;
+            let start = PARTS_OFFSET + i * PART_SIZE;
             let end   = start + PART_SIZE;
             pattern.parts.push(Electribe2Part::read(&raw[start..end]))
         }
         assert_eq!(&raw[0x3BFC..0x3C00], &PTED);
         pattern
     }
+    /// Read a single-pattern file (.e2pat/.e2spat)
+    pub fn read_file (raw: &[u8]) -> std::result::Result<Self, String> {
+        if raw.len() < PATTERN_FILE_SIZE {
+            return Err(format!("single-pattern files are at least {PATTERN_FILE_SIZE} bytes long, got {}", raw.len()))
+        }
+        if raw[PATTERN_FILE_HEADER..PATTERN_FILE_HEADER+4] != PTST {
+            return Err("no pattern after the file header".into())
+        }
+        Ok(Self::read(&raw[PATTERN_FILE_HEADER..PATTERN_FILE_SIZE]))
+    }
+    /// Write a single-pattern file (.e2pat/.e2spat). The header is taken from
+    /// the start of a bank's header, which begins with the same file signature.
+    pub fn write_file (&self, header: &[u8]) -> Vec<u8> {
+        let mut raw = header[..PATTERN_FILE_HEADER.min(header.len())].to_vec();
+        raw.resize(PATTERN_FILE_HEADER, 0);
+        raw.extend_from_slice(&self.write());
+        raw
+    }
+    /// Motion sequence slots in use, with their slot numbers
+    pub fn motion_slots (&self) -> impl Iterator<Item = (usize, &Electribe2Motion)> {
+        self.motion.iter().enumerate().filter(|(_, motion)|motion.is_used())
+    }
+    /// Copy the motion sequences of one part of another pattern into free slots
+    /// of this pattern, replacing any existing motion sequences for that part.
+    /// Returns how many slots were copied.
+    pub fn copy_motion (&mut self, from: &Self, part: u8) -> usize {
+        for motion in self.motion.iter_mut() {
+            if motion.part == part + 1 {
+                *motion = Electribe2Motion::default();
+            }
+        }
+        let mut copied = 0;
+        for source in from.motion.iter().filter(|motion|motion.part == part + 1) {
+            if let Some(target) = self.motion.iter_mut().find(|motion|!motion.is_used()) {
+                *target = source.clone();
+                copied += 1;
+            }
+        }
+        if copied > 0 {
+            if let (Some(target), Some(source)) = (self.parts.get_mut(part as usize), from.parts.get(part as usize)) {
+                target.motion_seq = source.motion_seq;
+            }
+        }
+        copied
+    }
+    /// Number of steps in the pattern, from its length in bars
+    pub fn steps (&self) -> usize {
+        (self.length.max(1) as usize * 16).min(64)
+    }
+    /// Create an empty pattern
+    pub fn init () -> Self {
+        Self {
+            name:   "Init Pattern".into(),
+            bpm:    120.0,
+            level:  100,
+            length: 1,
+            beats:  Beats::Sixteenth,
+            motion: vec![Electribe2Motion::default(); MOTION_SLOTS],
+            parts:  vec![Electribe2Part::init(); 16],
+            ..Self::default()
+        }
+    }
+    /// Whether the pattern is still an init pattern with no notes in it
+    pub fn is_init (&self) -> bool {
+        self.name.trim_end_matches(['\0', ' ']) == "Init Pattern" &&
+            self.parts.iter().all(|part|part.steps.iter().all(|step|step.notes().count() == 0))
+    }
+    /// Write the pattern into a 0x4000-byte block, on top of the original data if any
+    pub fn write (&self) -> Vec<u8> {
+        let mut raw = if self.raw.len() == PATTERN_SIZE {
+            self.raw.clone()
+        } else {
+            vec![0; PATTERN_SIZE]
+        };
+        raw[0x0000..0x0004].copy_from_slice(&PTST);
+        let mut name = [0u8; 16];
+        for (index, byte) in self.name.bytes().take(16).enumerate() {
+            name[index] = byte;
+        }
+        raw[0x0010..0x0020].copy_from_slice(&name);
+        let bpm = (self.bpm * 10.0).round() as u16;
+        raw[0x0022..0x0024].copy_from_slice(&bpm.to_le_bytes());
+        raw[0x0024] = self.swing;
+        raw[0x0025] = self.length;
+        raw[0x0026] = self.beats.into();
+        raw[0x0027] = self.key.into();
+        raw[0x0028] = self.scale.into();
+        raw[0x0029] = self.chord_set;
+        raw[0x002a] = 127u8.saturating_sub(self.level);
+        raw[0x0031] = self.gate_arp;
+        raw[0x003d] = self.mfx_type.into();
+        raw[0x0044] = self.alt_13_14;
+        raw[0x0045] = self.alt_15_16;
+        for (slot, motion) in self.motion.iter().enumerate().take(MOTION_SLOTS) {
+            motion.write(&mut raw, slot);
+        }
+        for (i, part) in self.parts.iter().enumerate().take(16) {
+            let start = PARTS_OFFSET + i * PART_SIZE;
+            let end   = start + PART_SIZE;
+            part.write(&mut raw[start..end]);
+        }
+        raw[0x3BFC..0x3C00].copy_from_slice(&PTED);
+        raw
+    }
+}
+
+/// Parameters that can be recorded into a motion sequence slot, by id,
+/// with the control change that they are exported as, if any.
+/// See docs/korg/electribe2_motion_format.md; the ids are unconfirmed.
+pub const MOTION_PARAMETERS: [(&str, Option<u8>); 24] = [
+    ("None",        None),
+    ("Oscillator",  None),
+    ("Osc Edit",    None),
+    ("Pitch",       None),
+    ("Glide",       Some(5)),
+    ("Filter Type", None),
+    ("Cutoff",      Some(74)),
+    ("Resonance",   Some(71)),
+    ("EG Int",      None),
+    ("Mod Type",    None),
+    ("Mod Speed",   None),
+    ("Mod Depth",   None),
+    ("Attack",      Some(73)),
+    ("Decay",       Some(72)),
+    ("Level",       Some(7)),
+    ("Pan",         Some(10)),
+    ("Amp EG",      None),
+    ("MFX Send",    None),
+    ("IFX On",      None),
+    ("IFX Type",    None),
+    ("IFX Edit",    None),
+    ("MFX X",       None),
+    ("MFX Y",       None),
+    ("MFX Hold",    None),
+];
+
+/// One of the 24 motion sequence slots of a pattern.
+///
+/// ```text
+/// 0x0100 + slot * 2       part (0 = unused, 1-16)
+/// 0x0101 + slot * 2       parameter id (see MOTION_PARAMETERS)
+/// 0x0130 + slot * 64      64 step values
+/// ```
+///
+/// This layout has not been confirmed against patterns saved on the device,
+/// see docs/korg/electribe2_motion_format.md.
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Electribe2Motion {
+    /// Part number, 1-based; 0 means the slot is unused
+    pub part:      u8,
+    /// Parameter id
+    #[serde(with = "motion_parameter")]
+    pub parameter: u8,
+    /// Parameter value at each step
+    pub steps:     Vec<u8>,
+}
+
+impl Electribe2Motion {
+    pub fn read (raw: &[u8], slot: usize) -> Self {
+        let start = MOTION_STEPS_OFFSET + slot * MOTION_STEPS;
+        Self {
+            part:      raw[MOTION_OFFSET + slot * 2],
+            parameter: raw[MOTION_OFFSET + slot * 2 + 1],
+            steps:     raw[start..start+MOTION_STEPS].to_vec(),
+        }
+    }
+    pub fn write (&self, raw: &mut [u8], slot: usize) {
+        raw[MOTION_OFFSET + slot * 2]     = self.part;
+        raw[MOTION_OFFSET + slot * 2 + 1] = self.parameter;
+        let start = MOTION_STEPS_OFFSET + slot * MOTION_STEPS;
+        for (index, value) in self.steps.iter().enumerate().take(MOTION_STEPS) {
+            raw[start + index] = *value;
+        }
+    }
+    /// Whether this slot holds a motion sequence
+    pub fn is_used (&self) -> bool {
+        self.part > 0 && self.parameter > 0
+    }
+    /// Name of the recorded parameter
+    pub fn parameter_name (&self) -> &'static str {
+        MOTION_PARAMETERS.get(self.parameter as usize).map(|x|x.0).unwrap_or("Unknown")
+    }
+    /// Control change that corresponds to the recorded parameter, if any
+    pub fn control (&self) -> Option<u8> {
+        MOTION_PARAMETERS.get(self.parameter as usize).and_then(|x|x.1)
+    }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Electribe2Part {
     /// 0x0000 - last step
     pub last_step:        u8,
     /// 0x0002 - voice assign
-    pub voice_assign:     u8,
+    pub voice_assign:     VoiceAssign,
     /// 0x0003 - part priority
     pub priority:         u8,
-    /// 0x0004 - motion sequence enable
-    pub motion_seq:       u8,
+    /// 0x0004 - motion sequence mode: 0 = off, 1 = smooth, 2 = trigger hold
+    pub motion_seq:       MotionSeqMode,
     /// 0x0005 - velocity curve
     pub trigger_vel:      u8,
     /// 0x0006 - scale mode
     pub scale:            u8,
     /// 0x0008..0x0009 - oscilator/sample number
-    pub sample:           u16,
+    pub sample:           Oscillator,
     /// 0x000b - osc edit
     pub osc:              u8,
     /// 0x000c - filter type
-    pub filter_type:      u8,
+    pub filter_type:      Filter,
     /// 0x000d - filter cutoff
     pub filter_cutoff:    u8,
     /// 0x000e - filter resonance
@@ -123,7 +382,7 @@ pub struct Electribe2Part {
     /// 0x000f - filter envelope
     pub filter_envelope:  u8,
     /// 0x0010 - modulation type
-    pub modulation_type:  u8,
+    pub modulation_type:  Modulation,
     /// 0x0011 - modulation speed
     pub modulation_speed: u8,
     /// 0x0012 - modulation depth
@@ -141,39 +400,38 @@ pub struct Electribe2Part {
     /// 0x001b - route to master effect?
     pub mfx_on:           u8,
     /// 0x001c - groove type
-    pub groove_type:      u8,
+    pub groove_type:      Groove,
     /// 0x001d - amount of groove to apply
     pub groove_depth:     u8,
     /// 0x0020 - insert effect toggle
     pub ifx_on:           u8,
     /// 0x0021 - insert effect type
-    pub ifx_type:         u8,
+    pub ifx_type:         InsertFx,
     /// 0x0022 - insert effect parameter
     pub ifx_param:        u8,
     /// 0x0024 - oscillator tuning
     pub pitch:            u8,
     /// 0x0025 - portamento
     pub glide:            u8,
-    /// 64 steps of 12 bytes each
+    /// 0x0030..0x0330 - 64 steps of 12 bytes each
     pub steps:            Vec<Electribe2Step>
 }
 
 impl Electribe2Part {
     pub fn read (raw: &[u8]) -> Self {
-        let mut part = Self::default();
-        part.last_step        = raw[0x00];
-        part.voice_assign     = raw[0x02];
+        let mut part = Self { last_step: raw[0x00], ..Self::default() };
+        part.voice_assign     = raw[0x02].into();
         part.priority         = raw[0x03];
-        part.motion_seq       = raw[0x04];
+        part.motion_seq       = raw[0x04].into();
         part.trigger_vel      = raw[0x05];
         part.scale            = raw[0x06];
-        part.sample           = (raw[0x08] as u16) + (0x100u16 * raw[0x09] as u16);
+        part.sample           = Oscillator(u16::from_le_bytes([raw[0x08], raw[0x09]]));
         part.osc              = raw[0x0b];
-        part.filter_type      = raw[0x0c];
+        part.filter_type      = raw[0x0c].into();
         part.filter_cutoff    = raw[0x0d];
         part.filter_resonance = raw[0x0e];
         part.filter_envelope  = raw[0x0f];
-        part.modulation_type  = raw[0x10];
+        part.modulation_type  = Modulation(raw[0x10]);
         part.modulation_speed = raw[0x11];
         part.modulation_depth = raw[0x12];
         part.attack           = raw[0x14];
@@ -182,13 +440,13 @@ impl Electribe2Part {
         part.pan              = raw[0x19];
         part.amp_eg           = raw[0x1a];
         part.mfx_on           = raw[0x1b];
-        part.groove_type      = raw[0x1c];
+        part.groove_type      = Groove(raw[0x1c]);
         part.groove_depth     = raw[0x1d];
         part.ifx_on           = raw[0x20];
-        part.ifx_type         = raw[0x21];
+        part.ifx_type         = raw[0x21].into();
         part.ifx_param        = raw[0x22];
-        part.pitch            = raw[0x23];
-        part.glide            = raw[0x24];
+        part.pitch            = raw[0x24];
+        part.glide            = raw[0x25];
         for index in 0..64 {
             let start = STEPS_OFFSET + index * STEP_SIZE;
             let end   = start + STEP_SIZE;
@@ -196,11 +454,57 @@ impl Electribe2Part {
         }
         part
     }
+    /// Create an empty part
+    pub fn init () -> Self {
+        Self {
+            last_step: 16,
+            level:     100,
+            pan:       64,
+            steps:     vec![Electribe2Step::default(); 64],
+            ..Self::default()
+        }
+    }
+    /// Write the part into a 0x330-byte slice of a pattern block
+    pub fn write (&self, raw: &mut [u8]) {
+        raw[0x00] = self.last_step;
+        raw[0x02] = self.voice_assign.into();
+        raw[0x03] = self.priority;
+        raw[0x04] = self.motion_seq.into();
+        raw[0x05] = self.trigger_vel;
+        raw[0x06] = self.scale;
+        raw[0x08..0x0a].copy_from_slice(&self.sample.0.to_le_bytes());
+        raw[0x0b] = self.osc;
+        raw[0x0c] = self.filter_type.into();
+        raw[0x0d] = self.filter_cutoff;
+        raw[0x0e] = self.filter_resonance;
+        raw[0x0f] = self.filter_envelope;
+        raw[0x10] = self.modulation_type.0;
+        raw[0x11] = self.modulation_speed;
+        raw[0x12] = self.modulation_depth;
+        raw[0x14] = self.attack;
+        raw[0x15] = self.decay;
+        raw[0x18] = self.level;
+        raw[0x19] = self.pan;
+        raw[0x1a] = self.amp_eg;
+        raw[0x1b] = self.mfx_on;
+        raw[0x1c] = self.groove_type.0;
+        raw[0x1d] = self.groove_depth;
+        raw[0x20] = self.ifx_on;
+        raw[0x21] = self.ifx_type.into();
+        raw[0x22] = self.ifx_param;
+        raw[0x24] = self.pitch;
+        raw[0x25] = self.glide;
+        for (index, step) in self.steps.iter().enumerate().take(64) {
+            let start = STEPS_OFFSET + index * STEP_SIZE;
+            let end   = start + STEP_SIZE;
+            step.write(&mut raw[start..end]);
+        }
+    }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Electribe2Step {
-    /// 0x00
+    /// 0x00 - 1 if the step has notes (named HasNotes in the forum thread, meaning unconfirmed)
     pub empty:    u8,
     /// 0x01
     pub gate:     u8,
@@ -208,7 +512,7 @@ pub struct Electribe2Step {
     pub velocity: u8,
     /// 0x03
     pub chord:    u8,
-    /// 0x04
+    /// 0x04 - notes are stored as MIDI note number + 1, 0 meaning no note
     pub note_1:   u8,
     /// 0x05
     pub note_2:   u8,
@@ -220,8 +524,7 @@ pub struct Electribe2Step {
 
 impl Electribe2Step {
     pub fn read (raw: &[u8]) -> Self {
-        let mut step = Self::default();
-        step.empty    = raw[0x00];
+        let mut step = Self { empty: raw[0x00], ..Self::default() };
         step.gate     = raw[0x01];
         step.velocity = raw[0x02];
         step.chord    = raw[0x03];
@@ -231,5 +534,311 @@ impl Electribe2Step {
         step.note_4   = raw[0x07];
         step
     }
+    /// MIDI note numbers of the notes on this step
+    pub fn notes (&self) -> impl Iterator<Item = u8> {
+        [self.note_1, self.note_2, self.note_3, self.note_4].into_iter()
+            .filter(|note|*note > 0)
+            .map(|note|note - 1)
+    }
+    /// Write the step into a 12-byte slice of a part
+    pub fn write (&self, raw: &mut [u8]) {
+        raw[0x00] = self.empty;
+        raw[0x01] = self.gate;
+        raw[0x02] = self.velocity;
+        raw[0x03] = self.chord;
+        raw[0x04] = self.note_1;
+        raw[0x05] = self.note_2;
+        raw[0x06] = self.note_3;
+        raw[0x07] = self.note_4;
+    }
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Build a pattern block with distinct values at the offsets that the parser reads.
+    /// This is synthetic data, not a dump from the device: it checks that fields land
+    /// where the parser and the writer expect them, not that those offsets are right.
+    fn known_pattern () -> Vec<u8> {
+        let mut raw = vec![0; PATTERN_SIZE];
+        raw[0x0000..0x0004].copy_from_slice(&PTST);
+        raw[0x0010..0x001b].copy_from_slice(b"Hello World");
+        raw[0x0022..0x0024].copy_from_slice(&1325u16.to_le_bytes());
+        raw[0x0024] = 12;
+        raw[0x0025] = 4;
+        raw[0x0026] = 2;
+        raw[0x0027] = 3;
+        raw[0x0028] = 7;
+        raw[0x0029] = 1;
+        raw[0x002a] = 27;
+        raw[0x0031] = 80;
+        raw[0x003d] = 5;
+        raw[0x0044] = 1;
+        raw[0x0045] = 2;
+        let part = PARTS_OFFSET + 2 * PART_SIZE;
+        raw[part] = 16;
+        raw[part + 0x08..part + 0x0a].copy_from_slice(&520u16.to_le_bytes());
+        raw[part + 0x0c] = 3;
+        raw[part + 0x0d] = 90;
+        raw[part + 0x21] = 17;
+        raw[part + 0x23] = 0xEE;
+        raw[part + 0x24] = 64;
+        raw[part + 0x25] = 10;
+        let step = part + STEPS_OFFSET + 4 * STEP_SIZE;
+        raw[step] = 1;
+        raw[step + 0x01] = 72;
+        raw[step + 0x02] = 100;
+        raw[step + 0x04] = 61;
+        raw[step + 0x05] = 65;
+        raw[0x3BFC..0x3C00].copy_from_slice(&PTED);
+        raw
+    }
+
+    fn known_bank () -> Vec<u8> {
+        let mut raw = vec![0; PATTERNS_OFFSET];
+        raw[0x0000..0x0004].copy_from_slice(b"KORG");
+        for _ in 0..PATTERN_COUNT {
+            raw.extend_from_slice(&known_pattern());
+        }
+        raw
+    }
+
+    #[test]
+    fn test_read_pattern () {
+        let pattern = Electribe2Pattern::read(&known_pattern());
+        assert_eq!(pattern.name,      "Hello World");
+        assert_eq!(pattern.bpm,       132.5);
+        assert_eq!(pattern.swing,     12);
+        assert_eq!(pattern.length,    4);
+        assert_eq!(pattern.beats,     Beats::EighthTriplet);
+        assert_eq!(pattern.key,       Key::DSharp);
+        assert_eq!(pattern.scale,     Scale::Locrian);
+        assert_eq!(pattern.chord_set, 1);
+        assert_eq!(pattern.level,     100);
+        assert_eq!(pattern.gate_arp,  80);
+        assert_eq!(pattern.mfx_type,  MasterFx::Decimator);
+        assert_eq!(pattern.alt_13_14, 1);
+        assert_eq!(pattern.alt_15_16, 2);
+        assert_eq!(pattern.parts.len(), 16);
+        let part = &pattern.parts[2];
+        assert_eq!(part.last_step,     16);
+        assert_eq!(part.sample,        Oscillator(520));
+        assert_eq!(part.filter_type,   Filter::P5LowPass);
+        assert_eq!(part.filter_cutoff, 90);
+        assert_eq!(part.ifx_type,      InsertFx::TalkingModulator);
+        assert_eq!(part.pitch,         64);
+        assert_eq!(part.glide,         10);
+        assert_eq!(part.steps.len(),   64);
+        let step = &part.steps[4];
+        assert_eq!(step.empty,    1);
+        assert_eq!(step.gate,     72);
+        assert_eq!(step.velocity, 100);
+        assert_eq!(step.note_1,   61);
+        assert_eq!(step.note_2,   65);
+        assert_eq!(part.steps[3].note_1, 0);
+    }
+
+    #[test]
+    fn test_read_bank () {
+        let bank = Electribe2PatternBank::read(&known_bank());
+        assert_eq!(bank.patterns.len(), PATTERN_COUNT);
+        assert_eq!(&bank.header[0..4], b"KORG");
+        assert_eq!(bank.patterns[249].name, "Hello World");
+        assert_eq!(bank.patterns[249].parts[2].steps[4].note_1, 61);
+    }
+
+    #[test]
+    fn test_write_bank () {
+        let raw = known_bank();
+        let mut bank = Electribe2PatternBank::read(&raw);
+        assert_eq!(bank.write(), raw);
+        bank.patterns[0].name  = "Renamed".into();
+        bank.patterns[0].bpm   = 90.0;
+        bank.patterns[0].level = 64;
+        bank.patterns[0].parts[2].pitch = 70;
+        let bank = Electribe2PatternBank::read(&bank.write());
+        assert_eq!(bank.patterns[0].name,  "Renamed");
+        assert_eq!(bank.patterns[0].bpm,   90.0);
+        assert_eq!(bank.patterns[0].level, 64);
+        assert_eq!(bank.patterns[0].parts[2].pitch, 70);
+        assert_eq!(bank.patterns[0].parts[2].glide, 10);
+        // Unknown bytes are preserved
+        assert_eq!(bank.patterns[0].raw[PARTS_OFFSET + 2 * PART_SIZE + 0x23], 0xEE);
+    }
+
+    #[test]
+    fn test_init_pattern () {
+        // A new pattern on the device plays 16th notes, which the beat byte stores as 0
+        let raw = Electribe2Pattern::init().write();
+        assert_eq!(raw[0x0026], 0);
+        assert_eq!(Electribe2Pattern::read(&raw).beats, Beats::Sixteenth);
+        assert!(Electribe2Pattern::read(&raw).is_init());
+    }
+
+
+    /// A pattern with a cutoff motion sequence for the third part in slot 3
+    fn motion_pattern () -> Vec<u8> {
+        let mut raw = known_pattern();
+        raw[MOTION_OFFSET + 3 * 2]     = 3;
+        raw[MOTION_OFFSET + 3 * 2 + 1] = 6;
+        for step in 0..MOTION_STEPS {
+            raw[MOTION_STEPS_OFFSET + 3 * MOTION_STEPS + step] = step as u8 * 2;
+        }
+        raw[PARTS_OFFSET + 2 * PART_SIZE + 0x04] = 2;
+        raw
+    }
+
+    #[test]
+    fn test_read_motion () {
+        let raw = motion_pattern();
+        let pattern = Electribe2Pattern::read(&raw);
+        assert_eq!(pattern.motion.len(), MOTION_SLOTS);
+        let slots = pattern.motion_slots().map(|(slot, _)|slot).collect::<Vec<_>>();
+        assert_eq!(slots, vec![3]);
+        let motion = &pattern.motion[3];
+        assert_eq!(motion.part, 3);
+        assert_eq!(motion.parameter_name(), "Cutoff");
+        assert_eq!(motion.control(), Some(74));
+        assert_eq!(motion.steps.len(), MOTION_STEPS);
+        assert_eq!(motion.steps[63], 126);
+        assert_eq!(pattern.parts[2].motion_seq, MotionSeqMode::TriggerHold);
+        assert_eq!(pattern.write(), raw);
+    }
+
+    #[test]
+    fn test_copy_motion () {
+        let mut source = Electribe2Pattern::read(&motion_pattern());
+        source.motion[7] = Electribe2Motion { part: 3, parameter: 15, steps: vec![64; MOTION_STEPS] };
+        source.motion[8] = Electribe2Motion { part: 1, parameter: 14, steps: vec![1; MOTION_STEPS] };
+        let mut target = Electribe2Pattern::init();
+        target.motion[0] = Electribe2Motion { part: 3, parameter: 7, steps: vec![9; MOTION_STEPS] };
+        target.motion[1] = Electribe2Motion { part: 5, parameter: 7, steps: vec![9; MOTION_STEPS] };
+        assert_eq!(target.copy_motion(&source, 2), 2);
+        // The target's own sequences for the part are replaced, those of other parts are kept
+        assert_eq!(target.motion[0], source.motion[3]);
+        assert_eq!(target.motion[1].part, 5);
+        assert_eq!(target.motion[2], source.motion[7]);
+        assert_eq!(target.motion_slots().count(), 3);
+        assert_eq!(target.parts[2].motion_seq, MotionSeqMode::TriggerHold);
+        // Copying a part without motion sequences clears the target's
+        assert_eq!(target.copy_motion(&Electribe2Pattern::init(), 4), 0);
+        assert_eq!(target.motion_slots().count(), 2);
+        assert_eq!(target.parts[4].motion_seq, MotionSeqMode::Off);
+    }
+
+    #[test]
+    fn test_motion_to_midi () {
+        let mut pattern = Electribe2Pattern::init();
+        let mut steps = vec![0; MOTION_STEPS];
+        steps[4] = 64;
+        // Past the length of the pattern
+        steps[20] = 100;
+        pattern.motion[0] = Electribe2Motion { part: 2, parameter: 6, steps };
+        // Oscillator changes have no control change, so they are not exported
+        pattern.motion[1] = Electribe2Motion { part: 2, parameter: 1, steps: vec![5; MOTION_STEPS] };
+        pattern.parts[1].motion_seq = MotionSeqMode::TriggerHold;
+        let smf = pattern.motion_to_midi();
+        assert_eq!(smf.tracks.len(), 2);
+        let control = |value|crate::midi::Event::Control { channel: 1, control: 74, value };
+        assert_eq!(&smf.tracks[1].events[1..], &[
+            (0, control(0)),
+            (4 * MIDI_STEP, control(64)),
+            (5 * MIDI_STEP, control(0)),
+        ]);
+        // In smooth mode, values are interpolated between steps
+        pattern.motion[0].steps = vec![40; MOTION_STEPS];
+        pattern.motion[0].steps[0] = 0;
+        pattern.parts[1].motion_seq = MotionSeqMode::Smooth;
+        let smf = pattern.motion_to_midi();
+        assert_eq!(&smf.tracks[1].events[1..], &[
+            (0, control(0)),
+            (MIDI_STEP / 4, control(10)),
+            (MIDI_STEP / 2, control(20)),
+            (MIDI_STEP * 3 / 4, control(30)),
+            (MIDI_STEP, control(40)),
+        ]);
+    }
+
+
+    #[test]
+    fn test_pattern_file () {
+        let bank = Electribe2PatternBank::read(&known_bank());
+        let mut pattern = bank.patterns[7].clone();
+        pattern.name = "Single".into();
+        let raw = pattern.write_file(&bank.header);
+        assert_eq!(raw.len(), PATTERN_FILE_SIZE);
+        assert_eq!(&raw[..PATTERN_FILE_HEADER], &bank.header[..PATTERN_FILE_HEADER]);
+        let read = Electribe2Pattern::read_file(&raw).unwrap();
+        assert_eq!(read.name, "Single");
+        assert_eq!(read.bpm, 132.5);
+        assert_eq!(read.parts[2].steps[4].note_1, 61);
+        assert_eq!(read.write_file(&bank.header), raw);
+        // A short header is padded
+        assert_eq!(&pattern.write_file(b"KORG")[..8], b"KORG\0\0\0\0");
+    }
+
+    #[test]
+    fn test_pattern_file_errors () {
+        let raw = Electribe2Pattern::init().write_file(&[]);
+        assert!(Electribe2Pattern::read_file(&raw[..PATTERN_FILE_SIZE - 1]).is_err());
+        assert!(Electribe2Pattern::read_file(&raw[PATTERN_FILE_HEADER..]).is_err());
+        assert!(Electribe2Pattern::read_file(&raw).unwrap().is_init());
+    }
+
+
+    /// An .e2pat file built from the offsets that the forum thread saved as
+    /// docs/korg/viewtopic.php?t=95368 gives, which count from the start of the file.
+    /// Still not a dump from the device, but a check against a source other than this
+    /// module: globals at 0x110-0x145, parts of 0x330 bytes from 0x900, their steps of
+    /// 12 bytes from 0x30 into the part, and notes stored as the note number + 1.
+    #[test]
+    fn test_forum_offsets () {
+        let mut raw = Electribe2Pattern::init().write_file(&[]);
+        raw[0x110..0x120].copy_from_slice(b"Forum 01\0\0\0\0\0\0\0\0");
+        raw[0x122..0x124].copy_from_slice(&1400u16.to_le_bytes());
+        raw[0x124] = 20;
+        raw[0x125] = 2;
+        raw[0x127] = 9;
+        raw[0x128] = 15;
+        raw[0x12a] = 27;
+        raw[0x13d] = 11;
+        let part = 0x900 + 5 * 0x330;
+        raw[part + 0x04] = 1;
+        raw[part + 0x08..part + 0x0a].copy_from_slice(&300u16.to_le_bytes());
+        raw[part + 0x0d] = 33;
+        raw[part + 0x18] = 90;
+        raw[part + 0x19] = 40;
+        raw[part + 0x21] = 6;
+        raw[part + 0x24] = 70;
+        let step = part + 0x30 + 9 * 12;
+        raw[step] = 1;
+        raw[step + 0x01] = 48;
+        raw[step + 0x02] = 110;
+        raw[step + 0x04] = 37;
+        let pattern = Electribe2Pattern::read_file(&raw).unwrap();
+        assert_eq!(pattern.name,     "Forum 01");
+        assert_eq!(pattern.bpm,      140.0);
+        assert_eq!(pattern.swing,    20);
+        assert_eq!(pattern.length,   2);
+        assert_eq!(pattern.key,      Key::A);
+        assert_eq!(pattern.scale,    Scale::MinorPentatonic);
+        assert_eq!(pattern.level,    100);
+        assert_eq!(pattern.mfx_type, MasterFx::RoomReverb);
+        let part = &pattern.parts[5];
+        assert_eq!(part.motion_seq,    MotionSeqMode::Smooth);
+        assert_eq!(part.sample,        Oscillator(300));
+        assert_eq!(part.filter_cutoff, 33);
+        assert_eq!(part.level,         90);
+        assert_eq!(part.pan,           40);
+        assert_eq!(part.ifx_type,      InsertFx::Delay);
+        assert_eq!(part.pitch,         70);
+        let step = &part.steps[9];
+        assert_eq!(step.gate,     48);
+        assert_eq!(step.velocity, 110);
+        assert_eq!(step.notes().collect::<Vec<_>>(), vec![36]);
+        assert_eq!(pattern.write_file(&raw), raw);
+    }
+
+}
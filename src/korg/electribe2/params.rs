@@ -0,0 +1,454 @@
+//! Typed parameter values, with display names from the parameter guide.
+//! Each type parses names (ignoring case) or plain numbers, and serializes
+//! to a name where one is known and to a plain number otherwise.
+//!
+//! The effect and filter names are best-effort transcriptions of the
+//! parameter guide, which is not part of this repository, and have not been
+//! checked against it yet; values past the end of a list are kept as `Other`.
+
+use super::*;
+
+/// Find a value by its name (ignoring case) or number
+pub fn value (table: &[&str], name: &str) -> Option<u8> {
+    table.iter().position(|x|x.eq_ignore_ascii_case(name.trim()))
+        .map(|index|index as u8)
+        .or_else(||name.trim().parse().ok())
+}
+
+/// A value as written to text: a name, or a number that has no name
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum Named {
+    Number(u8),
+    Name(String),
+}
+
+impl Named {
+    pub fn from (table: &[&str], value: u8) -> Self {
+        match table.get(value as usize) {
+            Some(name) => Self::Name(name.to_string()),
+            None => Self::Number(value)
+        }
+    }
+}
+
+/// Define an enum for a parameter byte, with one named variant per value
+/// starting from 0, and `Other` for the values without a name.
+macro_rules! parameter {
+    ($(#[$meta:meta])* $Type:ident { $($Variant:ident = $name:literal,)* }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum $Type {
+            $($Variant,)*
+            /// A value without a known name
+            Other(u8),
+        }
+
+        impl $Type {
+            /// The named values, in order
+            pub const VALUES: &'static [Self] = &[$(Self::$Variant),*];
+            /// The names of the values, in order
+            pub const NAMES: &'static [&'static str] = &[$($name),*];
+            pub fn from_u8 (value: u8) -> Self {
+                Self::VALUES.get(value as usize).copied().unwrap_or(Self::Other(value))
+            }
+            pub fn to_u8 (&self) -> u8 {
+                match self {
+                    Self::Other(value) => *value,
+                    named => Self::VALUES.iter().position(|x|x == named).unwrap() as u8
+                }
+            }
+            /// Display name of the value, if it has one
+            pub fn name (&self) -> Option<&'static str> {
+                Self::NAMES.get(self.to_u8() as usize).copied()
+            }
+        }
+
+        impl Default for $Type {
+            fn default () -> Self {
+                Self::from_u8(0)
+            }
+        }
+
+        impl From<u8> for $Type {
+            fn from (value: u8) -> Self {
+                Self::from_u8(value)
+            }
+        }
+
+        impl From<$Type> for u8 {
+            fn from (value: $Type) -> Self {
+                value.to_u8()
+            }
+        }
+
+        impl std::fmt::Display for $Type {
+            fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                match self.name() {
+                    Some(name) => write!(f, "{name}"),
+                    None => write!(f, "{}", self.to_u8())
+                }
+            }
+        }
+
+        impl std::str::FromStr for $Type {
+            type Err = String;
+            fn from_str (text: &str) -> Result<Self, Self::Err> {
+                value(Self::NAMES, text)
+                    .map(Self::from_u8)
+                    .ok_or_else(||format!("unknown {}: {text}", stringify!($Type)))
+            }
+        }
+
+        impl serde::Serialize for $Type {
+            fn serialize <S: serde::Serializer> (&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serde::Serialize::serialize(&Named::from(Self::NAMES, self.to_u8()), serializer)
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $Type {
+            fn deserialize <D: serde::Deserializer<'de>> (deserializer: D) -> Result<Self, D::Error> {
+                match <Named as serde::Deserialize>::deserialize(deserializer)? {
+                    Named::Number(number) => Ok(Self::from_u8(number)),
+                    Named::Name(name) => name.parse().map_err(serde::de::Error::custom)
+                }
+            }
+        }
+    }
+}
+
+parameter!(
+    /// Pattern key
+    Key {
+        C      = "C",
+        CSharp = "C#",
+        D      = "D",
+        DSharp = "D#",
+        E      = "E",
+        F      = "F",
+        FSharp = "F#",
+        G      = "G",
+        GSharp = "G#",
+        A      = "A",
+        ASharp = "A#",
+        B      = "B",
+    }
+);
+
+/// Name of a MIDI note, with middle C (60) as C4
+pub fn note_name (note: u8) -> String {
+    format!("{}{}", Key::from_u8(note % 12), note as i32 / 12 - 1)
+}
+
+parameter!(
+    /// Pattern scale
+    Scale {
+        Chromatic             = "Chromatic",
+        Ionian                = "Ionian",
+        Dorian                = "Dorian",
+        Phrygian              = "Phrygian",
+        Lydian                = "Lydian",
+        Mixolydian            = "Mixolydian",
+        Aeolian               = "Aeolian",
+        Locrian               = "Locrian",
+        HarmonicMinor         = "Harmonic Minor",
+        MelodicMinor          = "Melodic Minor",
+        MajorBlues            = "Major Blues",
+        MinorBlues            = "Minor Blues",
+        Diminished            = "Diminished",
+        CombinationDiminished = "Combination Diminished",
+        MajorPentatonic       = "Major Pentatonic",
+        MinorPentatonic       = "Minor Pentatonic",
+        RagaBhairav           = "Raga Bhairav",
+        RagaGamanasrama       = "Raga Gamanasrama",
+        RagaTodi              = "Raga Todi",
+        Spanish               = "Spanish",
+        Gypsy                 = "Gypsy",
+        Arabian               = "Arabian",
+        Egyptian              = "Egyptian",
+        Hawaiian              = "Hawaiian",
+        BaliIslandPelog       = "Bali Island Pelog",
+        JapaneseMiyakobushi   = "Japanese Miyakobushi",
+        Ryukyu                = "Ryukyu",
+        Wholetone             = "Wholetone",
+        MinorThirdInterval    = "Minor 3rd Interval",
+        ThirdInterval         = "3rd Interval",
+        FourthInterval        = "4th Interval",
+        FifthInterval         = "5th Interval",
+        OctaveInterval        = "Octave Interval",
+    }
+);
+
+parameter!(
+    /// Length of a step
+    Beats {
+        Sixteenth        = "16th",
+        ThirtySecond     = "32nd",
+        EighthTriplet    = "8th Triplet",
+        SixteenthTriplet = "16th Triplet",
+    }
+);
+
+parameter!(
+    /// Part voice assignment
+    VoiceAssign {
+        Mono1 = "Mono 1",
+        Mono2 = "Mono 2",
+        Poly1 = "Poly 1",
+        Poly2 = "Poly 2",
+    }
+);
+
+parameter!(
+    /// Part motion sequence mode
+    MotionSeqMode {
+        Off         = "Off",
+        Smooth      = "Smooth",
+        TriggerHold = "Trigger Hold",
+    }
+);
+
+parameter!(
+    /// Part filter type
+    Filter {
+        LowPass12    = "LPF 12dB",
+        LowPass24    = "LPF 24dB",
+        Ms20LowPass  = "MS-20 LPF",
+        P5LowPass    = "P5 LPF",
+        ObLowPass    = "OB LPF",
+        MgLowPass    = "Mg LPF",
+        AcidLowPass  = "Acid LPF",
+        HighPass12   = "HPF 12dB",
+        HighPass24   = "HPF 24dB",
+        Ms20HighPass = "MS-20 HPF",
+        P5HighPass   = "P5 HPF",
+        ObHighPass   = "OB HPF",
+        BandPass     = "BPF",
+        Ms20BandPass = "MS-20 BPF",
+        ObBandPass   = "OB BPF",
+        Comb         = "Comb",
+    }
+);
+
+parameter!(
+    /// Insert effect type
+    InsertFx {
+        Compressor              = "Compressor",
+        Filter                  = "Filter",
+        FilterBpmSync           = "Filter BPM Sync",
+        Isolator                = "Isolator",
+        Distortion              = "Distortion",
+        Decimator               = "Decimator",
+        Delay                   = "Delay",
+        DelayBpmSync            = "Delay BPM Sync",
+        TapeDelay               = "Tape Delay",
+        TapeDelayBpmSync        = "Tape Delay BPM Sync",
+        Reverb                  = "Reverb",
+        Chorus                  = "Chorus",
+        Flanger                 = "Flanger",
+        FlangerBpmSync          = "Flanger BPM Sync",
+        Phaser                  = "Phaser",
+        PhaserBpmSync           = "Phaser BPM Sync",
+        RingModulator           = "Ring Modulator",
+        TalkingModulator        = "Talking Modulator",
+        GrainShifter            = "Grain Shifter",
+        PitchShifter            = "Pitch Shifter",
+        AutoPan                 = "Auto Pan",
+        AutoPanBpmSync          = "Auto Pan BPM Sync",
+        Tremolo                 = "Tremolo",
+        TremoloBpmSync          = "Tremolo BPM Sync",
+        LowBoost                = "Low Boost",
+        MidBoost                = "Mid Boost",
+        HighBoost               = "High Boost",
+        LoFi                    = "Lo-Fi",
+        Looper                  = "Looper",
+        LooperBpmSync           = "Looper BPM Sync",
+        VinylBreak              = "Vinyl Break",
+        SeqReverse              = "Seq Reverse",
+        OddStepper              = "Odd Stepper",
+        StepperBpmSync          = "Stepper BPM Sync",
+        ModulationFilter        = "Modulation Filter",
+        ModulationFilterBpmSync = "Modulation Filter BPM Sync",
+        Exciter                 = "Exciter",
+        Limiter                 = "Limiter",
+    }
+);
+
+parameter!(
+    /// Master effect type
+    MasterFx {
+        StereoCompressor   = "Stereo Compressor",
+        Filter             = "Filter",
+        FilterBpmSync      = "Filter BPM Sync",
+        Isolator           = "Isolator",
+        Distortion         = "Distortion",
+        Decimator          = "Decimator",
+        StereoDelay        = "Stereo Delay",
+        StereoDelayBpmSync = "Stereo Delay BPM Sync",
+        TapeDelay          = "Tape Delay",
+        TapeDelayBpmSync   = "Tape Delay BPM Sync",
+        HallReverb         = "Hall Reverb",
+        RoomReverb         = "Room Reverb",
+        SpringReverb       = "Spring Reverb",
+        Chorus             = "Chorus",
+        Ensemble           = "Ensemble",
+        Flanger            = "Flanger",
+        FlangerBpmSync     = "Flanger BPM Sync",
+        Phaser             = "Phaser",
+        PhaserBpmSync      = "Phaser BPM Sync",
+        RingModulator      = "Ring Modulator",
+        TalkingModulator   = "Talking Modulator",
+        GrainShifter       = "Grain Shifter",
+        PitchShifter       = "Pitch Shifter",
+        AutoPan            = "Auto Pan",
+        AutoPanBpmSync     = "Auto Pan BPM Sync",
+        LoFi               = "Lo-Fi",
+        Looper             = "Looper",
+        LooperBpmSync      = "Looper BPM Sync",
+        VinylBreak         = "Vinyl Break",
+        SeqReverse         = "Seq Reverse",
+        OddStepper         = "Odd Stepper",
+        Limiter            = "Limiter",
+    }
+);
+
+/// Oscillator or sample played by a part, stored zero-based like the esli chunk does.
+/// Numbers up to 500 are factory oscillators, from 501 on they are user samples.
+/// The factory oscillator names are not tabulated here, as the parameter guide that
+/// lists them is not part of this repository: the factory sample bank of the device
+/// names them, see `Electribe2SampleBank::describe`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct Oscillator(pub u16);
+
+impl Oscillator {
+    /// Oscillator or sample with the number displayed on the device
+    pub fn from_number (number: u16) -> Self {
+        Self(number.saturating_sub(1))
+    }
+    /// Number displayed on the device
+    pub fn number (&self) -> u16 {
+        self.0 + 1
+    }
+    /// Whether this is a user sample rather than a factory oscillator
+    pub fn is_user_sample (&self) -> bool {
+        self.number() >= USER_SAMPLES_START
+    }
+}
+
+impl std::fmt::Display for Oscillator {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.is_user_sample() {
+            write!(f, "User {}", self.number())
+        } else {
+            write!(f, "Osc {}", self.number())
+        }
+    }
+}
+
+/// Number of modulation types
+pub const MODULATION_TYPES: u8 = 72;
+
+/// Number of groove types
+pub const GROOVE_TYPES: u8 = 25;
+
+/// Part modulation type, displayed by number until the 72 names of the parameter guide
+/// are tabulated
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct Modulation(pub u8);
+
+impl std::fmt::Display for Modulation {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Mod {}", self.0 + 1)
+    }
+}
+
+/// Part groove type, displayed by number until the 25 names of the parameter guide
+/// are tabulated
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct Groove(pub u8);
+
+impl std::fmt::Display for Groove {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Groove {}", self.0 + 1)
+    }
+}
+
+/// Motion sequence parameter names, from MOTION_PARAMETERS
+pub mod motion_parameter {
+    use super::Named;
+    fn table () -> Vec<&'static str> {
+        super::super::MOTION_PARAMETERS.iter().map(|(name, _)|*name).collect()
+    }
+    pub fn serialize <S: serde::Serializer> (value: &u8, serializer: S) -> Result<S::Ok, S::Error> {
+        serde::Serialize::serialize(&Named::from(&table(), *value), serializer)
+    }
+    pub fn deserialize <'de, D: serde::Deserializer<'de>> (deserializer: D) -> Result<u8, D::Error> {
+        let named: Named = serde::Deserialize::deserialize(deserializer)?;
+        match named {
+            Named::Number(number) => Ok(number),
+            Named::Name(name) => super::value(&table(), &name)
+                .ok_or_else(||serde::de::Error::custom("unknown motion parameter"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_names_and_numbers () {
+        assert_eq!("c#".parse::<Key>(), Ok(Key::CSharp));
+        assert_eq!(" Harmonic minor ".parse::<Scale>(), Ok(Scale::HarmonicMinor));
+        assert_eq!("3".parse::<Beats>(), Ok(Beats::SixteenthTriplet));
+        assert_eq!("ms-20 lpf".parse::<Filter>(), Ok(Filter::Ms20LowPass));
+        assert_eq!("Lo-Fi".parse::<InsertFx>(), Ok(InsertFx::LoFi));
+        assert_eq!("Limiter".parse::<MasterFx>(), Ok(MasterFx::Limiter));
+        // Numbers past the end of a table are kept as they are
+        assert_eq!("200".parse::<Filter>(), Ok(Filter::Other(200)));
+        assert!("Mono 3".parse::<VoiceAssign>().is_err());
+        assert!("".parse::<Scale>().is_err());
+    }
+
+    #[test]
+    fn value_names () {
+        for (index, name) in Scale::NAMES.iter().enumerate() {
+            let scale = Scale::from_u8(index as u8);
+            assert_eq!(scale.to_u8(), index as u8);
+            assert_eq!(scale.to_string(), *name);
+            assert_eq!(name.parse::<Scale>(), Ok(scale));
+        }
+        assert_eq!(MotionSeqMode::from_u8(2).name(), Some("Trigger Hold"));
+        assert_eq!(InsertFx::from_u8(200).name(), None);
+        assert_eq!(InsertFx::from_u8(200).to_string(), "200");
+        assert_eq!(u8::from(MasterFx::Other(99)), 99);
+        assert_eq!(note_name(60), "C4");
+        assert_eq!(note_name(0), "C-1");
+        assert_eq!(note_name(70), "A#4");
+    }
+
+    #[test]
+    fn serialize_named () {
+        assert_eq!(serde_json::to_string(&Key::FSharp).unwrap(), r#""F#""#);
+        assert_eq!(serde_json::to_string(&Filter::Other(40)).unwrap(), "40");
+        assert_eq!(serde_json::from_str::<Key>(r#""f#""#).unwrap(), Key::FSharp);
+        assert_eq!(serde_json::from_str::<Key>("6").unwrap(), Key::FSharp);
+        assert_eq!(serde_json::from_str::<Filter>("40").unwrap(), Filter::Other(40));
+        assert!(serde_json::from_str::<Key>(r#""H""#).is_err());
+    }
+
+    #[test]
+    fn numbered_values () {
+        let oscillator = Oscillator::from_number(USER_SAMPLES_START);
+        assert!(oscillator.is_user_sample());
+        assert_eq!(oscillator.to_string(), format!("User {USER_SAMPLES_START}"));
+        assert_eq!(Oscillator(0).number(), 1);
+        assert_eq!(Oscillator(0).to_string(), "Osc 1");
+        assert!(!Oscillator::from_number(USER_SAMPLES_START - 1).is_user_sample());
+        assert_eq!(Modulation(MODULATION_TYPES - 1).to_string(), "Mod 72");
+        assert_eq!(Groove(GROOVE_TYPES - 1).to_string(), "Groove 25");
+    }
+}
@@ -0,0 +1,624 @@
+//! Reading and writing of Electribe 2 sample bundles (e2sSample.all), as documented in
+//! docs/korg/sample_format.md.
+
+use super::*;
+use crate::wav::{riff_chunk, riff_subchunk, smpl, write_riff_chunk};
+
+/// Tag at the start of a sample bundle
+pub const SAMPLES_TAG: &[u8] = b"e2s sample all";
+/// Size of the header of a sample bundle
+pub const SAMPLES_HEADER_SIZE: usize = 0x1000;
+/// Offset of the table of sample offsets in the header
+pub const SAMPLES_OFFSETS: usize = 0x0058;
+/// Number of entries in the table of sample offsets
+pub const SAMPLES_MAX: usize = 1000;
+/// Volume of a newly imported sample, as the device and Oe2sSLE write it
+pub const DEFAULT_VOLUME: u16 = 0xFFFF;
+/// Data length of the esli chunk
+pub const ESLI_SIZE: usize = 1172;
+/// First sample number available for user samples
+pub const USER_SAMPLES_START: u16 = 501;
+/// Last sample number available for user samples
+pub const USER_SAMPLES_END: u16 = 999;
+/// Sample memory available for user samples, in bytes of audio data
+pub const USER_SAMPLES_MEMORY: usize = 24 * 1024 * 1024;
+
+/// Sample categories, in the order of their numbers
+pub const SAMPLE_CATEGORIES: [&str; 18] = [
+    "Analog", "Audio In", "Kick", "Snare", "Clap", "HiHat", "Cymbal", "Hits", "Shots",
+    "Voice", "SE", "FX", "Tom", "Perc.", "Phrase", "Loop", "PCM", "User"
+];
+
+#[derive(Debug, Default, Clone)]
+pub struct Electribe2SampleBank {
+    /// Samples in the order in which they appear in the offset table
+    pub samples: Vec<Electribe2Sample>
+}
+
+impl Electribe2SampleBank {
+    /// Read a sample bundle
+    pub fn read (raw: &[u8]) -> std::result::Result<Self, String> {
+        if raw.len() < SAMPLES_HEADER_SIZE || &raw[..SAMPLES_TAG.len()] != SAMPLES_TAG {
+            return Err("not an e2sSample.all file".into())
+        }
+        let mut samples = vec![];
+        for index in 0..SAMPLES_MAX {
+            let start  = SAMPLES_OFFSETS + index * 4;
+            if start + 4 > SAMPLES_HEADER_SIZE {
+                break
+            }
+            let offset = u32::from_le_bytes([
+                raw[start], raw[start+1], raw[start+2], raw[start+3]
+            ]) as usize;
+            if offset == 0 {
+                continue
+            }
+            let sample = raw.get(offset..)
+                .ok_or_else(||format!("offset {offset} is past the end of the file"))
+                .and_then(Electribe2Sample::read)
+                .map_err(|error|format!("sample {}: {error}", index + 1))?;
+            samples.push(sample);
+        }
+        Ok(Self { samples })
+    }
+    /// Lowest user sample number that is not taken yet
+    pub fn next_number (&self) -> Option<u16> {
+        (USER_SAMPLES_START..=USER_SAMPLES_END)
+            .find(|number|!self.samples.iter().any(|sample|sample.number == *number))
+    }
+    /// Add a sample with the next free user sample number. Returns the number,
+    /// or None if all user sample numbers are taken.
+    pub fn add (&mut self, mut sample: Electribe2Sample) -> Option<u16> {
+        let number = self.next_number()?;
+        sample.set_number(number);
+        self.samples.push(sample);
+        Some(number)
+    }
+    /// Bytes of audio data used by user samples
+    pub fn user_memory (&self) -> usize {
+        self.samples.iter()
+            .filter(|sample|sample.number >= USER_SAMPLES_START)
+            .map(|sample|sample.data_length as usize)
+            .sum()
+    }
+    /// Write a sample bundle. Each sample's offset is stored in the slot
+    /// of the table that corresponds to its zero-based number.
+    pub fn write (&self) -> Vec<u8> {
+        assert!(self.user_memory() <= USER_SAMPLES_MEMORY,
+            "user samples take {} bytes, the maximum is {USER_SAMPLES_MEMORY}", self.user_memory());
+        let mut raw = vec![0; SAMPLES_HEADER_SIZE];
+        raw[..SAMPLES_TAG.len()].copy_from_slice(SAMPLES_TAG);
+        for sample in self.samples.iter() {
+            let index = sample.number.saturating_sub(1) as usize;
+            assert!(index < SAMPLES_MAX, "sample number {} out of range", sample.number);
+            let start = SAMPLES_OFFSETS + index * 4;
+            let offset = raw.len() as u32;
+            raw[start..start+4].copy_from_slice(&offset.to_le_bytes());
+            raw.extend_from_slice(&sample.wav);
+            if raw.len() % 2 == 1 {
+                raw.push(0);
+            }
+        }
+        raw
+    }
+}
+
+/// A sample from a bundle: a WAV file with a `korg` chunk containing an `esli` chunk.
+///
+/// ```text
+/// esli:
+/// 0x00  2   sample number, zero-based
+/// 0x02  16  name
+/// 0x12  2   category
+/// 0x14  2   import number
+/// 0x16  12  00 00 00 7F 00 01 00 00 00 00 00 00
+/// 0x22  2   playback period
+/// 0x24  2   volume
+/// 0x28  4   start point
+/// 0x2C  4   loop point
+/// 0x30  4   end point
+/// 0x34  1   0 = loop, 1 = one-shot
+/// 0x3C  4   length of the WAV data
+/// 0x40  1   01
+/// 0x41  1   0 = mono, 1 = stereo
+/// 0x42  1   0 = normal, 1 = +12dB
+/// 0x43  5   01 B0 04 00 00
+/// 0x48  4   sample rate
+/// 0x4D  1   tune (signed)
+/// 0x4E  2   sample number, zero-based
+/// 0x50  ..  slices and slice-mode step assignments (see slices.rs)
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Electribe2Sample {
+    /// Sample number as displayed, i.e. one more than the stored number
+    pub number:          u16,
+    pub name:            String,
+    /// Index into SAMPLE_CATEGORIES
+    pub category:        u16,
+    pub import_number:   u16,
+    pub playback_period: u16,
+    pub volume:          u16,
+    pub start:           u32,
+    pub loop_start:      u32,
+    pub end:             u32,
+    pub one_shot:        bool,
+    pub data_length:     u32,
+    pub stereo:          bool,
+    /// Play level +12dB
+    pub boost:           bool,
+    pub sample_rate:     u32,
+    pub tune:            i8,
+    /// Slices, of which those with non-zero length are used
+    pub slices:          Vec<Electribe2Slice>,
+    /// Slice played by each step in slice mode
+    pub slice_steps:     Vec<u8>,
+    /// The esli chunk, preserved as-is
+    pub esli:            Vec<u8>,
+    /// The whole WAV file, including the korg chunk
+    pub wav:             Vec<u8>,
+}
+
+impl Electribe2Sample {
+    /// Read a sample from the start of a RIFF file
+    pub fn read (raw: &[u8]) -> std::result::Result<Self, String> {
+        if raw.len() < 12 || &raw[0..4] != b"RIFF" {
+            return Err("not a WAV file".into())
+        }
+        let size = u32::from_le_bytes([raw[4], raw[5], raw[6], raw[7]]) as usize;
+        let wav  = raw[..8usize.saturating_add(size).min(raw.len())].to_vec();
+        let mut sample = Self::default();
+        if let Some(korg) = riff_chunk(&wav, b"korg") {
+            if let Some(esli) = riff_subchunk(korg, b"esli") {
+                if esli.len() < SLICES_OFFSET {
+                    return Err(format!("esli chunk is {} bytes long, expected {ESLI_SIZE}", esli.len()))
+                }
+                sample.read_esli(esli);
+            }
+        }
+        sample.wav = wav;
+        Ok(sample)
+    }
+    /// Create a sample from the contents of a 16-bit PCM WAV file, replacing any
+    /// existing korg chunk with one holding default metadata.
+    pub fn from_wav (name: &str, raw: &[u8]) -> std::result::Result<Self, String> {
+        if raw.len() < 12 || &raw[0..4] != b"RIFF" || &raw[8..12] != b"WAVE" {
+            return Err("not a WAV file".into())
+        }
+        let fmt = riff_chunk(raw, b"fmt ").ok_or("no fmt chunk in WAV file")?;
+        if fmt.len() < 16 {
+            return Err("fmt chunk too short".into())
+        }
+        if u16::from_le_bytes([fmt[0], fmt[1]]) != 1 {
+            return Err("uncompressed wavs only".into())
+        }
+        if u16::from_le_bytes([fmt[14], fmt[15]]) != 16 {
+            return Err("16-bit wavs only".into())
+        }
+        let channels = u16::from_le_bytes([fmt[2], fmt[3]]);
+        if channels != 1 && channels != 2 {
+            return Err("mono or stereo wavs only".into())
+        }
+        let data = riff_chunk(raw, b"data").ok_or("no data chunk in WAV file")?;
+        let mut sample = Self::default();
+        sample.name            = name.chars().filter(|c|c.is_ascii()).take(16).collect();
+        sample.category        = Self::guess_category(name);
+        sample.sample_rate     = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]);
+        sample.playback_period = Self::default_playback_period(sample.sample_rate);
+        sample.volume          = DEFAULT_VOLUME;
+        sample.stereo          = channels == 2;
+        sample.data_length     = data.len() as u32;
+        sample.end             = sample.data_length.saturating_sub(2);
+        // Loops play from the start, everything else plays once
+        sample.one_shot        = sample.category != 14 && sample.category != 15;
+        sample.esli            = vec![0; ESLI_SIZE];
+        sample.wav             = raw.to_vec();
+        sample.write_esli();
+        Ok(sample)
+    }
+    /// Default playback period for a sample rate: 63132 - log2(rate) * 3072
+    pub fn default_playback_period (sample_rate: u32) -> u16 {
+        (63132.0 - (sample_rate.max(1) as f64).log2() * 3072.0).round() as u16
+    }
+    /// Guess the category of a sample from keywords in its name
+    pub fn guess_category (name: &str) -> u16 {
+        let name = name.to_lowercase();
+        let words = name.split(|c: char|!c.is_ascii_alphanumeric()).collect::<Vec<_>>();
+        let has = |keywords: &[&str]|keywords.iter().any(|keyword|if keyword.len() <= 2 {
+            words.contains(keyword)
+        } else {
+            name.contains(keyword)
+        });
+        if has(&["kick", "bd", "bass drum"]) {
+            2
+        } else if has(&["snare", "sd", "rim"]) {
+            3
+        } else if has(&["clap", "cp"]) {
+            4
+        } else if has(&["hat", "hh", "oh", "ch"]) {
+            5
+        } else if has(&["cymbal", "crash", "ride", "china", "splash"]) {
+            6
+        } else if has(&["tom"]) {
+            12
+        } else if has(&["perc", "conga", "bongo", "shaker", "clave", "cowbell", "tamb"]) {
+            13
+        } else if has(&["vox", "voice", "vocal"]) {
+            9
+        } else if has(&["loop", "break", "beat"]) {
+            15
+        } else if has(&["phrase"]) {
+            14
+        } else if has(&["fx", "sfx", "noise", "riser", "sweep"]) {
+            11
+        } else if has(&["hit", "stab", "chord"]) {
+            7
+        } else if has(&["shot"]) {
+            8
+        } else {
+            17
+        }
+    }
+    /// Change the sample number, updating the esli chunk
+    pub fn set_number (&mut self, number: u16) {
+        self.number        = number;
+        self.import_number = number;
+        self.write_esli();
+    }
+    /// Write the metadata into the esli chunk, and the esli chunk into the WAV
+    pub fn write_esli (&mut self) {
+        let mut raw = std::mem::take(&mut self.esli);
+        raw.resize(ESLI_SIZE, 0);
+        let number = self.number.saturating_sub(1).to_le_bytes();
+        raw[0x00..0x02].copy_from_slice(&number);
+        let mut name = [0u8; 16];
+        for (index, byte) in self.name.bytes().take(16).enumerate() {
+            name[index] = byte;
+        }
+        raw[0x02..0x12].copy_from_slice(&name);
+        raw[0x12..0x14].copy_from_slice(&self.category.to_le_bytes());
+        raw[0x14..0x16].copy_from_slice(&self.import_number.to_le_bytes());
+        raw[0x16..0x22].copy_from_slice(&[0x00, 0x00, 0x00, 0x7F, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        raw[0x22..0x24].copy_from_slice(&self.playback_period.to_le_bytes());
+        raw[0x24..0x26].copy_from_slice(&self.volume.to_le_bytes());
+        raw[0x28..0x2C].copy_from_slice(&self.start.to_le_bytes());
+        raw[0x2C..0x30].copy_from_slice(&self.loop_start.to_le_bytes());
+        raw[0x30..0x34].copy_from_slice(&self.end.to_le_bytes());
+        raw[0x34] = if self.one_shot { 1 } else { 0 };
+        raw[0x3C..0x40].copy_from_slice(&self.data_length.to_le_bytes());
+        raw[0x40] = 0x01;
+        raw[0x41] = if self.stereo { 1 } else { 0 };
+        raw[0x42] = if self.boost { 1 } else { 0 };
+        raw[0x43..0x48].copy_from_slice(&[0x01, 0xB0, 0x04, 0x00, 0x00]);
+        raw[0x48..0x4C].copy_from_slice(&self.sample_rate.to_le_bytes());
+        raw[0x4D] = self.tune as u8;
+        raw[0x4E..0x50].copy_from_slice(&number);
+        self.write_slices(&mut raw);
+        self.esli = raw;
+        self.write_korg();
+    }
+    /// Replace the korg chunk of the WAV with one holding the current esli chunk,
+    /// keeping all other chunks
+    fn write_korg (&mut self) {
+        if self.wav.len() < 12 {
+            return
+        }
+        let mut wav = self.wav[..12].to_vec();
+        let mut index = 12;
+        while index + 8 <= self.wav.len() {
+            let raw  = &self.wav;
+            let size = u32::from_le_bytes([raw[index+4], raw[index+5], raw[index+6], raw[index+7]]) as usize;
+            let end  = (index + 8 + size + size % 2).min(raw.len());
+            if &raw[index..index+4] != b"korg" {
+                wav.extend_from_slice(&raw[index..end]);
+            }
+            index = end;
+        }
+        wav.extend_from_slice(b"korg");
+        wav.extend_from_slice(&(8 + self.esli.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"esli");
+        wav.extend_from_slice(&(self.esli.len() as u32).to_le_bytes());
+        wav.extend_from_slice(&self.esli);
+        let size = (wav.len() - 8) as u32;
+        wav[4..8].copy_from_slice(&size.to_le_bytes());
+        self.wav = wav;
+    }
+    /// A plain WAV file with the loop points in a standard smpl chunk, and
+    /// optionally the korg chunk. Any existing smpl chunk is replaced.
+    pub fn to_wav (&self, keep_korg: bool) -> Vec<u8> {
+        let mut wav = self.wav[..12].to_vec();
+        let mut index = 12;
+        while index + 8 <= self.wav.len() {
+            let raw  = &self.wav;
+            let size = u32::from_le_bytes([raw[index+4], raw[index+5], raw[index+6], raw[index+7]]) as usize;
+            let end  = (index + 8 + size + size % 2).min(raw.len());
+            let id   = &raw[index..index+4];
+            if id != b"smpl" && (keep_korg || id != b"korg") {
+                wav.extend_from_slice(&raw[index..end]);
+            }
+            index = end;
+        }
+        wav.extend_from_slice(&self.smpl());
+        let size = (wav.len() - 8) as u32;
+        wav[4..8].copy_from_slice(&size.to_le_bytes());
+        wav
+    }
+    /// A smpl chunk with the sample's loop, if it is not a one-shot.
+    /// Loop points are converted from byte offsets to frames.
+    pub fn smpl (&self) -> Vec<u8> {
+        let frame = if self.stereo { 4 } else { 2 };
+        let looped = (!self.one_shot).then(||(self.loop_start / frame, self.end / frame));
+        let mut raw = vec![];
+        write_riff_chunk(&mut raw, b"smpl", &smpl(self.sample_rate, 60, looped));
+        raw
+    }
+    /// File name for extracting the sample: NNN_Name.wav
+    pub fn file_name (&self) -> String {
+        format!("{:03}_{}.wav", self.number, crate::filename::sanitize(&self.name))
+    }
+    /// Read the fields of an esli chunk
+    pub fn read_esli (&mut self, raw: &[u8]) {
+        let u16_at = |i: usize|u16::from_le_bytes([raw[i], raw[i+1]]);
+        let u32_at = |i: usize|u32::from_le_bytes([raw[i], raw[i+1], raw[i+2], raw[i+3]]);
+        self.number          = u16_at(0x00) + 1;
+        self.name            = String::from_utf8_lossy(&raw[0x02..0x12])
+            .trim_end_matches('\0').to_string();
+        self.category        = u16_at(0x12);
+        self.import_number   = u16_at(0x14);
+        self.playback_period = u16_at(0x22);
+        self.volume          = u16_at(0x24);
+        self.start           = u32_at(0x28);
+        self.loop_start      = u32_at(0x2C);
+        self.end             = u32_at(0x30);
+        self.one_shot        = raw[0x34] == 1;
+        self.data_length     = u32_at(0x3C);
+        self.stereo          = raw[0x41] == 1;
+        self.boost           = raw[0x42] == 1;
+        self.sample_rate     = u32_at(0x48);
+        self.tune            = raw[0x4D] as i8;
+        if raw.len() >= SLICE_STEPS_OFFSET + SLICES {
+            self.read_slices(raw);
+        }
+        self.esli            = raw.to_vec();
+    }
+    /// Name of the sample's category
+    pub fn category_name (&self) -> &'static str {
+        SAMPLE_CATEGORIES.get(self.category as usize).copied().unwrap_or("?")
+    }
+    /// Length of the sample in seconds
+    pub fn duration (&self) -> f32 {
+        let channels = if self.stereo { 2 } else { 1 };
+        self.data_length as f32 / (2 * channels * self.sample_rate.max(1)) as f32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A 16-bit WAV file at 48kHz with a ramp in each channel
+    fn wav (channels: u16, frames: usize) -> Vec<u8> {
+        let mut fmt = vec![];
+        fmt.extend_from_slice(&1u16.to_le_bytes());
+        fmt.extend_from_slice(&channels.to_le_bytes());
+        fmt.extend_from_slice(&48000u32.to_le_bytes());
+        fmt.extend_from_slice(&(48000 * 2 * channels as u32).to_le_bytes());
+        fmt.extend_from_slice(&(2 * channels).to_le_bytes());
+        fmt.extend_from_slice(&16u16.to_le_bytes());
+        let data = (0..frames * channels as usize)
+            .flat_map(|index|(index as i16).to_le_bytes())
+            .collect::<Vec<_>>();
+        let mut raw = b"RIFF\0\0\0\0WAVE".to_vec();
+        write_riff_chunk(&mut raw, b"fmt ", &fmt);
+        write_riff_chunk(&mut raw, b"data", &data);
+        let size = (raw.len() - 8) as u32;
+        raw[4..8].copy_from_slice(&size.to_le_bytes());
+        raw
+    }
+
+    /// Append a korg chunk holding an esli chunk to a WAV file
+    fn with_esli (mut raw: Vec<u8>, esli: &[u8]) -> Vec<u8> {
+        let mut korg = vec![];
+        write_riff_chunk(&mut korg, b"esli", esli);
+        write_riff_chunk(&mut raw, b"korg", &korg);
+        let size = (raw.len() - 8) as u32;
+        raw[4..8].copy_from_slice(&size.to_le_bytes());
+        raw
+    }
+
+    /// An esli chunk with distinct values at the documented offsets
+    fn known_esli () -> Vec<u8> {
+        let mut raw = vec![0; ESLI_SIZE];
+        raw[0x00..0x02].copy_from_slice(&519u16.to_le_bytes());
+        raw[0x02..0x0A].copy_from_slice(b"Clap 909");
+        raw[0x12..0x14].copy_from_slice(&4u16.to_le_bytes());
+        raw[0x14..0x16].copy_from_slice(&520u16.to_le_bytes());
+        raw[0x22..0x24].copy_from_slice(&15360u16.to_le_bytes());
+        raw[0x24..0x26].copy_from_slice(&DEFAULT_VOLUME.to_le_bytes());
+        raw[0x28..0x2C].copy_from_slice(&8u32.to_le_bytes());
+        raw[0x2C..0x30].copy_from_slice(&100u32.to_le_bytes());
+        raw[0x30..0x34].copy_from_slice(&398u32.to_le_bytes());
+        raw[0x34] = 1;
+        raw[0x3C..0x40].copy_from_slice(&400u32.to_le_bytes());
+        raw[0x41] = 1;
+        raw[0x42] = 1;
+        raw[0x48..0x4C].copy_from_slice(&48000u32.to_le_bytes());
+        raw[0x4D] = -3i8 as u8;
+        raw[SLICES_OFFSET + 4..SLICES_OFFSET + 8].copy_from_slice(&50u32.to_le_bytes());
+        raw
+    }
+
+    /// A sample bundle with the given WAV files at the given zero-based numbers
+    fn bundle (samples: &[(usize, Vec<u8>)]) -> Vec<u8> {
+        let mut raw = vec![0; SAMPLES_HEADER_SIZE];
+        raw[..SAMPLES_TAG.len()].copy_from_slice(SAMPLES_TAG);
+        for (index, wav) in samples {
+            let offset = raw.len() as u32;
+            raw[SAMPLES_OFFSETS + index * 4..SAMPLES_OFFSETS + index * 4 + 4]
+                .copy_from_slice(&offset.to_le_bytes());
+            raw.extend_from_slice(wav);
+        }
+        raw
+    }
+
+    #[test]
+    fn read_bank () {
+        let clap = with_esli(wav(2, 100), &known_esli());
+        let bank = Electribe2SampleBank::read(&bundle(&[(0, wav(1, 10)), (519, clap.clone())])).unwrap();
+        assert_eq!(bank.samples.len(), 2);
+        // A WAV file without a korg chunk has no metadata
+        assert_eq!(bank.samples[0].number, 0);
+        assert_eq!(bank.samples[0].wav, wav(1, 10));
+        let sample = &bank.samples[1];
+        assert_eq!(sample.number,          520);
+        assert_eq!(sample.name,            "Clap 909");
+        assert_eq!(sample.category_name(), "Clap");
+        assert_eq!(sample.import_number,   520);
+        assert_eq!(sample.playback_period, 15360);
+        assert_eq!(sample.volume,          DEFAULT_VOLUME);
+        assert_eq!(sample.start,           8);
+        assert_eq!(sample.loop_start,      100);
+        assert_eq!(sample.end,             398);
+        assert!(sample.one_shot);
+        assert_eq!(sample.data_length,     400);
+        assert!(sample.stereo);
+        assert!(sample.boost);
+        assert_eq!(sample.sample_rate,     48000);
+        assert_eq!(sample.tune,            -3);
+        assert_eq!(sample.frames(),        100);
+        assert_eq!(sample.slice_count(),   1);
+        assert_eq!(sample.slices[0].length, 50);
+        assert_eq!(sample.esli,            known_esli());
+        assert_eq!(sample.wav,             clap);
+        assert_eq!(bank.user_memory(),     400);
+    }
+
+    #[test]
+    fn read_errors () {
+        assert!(Electribe2SampleBank::read(b"e2s sample all").is_err());
+        assert!(Electribe2SampleBank::read(&vec![0; SAMPLES_HEADER_SIZE]).is_err());
+        let mut raw = bundle(&[]);
+        raw[SAMPLES_OFFSETS..SAMPLES_OFFSETS + 4].copy_from_slice(&0x2000u32.to_le_bytes());
+        assert!(Electribe2SampleBank::read(&raw).is_err());
+        assert!(Electribe2SampleBank::read(&bundle(&[(3, b"RIFX".to_vec())])).is_err());
+        let short = with_esli(wav(1, 10), &known_esli()[..0x40]);
+        assert!(Electribe2SampleBank::read(&bundle(&[(3, short)])).is_err());
+        assert_eq!(Electribe2SampleBank::read(&bundle(&[])).unwrap().samples.len(), 0);
+    }
+
+    #[test]
+    fn from_wav () {
+        let raw = wav(2, 100);
+        let sample = Electribe2Sample::from_wav("Amen Loop 170 BPM long name", &raw).unwrap();
+        assert_eq!(sample.name,            "Amen Loop 170 BP");
+        assert_eq!(sample.category_name(), "Loop");
+        assert!(!sample.one_shot);
+        assert!(sample.stereo);
+        assert_eq!(sample.sample_rate,     48000);
+        assert_eq!(sample.playback_period, 15360);
+        assert_eq!(sample.volume,          DEFAULT_VOLUME);
+        assert_eq!(sample.data_length,     400);
+        assert_eq!(sample.end,             398);
+        assert_eq!(sample.esli.len(),      ESLI_SIZE);
+        assert_eq!(&sample.esli[0x43..0x48], &[0x01, 0xB0, 0x04, 0x00, 0x00]);
+        // The audio data is kept, and the esli chunk is appended in a korg chunk
+        assert_eq!(riff_chunk(&sample.wav, b"data"), riff_chunk(&raw, b"data"));
+        let korg = riff_chunk(&sample.wav, b"korg").unwrap();
+        assert_eq!(riff_subchunk(korg, b"esli"), Some(sample.esli.as_slice()));
+        let size = u32::from_le_bytes([sample.wav[4], sample.wav[5], sample.wav[6], sample.wav[7]]);
+        assert_eq!(size as usize, sample.wav.len() - 8);
+        let kick = Electribe2Sample::from_wav("BD 01", &wav(1, 10)).unwrap();
+        assert_eq!(kick.category_name(), "Kick");
+        assert!(kick.one_shot);
+    }
+
+    #[test]
+    fn from_wav_errors () {
+        let raw = wav(1, 10);
+        assert!(Electribe2Sample::from_wav("x", b"RIFF").is_err());
+        assert!(Electribe2Sample::from_wav("x", &raw[..12]).is_err());
+        let mut eight_bit = raw.clone();
+        eight_bit[12 + 8 + 14] = 8;
+        assert!(Electribe2Sample::from_wav("x", &eight_bit).is_err());
+        let mut surround = raw.clone();
+        surround[12 + 8 + 2] = 6;
+        assert!(Electribe2Sample::from_wav("x", &surround).is_err());
+        let mut float = raw.clone();
+        float[12 + 8] = 3;
+        assert!(Electribe2Sample::from_wav("x", &float).is_err());
+    }
+
+    #[test]
+    fn metadata_defaults () {
+        assert_eq!(Electribe2Sample::default_playback_period(48000), 15360);
+        assert_eq!(Electribe2Sample::default_playback_period(44100), 15736);
+        assert_eq!(Electribe2Sample::default_playback_period(24000), 18432);
+        assert_eq!(Electribe2Sample::guess_category("TR-909 Snare"), 3);
+        assert_eq!(Electribe2Sample::guess_category("hh closed"), 5);
+        // Short keywords only match whole words
+        assert_eq!(Electribe2Sample::guess_category("Cheese"), 17);
+        assert_eq!(Electribe2Sample::guess_category("Riser FX"), 11);
+    }
+
+    #[test]
+    fn write_bank () {
+        let mut bank = Electribe2SampleBank::default();
+        assert_eq!(bank.add(Electribe2Sample::from_wav("Kick", &wav(1, 11)).unwrap()), Some(501));
+        assert_eq!(bank.add(Electribe2Sample::from_wav("Loop", &wav(2, 50)).unwrap()), Some(502));
+        assert_eq!(bank.next_number(), Some(503));
+        assert_eq!(bank.user_memory(), 22 + 200);
+        let raw = bank.write();
+        assert_eq!(&raw[..SAMPLES_TAG.len()], SAMPLES_TAG);
+        let offset = |index: usize|u32::from_le_bytes(
+            raw[SAMPLES_OFFSETS + index * 4..SAMPLES_OFFSETS + index * 4 + 4].try_into().unwrap()
+        ) as usize;
+        assert_eq!(offset(499), 0);
+        assert_eq!(offset(500), SAMPLES_HEADER_SIZE);
+        assert_eq!(offset(501), SAMPLES_HEADER_SIZE + bank.samples[0].wav.len());
+        let read = Electribe2SampleBank::read(&raw).unwrap();
+        assert_eq!(read.samples.len(), 2);
+        assert_eq!(read.samples[0].number, 501);
+        assert_eq!(read.samples[0].import_number, 501);
+        assert_eq!(read.samples[1].name, "Loop");
+        assert_eq!(read.samples[1].wav, bank.samples[1].wav);
+        assert_eq!(read.write(), raw);
+    }
+
+
+    /// IDs of the top-level chunks of a RIFF file
+    fn chunk_ids (wav: &[u8]) -> Vec<[u8; 4]> {
+        let mut ids = vec![];
+        let mut index = 12;
+        while index + 8 <= wav.len() {
+            let size = u32::from_le_bytes(wav[index+4..index+8].try_into().unwrap()) as usize;
+            ids.push(wav[index..index+4].try_into().unwrap());
+            index += 8 + size + size % 2;
+        }
+        ids
+    }
+
+    #[test]
+    fn extract_wav () {
+        let mut sample = Electribe2Sample::from_wav("Pad/Loop", &wav(2, 100)).unwrap();
+        sample.set_number(503);
+        sample.loop_start = 40;
+        assert_eq!(sample.file_name(), "503_Pad_Loop.wav");
+        let plain = sample.to_wav(false);
+        assert_eq!(chunk_ids(&plain), vec![*b"fmt ", *b"data", *b"smpl"]);
+        assert_eq!(riff_chunk(&plain, b"data"), riff_chunk(&sample.wav, b"data"));
+        // Loop points are stored in bytes, the smpl chunk counts frames
+        assert_eq!(crate::wav::wav_loop(&plain), Some((10, 99)));
+        assert_eq!(crate::wav::wav_root_key(&plain), Some(60));
+        let size = u32::from_le_bytes(plain[4..8].try_into().unwrap()) as usize;
+        assert_eq!(size, plain.len() - 8);
+        let kept = sample.to_wav(true);
+        assert_eq!(chunk_ids(&kept), vec![*b"fmt ", *b"data", *b"korg", *b"smpl"]);
+        assert_eq!(Electribe2Sample::read(&kept).unwrap().number, 503);
+        // An existing smpl chunk is replaced, and one-shots have no loop
+        let mut again = Electribe2Sample::from_wav("Hit", &plain).unwrap();
+        again.one_shot = true;
+        let extracted = again.to_wav(false);
+        assert_eq!(chunk_ids(&extracted), vec![*b"fmt ", *b"data", *b"smpl"]);
+        assert_eq!(crate::wav::wav_loop(&extracted), None);
+    }
+
+}
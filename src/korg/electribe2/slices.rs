@@ -0,0 +1,273 @@
+//! Slice data at the end of the esli chunk, as used by Oe2sSLE, and automatic slicing.
+
+use super::*;
+use crate::wav::riff_chunk;
+
+/// Offset of the slices in the esli chunk
+pub const SLICES_OFFSET: usize = 0x0050;
+/// Number of slices per sample
+pub const SLICES: usize = 64;
+/// Size of a slice
+pub const SLICE_SIZE: usize = 16;
+/// Offset of the slice-to-step assignments in the esli chunk. These are the slice-mode
+/// settings of a sample: in slice mode, each step plays the slice assigned to it.
+pub const SLICE_STEPS_OFFSET: usize = SLICES_OFFSET + SLICES * SLICE_SIZE;
+/// Offset of the unidentified bytes after the slice-to-step assignments, up to the
+/// end of the esli chunk. They are preserved as-is.
+pub const SLICE_UNKNOWN_OFFSET: usize = SLICE_STEPS_OFFSET + SLICES;
+/// Amplitude given to slices created by automatic slicing
+pub const SLICE_AMPLITUDE: u32 = 256;
+/// Size of the analysis window for transient detection, in frames
+const ONSET_WINDOW: usize = 512;
+
+/// A slice of a sample. Positions are in frames from the start of the audio data.
+///
+/// ```text
+/// 0x00  4   start
+/// 0x04  4   length (0 = unused)
+/// 0x08  4   attack length
+/// 0x0C  4   amplitude
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Electribe2Slice {
+    pub start:     u32,
+    pub length:    u32,
+    pub attack:    u32,
+    pub amplitude: u32,
+}
+
+impl Electribe2Slice {
+    pub fn read (raw: &[u8]) -> Self {
+        let u32_at = |i: usize|u32::from_le_bytes([raw[i], raw[i+1], raw[i+2], raw[i+3]]);
+        Self { start: u32_at(0x00), length: u32_at(0x04), attack: u32_at(0x08), amplitude: u32_at(0x0C) }
+    }
+    pub fn write (&self, raw: &mut [u8]) {
+        raw[0x00..0x04].copy_from_slice(&self.start.to_le_bytes());
+        raw[0x04..0x08].copy_from_slice(&self.length.to_le_bytes());
+        raw[0x08..0x0C].copy_from_slice(&self.attack.to_le_bytes());
+        raw[0x0C..0x10].copy_from_slice(&self.amplitude.to_le_bytes());
+    }
+    pub fn is_used (&self) -> bool {
+        self.length > 0
+    }
+}
+
+impl Electribe2Sample {
+
+    /// Read the slices and slice-mode step assignments from an esli chunk
+    pub fn read_slices (&mut self, raw: &[u8]) {
+        self.slices = (0..SLICES)
+            .map(|index|SLICES_OFFSET + index * SLICE_SIZE)
+            .map(|start|Electribe2Slice::read(&raw[start..start+SLICE_SIZE]))
+            .collect();
+        self.slice_steps = raw[SLICE_STEPS_OFFSET..SLICE_STEPS_OFFSET+SLICES].to_vec();
+    }
+
+    /// Write the slices and slice-mode step assignments into an esli chunk
+    pub fn write_slices (&self, raw: &mut [u8]) {
+        for (index, slice) in self.slices.iter().enumerate().take(SLICES) {
+            let start = SLICES_OFFSET + index * SLICE_SIZE;
+            slice.write(&mut raw[start..start+SLICE_SIZE]);
+        }
+        for (index, step) in self.slice_steps.iter().enumerate().take(SLICES) {
+            raw[SLICE_STEPS_OFFSET + index] = *step;
+        }
+    }
+
+    /// The unidentified bytes after the slice-to-step assignments
+    pub fn slice_unknown (&self) -> &[u8] {
+        self.esli.get(SLICE_UNKNOWN_OFFSET..).unwrap_or(&[])
+    }
+
+    /// Slice played by a step in slice mode, if that slice is in use
+    pub fn step_slice (&self, step: usize) -> Option<&Electribe2Slice> {
+        let slice = *self.slice_steps.get(step)? as usize;
+        self.slices.get(slice).filter(|slice|slice.is_used())
+    }
+
+    /// Assign slices (numbered from 0) to the steps played in slice mode, starting
+    /// from the first step. The remaining steps play the first slice.
+    pub fn set_slice_steps (&mut self, steps: &[u8]) -> std::result::Result<(), String> {
+        if steps.len() > SLICES {
+            return Err(format!("{} steps given, there are only {SLICES}", steps.len()))
+        }
+        for (step, slice) in steps.iter().enumerate() {
+            if !self.slices.get(*slice as usize).is_some_and(|slice|slice.is_used()) {
+                return Err(format!("step {} plays slice {}, which is not in use", step + 1, slice + 1))
+            }
+        }
+        let mut steps = steps.to_vec();
+        steps.resize(SLICES, 0);
+        self.slice_steps = steps;
+        self.write_esli();
+        Ok(())
+    }
+
+    /// Number of slices in use
+    pub fn slice_count (&self) -> usize {
+        self.slices.iter().filter(|slice|slice.is_used()).count()
+    }
+
+    /// Number of frames of audio data
+    pub fn frames (&self) -> usize {
+        let channels = if self.stereo { 2 } else { 1 };
+        self.data_length as usize / (2 * channels)
+    }
+
+    /// Audio data mixed down to mono
+    pub fn mono (&self) -> Vec<i32> {
+        let Some(data) = riff_chunk(&self.wav, b"data") else { return vec![] };
+        let channels = if self.stereo { 2 } else { 1 };
+        data.chunks_exact(2 * channels)
+            .map(|frame|frame.chunks_exact(2)
+                .map(|x|i16::from_le_bytes([x[0], x[1]]) as i32)
+                .sum::<i32>() / channels as i32)
+            .collect()
+    }
+
+    /// Replace the slices with ones starting at the given frames, each lasting
+    /// until the next one, and assign them to consecutive steps.
+    pub fn set_slices (&mut self, starts: &[usize]) {
+        let frames = self.frames();
+        let mut starts = starts.iter().copied().filter(|start|*start < frames).collect::<Vec<_>>();
+        starts.sort();
+        starts.dedup();
+        starts.truncate(SLICES);
+        self.slices = vec![Electribe2Slice::default(); SLICES];
+        for (index, start) in starts.iter().enumerate() {
+            let end = starts.get(index + 1).copied().unwrap_or(frames);
+            self.slices[index] = Electribe2Slice {
+                start:     *start as u32,
+                length:    (end - start) as u32,
+                attack:    0,
+                amplitude: SLICE_AMPLITUDE,
+            };
+        }
+        self.slice_steps = (0..SLICES as u8).map(|step|if (step as usize) < starts.len() { step } else { 0 }).collect();
+        self.write_esli();
+    }
+
+    /// Cut the sample into equal slices
+    pub fn slice_equal (&mut self, count: usize) -> usize {
+        let count = count.clamp(1, SLICES);
+        let frames = self.frames();
+        let starts = (0..count).map(|index|index * frames / count).collect::<Vec<_>>();
+        self.set_slices(&starts);
+        self.slice_count()
+    }
+
+    /// Cut the sample at transients, found where the energy of a window rises sharply
+    /// above that of the previous one. Keeps at most `max` of the strongest transients,
+    /// and always starts a slice at the beginning of the sample.
+    pub fn slice_transients (&mut self, max: usize) -> usize {
+        let energy = self.mono()
+            .chunks(ONSET_WINDOW)
+            .map(|window|window.iter().map(|x|(*x as f64).powi(2)).sum::<f64>() / window.len() as f64)
+            .collect::<Vec<_>>();
+        let peak = energy.iter().copied().fold(0.0, f64::max);
+        let mut onsets = vec![];
+        for index in 1..energy.len() {
+            let rise = energy[index] / energy[index - 1].max(1.0);
+            // Ignore quiet windows, and rises of less than 6dB
+            if energy[index] > peak / 100.0 && rise > 4.0 {
+                onsets.push((index * ONSET_WINDOW, rise));
+            }
+        }
+        onsets.sort_by(|a, b|b.1.total_cmp(&a.1));
+        onsets.truncate(max.clamp(1, SLICES) - 1);
+        let mut starts = vec![0];
+        starts.extend(onsets.iter().map(|(start, _)|*start));
+        self.set_slices(&starts);
+        self.slice_count()
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::wav::write_riff_chunk;
+
+    /// A 16-bit mono WAV file at 48kHz
+    fn wav (frames: &[i16]) -> Vec<u8> {
+        let mut fmt = vec![];
+        fmt.extend_from_slice(&1u16.to_le_bytes());
+        fmt.extend_from_slice(&1u16.to_le_bytes());
+        fmt.extend_from_slice(&48000u32.to_le_bytes());
+        fmt.extend_from_slice(&96000u32.to_le_bytes());
+        fmt.extend_from_slice(&2u16.to_le_bytes());
+        fmt.extend_from_slice(&16u16.to_le_bytes());
+        let data = frames.iter().flat_map(|frame|frame.to_le_bytes()).collect::<Vec<_>>();
+        let mut raw = b"RIFF\0\0\0\0WAVE".to_vec();
+        write_riff_chunk(&mut raw, b"fmt ", &fmt);
+        write_riff_chunk(&mut raw, b"data", &data);
+        let size = (raw.len() - 8) as u32;
+        raw[4..8].copy_from_slice(&size.to_le_bytes());
+        raw
+    }
+
+    /// Silence with a loud burst at each of the given frames
+    fn hits (frames: usize, at: &[usize]) -> Vec<i16> {
+        let mut data = vec![0; frames];
+        for start in at {
+            for frame in data[*start..].iter_mut().take(ONSET_WINDOW * 2) {
+                *frame = 20000;
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn slice_equal () {
+        let mut sample = Electribe2Sample::from_wav("Loop", &wav(&vec![0; 1000])).unwrap();
+        assert_eq!(sample.slice_equal(4), 4);
+        assert_eq!(sample.slices[1], Electribe2Slice { start: 250, length: 250, attack: 0, amplitude: SLICE_AMPLITUDE });
+        assert_eq!(sample.slices[3].start + sample.slices[3].length, 1000);
+        assert!(!sample.slices[4].is_used());
+        assert_eq!(&sample.slice_steps[..5], &[0, 1, 2, 3, 0]);
+        // Counts are kept within 1 and the number of slices
+        assert_eq!(sample.slice_equal(0), 1);
+        assert_eq!(sample.slices[0].length, 1000);
+        assert_eq!(sample.slice_equal(100), SLICES);
+    }
+
+    #[test]
+    fn slice_transients () {
+        let at = [0, ONSET_WINDOW * 8, ONSET_WINDOW * 16, ONSET_WINDOW * 24];
+        let mut sample = Electribe2Sample::from_wav("Break", &wav(&hits(ONSET_WINDOW * 32, &at))).unwrap();
+        assert_eq!(sample.slice_transients(SLICES), 4);
+        let starts = sample.slices.iter().take(4).map(|slice|slice.start as usize).collect::<Vec<_>>();
+        assert_eq!(starts, at);
+        // Keeps the start of the sample, and the strongest of the others
+        assert_eq!(sample.slice_transients(2), 2);
+        assert_eq!(sample.slices[0].start, 0);
+        // Silence has no transients
+        let mut sample = Electribe2Sample::from_wav("Silence", &wav(&vec![0; 4096])).unwrap();
+        assert_eq!(sample.slice_transients(SLICES), 1);
+    }
+
+    #[test]
+    fn esli_round_trip () {
+        let mut sample = Electribe2Sample::from_wav("Loop", &wav(&vec![0; 1000])).unwrap();
+        sample.slice_equal(3);
+        sample.slices[1].attack = 12;
+        sample.set_slice_steps(&[2, 1, 0, 2]).unwrap();
+        let read = Electribe2Sample::read(&sample.wav).unwrap();
+        assert_eq!(read.slices, sample.slices);
+        assert_eq!(&read.slice_steps[..5], &[2, 1, 0, 2, 0]);
+        assert_eq!(read.step_slice(0), Some(&sample.slices[2]));
+        assert_eq!(read.slice_count(), 3);
+        assert_eq!(read.esli, sample.esli);
+    }
+
+    #[test]
+    fn slice_steps_errors () {
+        let mut sample = Electribe2Sample::from_wav("Loop", &wav(&vec![0; 1000])).unwrap();
+        sample.slice_equal(2);
+        assert!(sample.set_slice_steps(&[0, 2]).is_err());
+        assert!(sample.set_slice_steps(&[0; SLICES + 1]).is_err());
+        assert_eq!(sample.step_slice(1), Some(&sample.slices[1]));
+        assert_eq!(sample.step_slice(2).map(|slice|slice.start), Some(0));
+        assert_eq!(sample.step_slice(SLICES), None);
+    }
+}
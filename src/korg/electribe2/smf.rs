@@ -0,0 +1,196 @@
+use super::*;
+use crate::midi;
+
+/// MIDI resolution used when exporting patterns
+pub const MIDI_PPQN: u32 = 96;
+/// Ticks per step when exporting patterns with 16th note steps
+pub const MIDI_STEP: u32 = MIDI_PPQN / 4;
+/// Gate value meaning that the note is tied to the next one
+pub const GATE_TIE: u8 = 127;
+/// Longest gate that is not a tie, in 1/24ths of a 16th note
+pub const GATE_MAX: u8 = 96;
+
+/// Which notes of a MIDI file to import into a part
+#[derive(Debug, Clone, PartialEq)]
+pub enum Electribe2MidiSource {
+    /// All notes on a MIDI channel (0-15)
+    Channel(u8),
+    /// All notes of the tracks with this name
+    Track(String),
+}
+
+impl Electribe2MidiSource {
+
+    /// Parse `ch10` (channel 10, counting from 1) or a track name
+    pub fn parse (text: &str) -> Self {
+        match text.strip_prefix("ch").and_then(|channel|channel.parse::<u8>().ok()) {
+            Some(channel) if (1..=16).contains(&channel) => Self::Channel(channel - 1),
+            _ => Self::Track(text.into())
+        }
+    }
+
+    /// Collect the matching notes from a MIDI file, in order
+    pub fn notes (&self, smf: &midi::SMF) -> Vec<midi::Note> {
+        let mut notes = vec![];
+        for track in smf.tracks.iter() {
+            match self {
+                Self::Channel(channel) => notes.extend(
+                    track.notes().into_iter().filter(|note|note.channel == *channel)
+                ),
+                Self::Track(name) => if track.name().map(|x|x.trim()) == Some(name.trim()) {
+                    notes.extend(track.notes())
+                }
+            }
+        }
+        notes.sort_by_key(|note|note.time);
+        notes
+    }
+
+}
+
+impl Electribe2Pattern {
+
+    /// Length of a step in ticks, from the beat setting (16th, 32nd, 8th triplet, 16th triplet)
+    pub fn step_ticks (&self) -> u32 {
+        match self.beats {
+            Beats::ThirtySecond     => MIDI_STEP / 2,
+            Beats::EighthTriplet    => MIDI_STEP * 4 / 3,
+            Beats::SixteenthTriplet => MIDI_STEP * 2 / 3,
+            _ => MIDI_STEP
+        }
+    }
+
+    /// Offset in ticks applied to a step by the swing setting:
+    /// every other step is delayed (or advanced) by up to half a step.
+    pub fn swing_ticks (&self, step: usize) -> i32 {
+        if step % 2 == 1 {
+            self.swing as i8 as i32 * self.step_ticks() as i32 / 100
+        } else {
+            0
+        }
+    }
+
+    /// Render the pattern as a type-1 Standard MIDI File: a tempo track, then one track
+    /// per part, sent on channels 1-16, with the part's notes and motion sequences.
+    pub fn to_midi (&self) -> midi::SMF {
+        let mut smf = midi::SMF::new(MIDI_PPQN as u16);
+        smf.track(&self.name)
+            .add(0, midi::Event::tempo(self.bpm))
+            .add(0, midi::Event::TimeSignature(4, 4));
+        let steps = self.steps();
+        let step  = self.step_ticks();
+        for (index, part) in self.parts.iter().enumerate() {
+            let channel = index as u8;
+            let track = smf.track(&format!("Part {}", index + 1));
+            for (number, data) in part.steps.iter().enumerate().take(steps) {
+                let time = (number as u32 * step) as i32 + self.swing_ticks(number);
+                let time = time.max(0) as u32;
+                let duration = if data.gate >= GATE_TIE {
+                    // Tie until the next step with notes, or the end of the pattern
+                    let next = part.steps.iter().enumerate().take(steps).skip(number + 1)
+                        .find(|(_, step)|step.notes().count() > 0)
+                        .map(|(next, _)|next)
+                        .unwrap_or(steps);
+                    (next - number) as u32 * step
+                } else {
+                    // Gate is measured in 1/24ths of a 16th note
+                    data.gate as u32 * step / MIDI_STEP
+                };
+                for note in data.notes() {
+                    if data.gate > 0 {
+                        track.note(time, duration, channel, note, data.velocity.max(1));
+                    }
+                }
+            }
+            for motion in self.motion.iter().filter(|motion|motion.part as usize == index + 1) {
+                if let Some(control) = motion.control() {
+                    motion.to_midi(track, control, part.motion_seq == MotionSeqMode::Smooth, steps, step);
+                }
+            }
+        }
+        smf
+    }
+
+    /// Quantize notes from a MIDI file with the given resolution onto the steps
+    /// of a part (0-15), replacing its contents. Up to 4 notes are kept per step;
+    /// the step takes the highest velocity and the longest gate of its notes.
+    /// The pattern is lengthened to fit the notes, up to 4 bars.
+    /// Returns the number of notes that were placed.
+    pub fn import_midi (&mut self, part: usize, notes: &[midi::Note], ppqn: u16) -> usize {
+        // Length of a step in ticks of the MIDI file
+        let step = ppqn as f32 * self.step_ticks() as f32 / MIDI_PPQN as f32;
+        let steps = &mut self.parts[part].steps;
+        for data in steps.iter_mut() {
+            *data = Electribe2Step::default();
+        }
+        let mut placed = 0;
+        let mut last = 0;
+        for note in notes {
+            let index = (note.time as f32 / step).round() as usize;
+            let Some(data) = steps.get_mut(index) else { continue };
+            let slot = [&mut data.note_1, &mut data.note_2, &mut data.note_3, &mut data.note_4]
+                .into_iter()
+                .find(|slot|**slot == 0);
+            let Some(slot) = slot else { continue };
+            *slot = note.note.min(127) + 1;
+            // Gate is measured in 1/24ths of a 16th note
+            let gate = (note.duration as f32 / step * MIDI_STEP as f32).round();
+            let gate = if gate > GATE_MAX as f32 { GATE_TIE } else { (gate as u8).max(1) };
+            data.empty    = 1;
+            data.gate     = data.gate.max(gate);
+            data.velocity = data.velocity.max(note.velocity);
+            placed += 1;
+            last = last.max(index);
+        }
+        if placed > 0 {
+            self.length = self.length.max((last / 16 + 1) as u8).min(4);
+        }
+        placed
+    }
+
+    /// Render only the motion sequences as MIDI control changes, one track per slot
+    /// whose parameter has a CC assignment. Parts are sent on channels 1-16.
+    pub fn motion_to_midi (&self) -> midi::SMF {
+        let mut smf = midi::SMF::new(MIDI_PPQN as u16);
+        smf.track(&self.name).add(0, midi::Event::tempo(self.bpm));
+        for (slot, motion) in self.motion_slots() {
+            if let Some(control) = motion.control() {
+                let mode = self.parts.get(motion.part as usize - 1).map(|part|part.motion_seq).unwrap_or_default();
+                let track = smf.track(&format!("Motion {} {}", slot + 1, motion.parameter_name()));
+                motion.to_midi(track, control, mode == MotionSeqMode::Smooth, self.steps(), self.step_ticks());
+            }
+        }
+        smf
+    }
+
+}
+
+impl Electribe2Motion {
+
+    /// Add the motion sequence to a MIDI track as control changes on the part's channel.
+    /// In smooth mode, values are interpolated between steps.
+    pub fn to_midi (
+        &self, track: &mut midi::Track, control: u8, smooth: bool, steps: usize, step: u32
+    ) {
+        let channel = self.part.saturating_sub(1) % 16;
+        let steps = &self.steps[..steps.min(self.steps.len())];
+        let mut last = None;
+        for (index, value) in steps.iter().enumerate() {
+            let time = index as u32 * step;
+            let next = if smooth { steps.get(index + 1).copied() } else { None };
+            let subdivisions = if next.is_some() { 4 } else { 1 };
+            for sub in 0..subdivisions {
+                let value = match next {
+                    Some(next) => (*value as i32 + (next as i32 - *value as i32) * sub / subdivisions) as u8,
+                    None => *value
+                };
+                if last != Some(value) {
+                    let time = time + sub as u32 * step / subdivisions as u32;
+                    track.add(time, midi::Event::Control { channel, control, value: value.min(127) });
+                    last = Some(value);
+                }
+            }
+        }
+    }
+
+}
@@ -0,0 +1,95 @@
+//! Text representation of pattern banks, for keeping them in version control.
+
+use super::*;
+
+impl Electribe2PatternBank {
+
+    /// Serialize the patterns as YAML, or as JSON if `yaml` is false
+    pub fn dump (&self, yaml: bool) -> String {
+        if yaml {
+            serde_yaml::to_string(&self.patterns).expect("could not serialize patterns")
+        } else {
+            serde_json::to_string_pretty(&self.patterns).expect("could not serialize patterns")
+        }
+    }
+
+    /// Replace the patterns with ones read from YAML or JSON. Patterns that replace
+    /// an existing one keep its binary block, so that bytes which are not part of
+    /// the text representation survive. Load into a bank read from the device,
+    /// since the text leaves out the bank header.
+    pub fn load (&mut self, text: &str, yaml: bool) -> std::result::Result<(), String> {
+        let mut patterns: Vec<Electribe2Pattern> = if yaml {
+            serde_yaml::from_str(text).map_err(|e|format!("could not parse YAML patterns: {e}"))?
+        } else {
+            serde_json::from_str(text).map_err(|e|format!("could not parse JSON patterns: {e}"))?
+        };
+        if patterns.len() > PATTERN_COUNT {
+            return Err(format!("too many patterns ({}), the maximum is {PATTERN_COUNT}", patterns.len()))
+        }
+        for (index, pattern) in patterns.iter_mut().enumerate() {
+            pattern.raw = match self.patterns.get(index) {
+                Some(existing) => existing.raw.clone(),
+                None => Electribe2Pattern::init().write()
+            };
+        }
+        self.patterns = patterns;
+        Ok(())
+    }
+}
+
+/// Whether a path has a YAML extension
+pub fn is_yaml (path: &std::path::Path) -> bool {
+    path.extension()
+        .map(|ext|matches!(ext.to_string_lossy().to_lowercase().as_str(), "yaml" | "yml"))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A bank with a non-zero header and some bytes that are not part of the text
+    fn bank () -> Electribe2PatternBank {
+        let mut bank = Electribe2PatternBank::empty();
+        bank.header[0x0000..0x0004].copy_from_slice(b"KORG");
+        for index in 0..PATTERN_COUNT {
+            let mut pattern = Electribe2Pattern::init();
+            pattern.name = format!("Pattern {index}");
+            pattern.bpm = 90.5;
+            pattern.parts[1].steps[3].note_1 = 61;
+            let mut raw = pattern.write();
+            raw[0x3000] = index as u8;
+            bank.patterns.push(Electribe2Pattern::read(&raw));
+        }
+        bank
+    }
+
+    #[test]
+    fn dump_load_write () {
+        let raw = bank().write();
+        let text = Electribe2PatternBank::read(&raw).dump(false);
+        let mut loaded = Electribe2PatternBank::read(&raw);
+        loaded.load(&text, false).unwrap();
+        assert_eq!(loaded.write(), raw);
+    }
+
+    #[test]
+    fn load_yaml () {
+        // A few patterns only, since YAML of a whole bank is slow in debug builds
+        let original = bank();
+        let text = serde_yaml::to_string(&original.patterns[..2]).unwrap();
+        let mut loaded = bank();
+        loaded.load(&text, true).unwrap();
+        assert_eq!(loaded.patterns.len(), 2);
+        assert_eq!(loaded.patterns[1].write(), original.patterns[1].write());
+    }
+
+    #[test]
+    fn load_errors () {
+        let mut loaded = bank();
+        assert!(loaded.load("[{\"name\": }]", false).is_err());
+        assert!(loaded.load("- name: [", true).is_err());
+        assert_eq!(loaded.patterns.len(), PATTERN_COUNT);
+    }
+
+}
@@ -5,7 +5,7 @@ use thatsit::{
     layouts::*,
     engines::tui::{
         *,
-        crossterm::style::Color
+        crossterm::{style::Color, event::{Event, KeyEvent, KeyCode}}
     },
     widgets::tui::*
 };
@@ -23,6 +23,7 @@ impl<E, I, O> Electribe2UI<E, I, O> {
         let mut selector = Tabbed::<Box<dyn Widget<E, I, O>>>::new(TabSide::Left, vec![]);
         selector.add("Edit patterns".into(), Box::new(Electribe2PatternsUI::new()));
         selector.add("Edit samples".into(),  Box::new(Electribe2SamplesUI::new()));
+        selector.add("Sample usage".into(),  Box::new(Electribe2UsageUI::new()));
         selector.pages.select_next();
         Self(selector)
     }
@@ -45,10 +46,18 @@ impl<E, I, O> Output<E, O> for Electribe2UI<E, I, O> {
 pub struct Electribe2PatternsUI {
     /// File explorer for selecting a pattern bank
     pub file_list: FileList,
+    /// Path of the currently loaded pattern bank
+    pub path:      Option<std::path::PathBuf>,
     /// The currently selected pattern bank
     pub bank:      Option<Electribe2PatternBank>,
     /// Selector for editing individual patterns
     pub patterns:  Tabbed<Electribe2PatternUI>,
+    /// Whether keys go to the steps of the open pattern rather than the pattern list
+    pub editing:   bool,
+    /// Whether the bank was modified since it was loaded
+    pub dirty:     bool,
+    /// Whether to ask about saving the changes before closing the bank
+    pub prompt:    bool,
 }
 
 impl<W: Write> Output<TUI<W>, [u16;2]> for Electribe2PatternsUI {
@@ -84,16 +93,39 @@ impl<W: Write> Output<TUI<W>, [u16;2]> for Electribe2PatternsUI {
                 }));
             }
 
-            Rows::new()
-                .add(" Patterns in this file:")
-                .add(Columns::new()
-                    .border(Tall, Inset)
-                    .add(patterns)
-                    .add(if self.patterns.open && let Some((_,page)) = self.patterns.pages.get() {
-                        Some(page)
-                    } else {
-                        None
-                    }))
+            let page = if self.patterns.open && let Some((_,page)) = self.patterns.pages.get() {
+                Some(page)
+            } else {
+                None
+            };
+
+            let modified = if self.dirty { " (modified)" } else { "" };
+
+            let help = if self.prompt {
+                format!(" Save changes to {:?}? y: save │ n: discard │ Esc: cancel",
+                    self.path.as_ref().map(|path|path.display().to_string()).unwrap_or_default())
+                    .on(Color::Yellow).with(Color::Black).bold()
+            } else if self.editing {
+                page.map(|page|page.help()).unwrap_or_default().with(Color::White)
+            } else {
+                " Up/Down: select │ Alt-Up/Down: move │ Enter: edit │ s: save │ Esc: close".to_string()
+                    .with(Color::White)
+            };
+
+            if self.editing {
+                Rows::new()
+                    .add(format!(" Editing pattern{modified}:"))
+                    .add(Columns::new().border(Tall, Inset).add(page))
+                    .add(help)
+            } else {
+                Rows::new()
+                    .add(format!(" Patterns in this file{modified}:"))
+                    .add(Columns::new()
+                        .border(Tall, Inset)
+                        .add(patterns)
+                        .add(page))
+                    .add(help)
+            }
 
         } else {
 
@@ -110,7 +142,44 @@ impl Input<TUIInputEvent, bool> for Electribe2PatternsUI {
 
     fn handle (&mut self, event: TUIInputEvent) -> Result<Option<bool>> {
         Ok(if self.bank.is_some() {
-            if *event == key!(Alt-Up) {
+            if self.prompt {
+                // Nothing else happens until the question is answered
+                if let Event::Key(KeyEvent { code: KeyCode::Char('y'), .. }) = *event {
+                    self.save()?;
+                    self.close();
+                } else if let Event::Key(KeyEvent { code: KeyCode::Char('n'), .. }) = *event {
+                    self.close();
+                } else if *event == key!(Esc) {
+                    self.prompt = false;
+                }
+                true
+            } else if self.editing {
+                if *event == key!(Esc) {
+                    self.editing = false;
+                    true
+                } else if self.edit(event)? {
+                    true
+                } else if let Event::Key(KeyEvent { code: KeyCode::Char('s'), .. }) = *event {
+                    self.save()?
+                } else if let Event::Key(KeyEvent { code: KeyCode::Char('q'), .. }) = *event {
+                    // Hold off quitting until the changes are saved or discarded
+                    self.prompt = self.dirty;
+                    self.dirty
+                } else {
+                    false
+                }
+            } else if *event == key!(Esc) {
+                if self.dirty {
+                    self.prompt = true;
+                } else {
+                    self.close();
+                }
+                true
+            } else if let Event::Key(KeyEvent { code: KeyCode::Char('q'), .. }) = *event {
+                // Hold off quitting until the changes are saved or discarded
+                self.prompt = self.dirty;
+                self.dirty
+            } else if *event == key!(Alt-Up) {
                 self.swap_up() && self.select_prev()
             } else if *event == key!(Alt-Down) {
                 self.swap_down() && self.select_next()
@@ -119,7 +188,11 @@ impl Input<TUIInputEvent, bool> for Electribe2PatternsUI {
             } else if *event == key!(Down) {
                 self.select_next()
             } else if *event == key!(Enter) {
-                self.patterns.open()
+                self.patterns.open();
+                self.editing = true;
+                true
+            } else if let Event::Key(KeyEvent { code: KeyCode::Char('s'), .. }) = *event {
+                self.save()?
             } else {
                 false
             }
@@ -160,26 +233,72 @@ impl Electribe2PatternsUI {
     pub fn import (&mut self, bank: &std::path::Path) {
         self.load_bank(Electribe2PatternBank::read(&super::super::read(bank)));
         self.patterns.open();
+        self.path    = Some(bank.into());
+        self.editing = false;
+        self.dirty   = false;
+    }
+
+    /// Close the pattern bank, discarding unsaved changes, and go back to the file list
+    pub fn close (&mut self) {
+        self.bank     = None;
+        self.path     = None;
+        self.patterns = Default::default();
+        self.editing  = false;
+        self.dirty    = false;
+        self.prompt   = false;
+        self.update();
+    }
+
+    /// Write the pattern bank back to the file it was loaded from
+    pub fn save (&mut self) -> Result<bool> {
+        if let (Some(bank), Some(path)) = (&self.bank, &self.path) {
+            std::fs::File::create(path)?.write_all(&bank.write())?;
+            self.dirty = false;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Pass a key to the open pattern, and copy any changes into the bank
+    pub fn edit (&mut self, event: TUIInputEvent) -> Result<bool> {
+        let Some(index) = self.selected() else { return Ok(false) };
+        let (label, page) = &mut self.patterns.pages.items_mut()[index];
+        let handled = page.handle(event)?;
+        if let Some(bank) = &mut self.bank {
+            if bank.patterns[index] != page.pattern {
+                bank.patterns[index] = page.pattern.clone();
+                *label = Self::pattern_header(index, &page.pattern);
+                self.dirty = true;
+            }
+        }
+        Ok(handled)
     }
 
     /// Load a pattern bank
     pub fn load_bank (&mut self, bank: Electribe2PatternBank) {
         let new_pages = bank.patterns.iter().enumerate()
-            .map(|(index,pattern)|(Self::format_header(
-                index + 1,
-                pattern.name.trim(),
-                pattern.bpm as u64,
-                pattern.length,
-                pattern.beats,
-                pattern.key,
-                pattern.scale,
-            ), Electribe2PatternUI::new(pattern))).collect::<Vec<_>>();
+            .map(|(index,pattern)|(Self::pattern_header(index, pattern), Electribe2PatternUI::new(pattern)))
+            .collect::<Vec<_>>();
         self.patterns.scroll.total = new_pages.len();
         self.patterns.pages.replace(new_pages);
         self.patterns.pages.select_next();
         self.bank = Some(bank);
     }
 
+    /// Format the row of a pattern in the pattern list
+    pub fn pattern_header (index: usize, pattern: &Electribe2Pattern) -> String {
+        Self::format_header(
+            index + 1,
+            pattern.name.trim(),
+            pattern.bpm as u64,
+            pattern.length,
+            pattern.beats,
+            pattern.key,
+            pattern.scale,
+        )
+    }
+
     /// Format a row in the pattern list
     pub fn format_header (
         index:  impl Display,
@@ -191,7 +310,7 @@ impl Electribe2PatternsUI {
         scale:  impl Display
     ) -> String {
         format!(
-            "{:>4} │ {:<16} │ {:>5} │ {:>6} │ {:>5} │ {:>3} │ {:>5} │",
+            "{:>4} │ {:<16} │ {:>5} │ {:>6} │ {:<12} │ {:<3} │ {:<22} │",
             index, name, bpm, length, beats, key, scale,
         )
     }
@@ -202,6 +321,9 @@ impl Electribe2PatternsUI {
             if index > 0 {
                 //self.patterns.tabs.items.items.swap(index, index-1);
                 self.patterns.pages.items_mut().swap(index, index-1);
+                if let Some(bank) = &mut self.bank {
+                    bank.swap(index, index-1);
+                }
                 return true
             }
         }
@@ -214,6 +336,9 @@ impl Electribe2PatternsUI {
             if index < self.len() - 1 {
                 //self.patterns.tabs.items.items.swap(index, index+1);
                 self.patterns.pages.items_mut().swap(index, index+1);
+                if let Some(bank) = &mut self.bank {
+                    bank.swap(index, index+1);
+                }
                 return true
             }
         }
@@ -245,11 +370,47 @@ impl Electribe2PatternsUI {
     }
 }
 
+/// Which part of the pattern editor receives keys
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Electribe2Focus {
+    /// The parameters of the pattern
+    Pattern,
+    /// The parameters of the current part
+    Part,
+    /// The piano roll of the current part
+    #[default]
+    Steps,
+}
+
+/// Editor for the parameters and parts of a pattern
 #[derive(Debug, Default)]
-pub struct Electribe2PatternUI(pub Electribe2Pattern, Tabbed<Electribe2PartUI>);
+pub struct Electribe2PatternUI {
+    /// The pattern being edited
+    pub pattern: Electribe2Pattern,
+    /// Editors for the pattern's parts
+    pub parts:   Tabbed<Electribe2PartUI>,
+    /// Which part of the editor receives keys
+    pub focus:   Electribe2Focus,
+    /// Focused parameter, counting through FIELDS row by row
+    pub field:   usize,
+}
 
 impl Electribe2PatternUI {
 
+    /// Rows of parameters, by their labels in `Electribe2Pattern::fields`, and their widths
+    pub const FIELDS: [&'static [(&'static str, Unit)]; 4] = [
+        &[("Name",     20), ("Level",     10)],
+        &[("BPM",      10), ("Swing",     10), ("Length",    10), ("Beats", 14)],
+        &[("Key",      10), ("Scale",     24), ("Chord set", 10), ("MFX",   24)],
+        &[("Gate arp", 10), ("Alt 13/14", 10), ("Alt 15/16", 10)],
+    ];
+
+    /// Key bindings of the parameter editors
+    pub const HELP: &'static str = concat!(
+        " Left/Right: select │ Up/Down: change │ PgUp/PgDn: change by 10 │ type: name │",
+        " Tab: focus │ Alt-Left/Right: part │ s: save │ Esc: back"
+    );
+
     pub fn new (
         pattern: &Electribe2Pattern
     ) -> Self {
@@ -259,102 +420,393 @@ impl Electribe2PatternUI {
         }
         parts.pages.select_next();
         parts.open();
-        Self(pattern.clone(), parts)
+        Self { pattern: pattern.clone(), parts, ..Self::default() }
+    }
+
+    /// Key bindings for the focused part of the editor
+    pub fn help (&self) -> String {
+        match self.focus {
+            Electribe2Focus::Steps => Electribe2PartUI::HELP,
+            _ => Self::HELP
+        }.to_string()
+    }
+
+    /// Move the focus to the pattern parameters, the part parameters or the piano roll
+    pub fn cycle_focus (&mut self) -> bool {
+        self.focus = match self.focus {
+            Electribe2Focus::Pattern => Electribe2Focus::Part,
+            Electribe2Focus::Part    => Electribe2Focus::Steps,
+            Electribe2Focus::Steps   => Electribe2Focus::Pattern,
+        };
+        for (_, part) in self.parts.pages.items_mut().iter_mut() {
+            part.focus = self.focus;
+        }
+        true
+    }
+
+    /// Select, change or type into the pattern parameters
+    pub fn edit_field (&mut self, event: TUIInputEvent) -> bool {
+        let labels = Self::FIELDS.iter().flat_map(|row|row.iter().map(|(label, _)|*label)).collect::<Vec<_>>();
+        let label = labels[self.field.min(labels.len() - 1)];
+        if *event == key!(Left) && self.field > 0 {
+            self.field -= 1;
+            true
+        } else if *event == key!(Right) && self.field + 1 < labels.len() {
+            self.field += 1;
+            true
+        } else if *event == key!(Up) {
+            self.pattern.adjust(label, 1)
+        } else if *event == key!(Down) {
+            self.pattern.adjust(label, -1)
+        } else if *event == key!(PageUp) {
+            self.pattern.adjust(label, 10)
+        } else if *event == key!(PageDown) {
+            self.pattern.adjust(label, -10)
+        } else if label != "Name" {
+            false
+        } else if *event == key!(Backspace) {
+            let mut name = self.pattern.name.trim_end().to_string();
+            name.pop();
+            self.pattern.set_name(&name);
+            true
+        } else if let Event::Key(KeyEvent { code: KeyCode::Char(c), .. }) = *event {
+            let name = format!("{}{c}", self.pattern.name.trim_end());
+            self.pattern.set_name(&name);
+            true
+        } else {
+            false
+        }
     }
 
     pub fn field <U, V> (
-        label: &str, width: U, value: impl Display
+        label: &str, width: U, value: impl Display, focused: bool
     ) -> Fixed<U, V> {
+        let style1 = |s: String|s.on(Color::Yellow).with(Color::Black).bold();
+        let style2 = |s: String|s.with(Color::Green);
         Fixed::Y(3, Layers::new()
             .add(Fixed::X(width, label.to_string().style(&|s: String|s.with(Color::White).bold())))
             .add(Fixed::X(width, format!(" {}", value.to_string())
-                .style(&|s: String|s.with(Color::Green))
+                .style(&if focused { style1 } else { style2 })
                 .border(Tall, Inset))))
     }
 
 }
 
+impl Input<TUIInputEvent, bool> for Electribe2PatternUI {
+
+    /// Tab moves the focus and Alt-Left/Right switch between parts; other keys go to
+    /// the focused editor. Changes to the current part are copied into the pattern.
+    fn handle (&mut self, event: TUIInputEvent) -> Result<Option<bool>> {
+        Ok(if *event == key!(Tab) {
+            self.cycle_focus()
+        } else if *event == key!(Alt-Right) {
+            self.parts.select_next()
+        } else if *event == key!(Alt-Left) {
+            self.parts.select_prev()
+        } else if self.focus == Electribe2Focus::Pattern {
+            self.edit_field(event)
+        } else if let Some(index) = self.parts.selected() {
+            let focus = self.focus;
+            let part = &mut self.parts.pages.items_mut()[index].1;
+            let handled = if focus == Electribe2Focus::Part {
+                part.edit_field(event)
+            } else {
+                part.handle(event)?
+            };
+            self.pattern.parts[index] = part.part.clone();
+            handled
+        } else {
+            false
+        })
+    }
+
+}
+
 impl<W: Write> Output<TUI<W>, [u16;2]> for Electribe2PatternUI {
 
     fn render (&self, context: &mut TUI<W>) -> Result<Option<[u16;2]>> {
+        let values = self.pattern.fields();
+        let mut fields = Rows::new();
+        let mut index = 0;
+        for row in Self::FIELDS.iter() {
+            let mut columns = Columns::new();
+            for (label, width) in row.iter() {
+                let focused = self.focus == Electribe2Focus::Pattern && self.field == index;
+                let mut value = values.iter()
+                    .find(|(name, _)|name == label)
+                    .map(|(_, value)|value.clone())
+                    .unwrap_or_default();
+                if focused && *label == "Name" {
+                    value.push('_');
+                }
+                columns = columns.add(Self::field(label, *width, value, focused));
+                index += 1;
+            }
+            fields = fields.add(columns);
+        }
         Columns::new()
             .add(1)
-            .add(Rows::new()
-                .add(Columns::new()
-                    .add(Self::field("Pattern name", 20, &self.0.name))
-                    .add(Self::field("Level",        10, &self.0.level)))
-                .add(Columns::new()
-                    .add(Self::field("BPM",          10, format!("{:>5.1}", self.0.bpm)))
-                    .add(Self::field("Swing",        10, &self.0.swing))
-                    .add(Self::field("Length",       10, &self.0.length))
-                    .add(Self::field("Beats",        10, &self.0.beats)))
-                .add(Columns::new()
-                    .add(Self::field("Key",          10, &self.0.key))
-                    .add(Self::field("Scale",        10, &self.0.scale))
-                    .add(Self::field("Chords",       10, &self.0.chord_set))
-                    .add(Self::field("MFX",          10, &self.0.mfx_type)))
-                .add(Columns::new()
-                    .add(Self::field("Gate arp",     10, &self.0.gate_arp))
-                    .add(Self::field("Alt 13/14",    10, &self.0.alt_13_14))
-                    .add(Self::field("Alt 15/16",    10, &self.0.alt_15_16)))
+            .add(fields
                 .add(2)
-                .add(&self.1))
+                .add(&self.parts))
             .render(context)
     }
 
 }
 
-#[derive(Debug, Default)]
-pub struct Electribe2PartUI(pub Electribe2Part);
+/// Editor for the parameters and steps of a part
+#[derive(Debug)]
+pub struct Electribe2PartUI {
+    /// The part being edited
+    pub part:   Electribe2Part,
+    /// Step under the cursor (0-63)
+    pub step:   usize,
+    /// MIDI note under the cursor
+    pub note:   u8,
+    /// Lowest note shown in the piano roll
+    pub offset: u8,
+    /// Note slot of the step (0-3) that is transposed or cleared
+    pub slot:   usize,
+    /// Which part of the pattern editor receives keys
+    pub focus:  Electribe2Focus,
+    /// Focused parameter, counting through FIELDS row by row
+    pub field:  usize,
+}
+
+impl Default for Electribe2PartUI {
+    fn default () -> Self {
+        Self {
+            part:   Electribe2Part::default(),
+            step:   0,
+            note:   60,
+            offset: 48,
+            slot:   0,
+            focus:  Electribe2Focus::default(),
+            field:  0,
+        }
+    }
+}
 
 impl Electribe2PartUI {
+
+    /// Rows of parameters: short labels, and the labels in `Electribe2Part::fields`
+    pub const FIELDS: [[(&'static str, &'static str); 3]; 6] = [
+        [("Sample", "Sample"),  ("Pitch",  "Pitch"),     ("Osc",   "Osc edit")],
+        [("Filter", "Filter"),  ("Freq",   "Cutoff"),    ("Res",   "Resonance")],
+        [("Mod",    "Mod"),     ("Speed",  "Mod speed"), ("Depth", "Mod depth")],
+        [("IFX",    "IFX on"),  ("Type",   "IFX"),       ("Param", "IFX param")],
+        [("Attack", "Attack"),  ("Decay",  "Decay"),     ("Level", "Level")],
+        [("Pan",    "Pan"),     ("Voice",  "Voice"),     ("Glide", "Glide")],
+    ];
+
+    /// Key bindings of the piano roll
+    pub const HELP: &'static str = concat!(
+        " Arrows: move │ PgUp/PgDn: octave │ Enter: toggle note │ 1-4: chord note │",
+        " Alt-Up/Down: transpose │ Del: clear │ +/-: velocity │ [/]: gate │",
+        " Tab: focus │ Alt-Left/Right: part │ s: save │ Esc: back"
+    );
+
     pub fn new (part: &Electribe2Part) -> Self {
-        Self(part.clone())
+        Self { part: part.clone(), ..Self::default() }
+    }
+
+    /// Move the cursor by a number of steps and notes, scrolling the piano roll to keep it in view
+    pub fn move_cursor (&mut self, steps: isize, notes: isize) -> bool {
+        let step = (self.step as isize + steps).clamp(0, self.part.steps.len() as isize - 1) as usize;
+        let note = (self.note as isize + notes).clamp(0, 127) as u8;
+        if (step, note) == (self.step, self.note) {
+            return false
+        }
+        self.step = step;
+        self.note = note;
+        // Rows show two notes each, so keep the lowest note shown even
+        let visible = 2 * laterna::ROWS as u8;
+        if note < self.offset {
+            self.offset = note & !1;
+        } else if note >= self.offset + visible {
+            self.offset = (note + 2 - visible) & !1;
+        }
+        true
+    }
+
+    /// Add or remove the note under the cursor
+    pub fn toggle_note (&mut self) -> bool {
+        let note = self.note;
+        self.part.steps[self.step].toggle_note(note)
+    }
+
+    /// Select which of the step's four notes is transposed or cleared
+    pub fn select_slot (&mut self, slot: usize) -> bool {
+        self.slot = slot;
+        true
+    }
+
+    /// Transpose the selected note of the step, or set it to the note under the cursor if empty
+    pub fn transpose (&mut self, delta: isize) -> bool {
+        let cursor = self.note;
+        let step = &mut self.part.steps[self.step];
+        let note = match step.note(self.slot) {
+            Some(note) => (note as isize + delta).clamp(0, 127) as u8,
+            None => cursor
+        };
+        step.set_note(self.slot, Some(note));
+        true
+    }
+
+    /// Clear the selected note of the step
+    pub fn clear_slot (&mut self) -> bool {
+        let step = &mut self.part.steps[self.step];
+        if step.note(self.slot).is_some() {
+            step.set_note(self.slot, None);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Change the velocity of the step, if it has notes
+    pub fn adjust_velocity (&mut self, delta: isize) -> bool {
+        let step = &mut self.part.steps[self.step];
+        if step.notes().count() == 0 {
+            return false
+        }
+        step.velocity = (step.velocity as isize + delta).clamp(1, 127) as u8;
+        true
+    }
+
+    /// Change the gate of the step, if it has notes. Past the longest gate, the note is tied.
+    pub fn adjust_gate (&mut self, delta: isize) -> bool {
+        let step = &mut self.part.steps[self.step];
+        if step.notes().count() == 0 {
+            return false
+        }
+        let gate = if step.gate >= GATE_TIE { GATE_MAX as isize + 1 } else { step.gate as isize };
+        let gate = (gate + delta).clamp(1, GATE_MAX as isize + 1);
+        step.gate = if gate > GATE_MAX as isize { GATE_TIE } else { gate as u8 };
+        true
+    }
+
+    /// Select or change the part parameters
+    pub fn edit_field (&mut self, event: TUIInputEvent) -> bool {
+        let count = Self::FIELDS.len() * 3;
+        let (_, label) = Self::FIELDS[self.field / 3][self.field % 3];
+        if *event == key!(Left) && self.field > 0 {
+            self.field -= 1;
+            true
+        } else if *event == key!(Right) && self.field + 1 < count {
+            self.field += 1;
+            true
+        } else if *event == key!(Up) {
+            self.part.adjust(label, 1)
+        } else if *event == key!(Down) {
+            self.part.adjust(label, -1)
+        } else if *event == key!(PageUp) {
+            self.part.adjust(label, 10)
+        } else if *event == key!(PageDown) {
+            self.part.adjust(label, -10)
+        } else {
+            false
+        }
     }
 
     pub fn field <T, U> (
-        label: &str, value: impl Display
+        label: &str, value: impl Display, focused: bool
     ) -> Fixed<T, U> {
-        let white = |s: String|s.with(Color::White).bold();
-        let green = |s: String|s.with(Color::Green);
-        Fixed::XY((10, 3), Layers::new()
+        let white  = |s: String|s.with(Color::White).bold();
+        let style1 = |s: String|s.on(Color::Yellow).with(Color::Black).bold();
+        let style2 = |s: String|s.with(Color::Green);
+        Fixed::XY((12, 3), Layers::new()
             .add(Columns::new()
                 .add((2, 1))
                 .add(label.to_string().style(&white)))
             .add(format!(" {}", value.to_string())
-                .style(&green)
+                .style(&if focused { style1 } else { style2 })
                 .border(Tall, Inset)))
     }
 
     pub fn layout_metadata <T, U> (&self) -> Rows<T, U> {
-        Rows::new()
-            .add(Columns::new()
-                .add(Self::field("Sample", &self.0.sample))
-                .add(Self::field("Pitch",  &self.0.pitch))
-                .add(Self::field("Osc",    &self.0.pitch)))
-            .add(1)
-            .add(Columns::new()
-                .add(Self::field("Filter", &self.0.filter_type))
-                .add(Self::field("Freq",   &self.0.filter_type))
-                .add(Self::field("Res",    &self.0.filter_type)))
-            .add(1)
-            .add(Columns::new()
-                .add(Self::field("Mod",    &self.0.filter_type))
-                .add(Self::field("Speed",  &self.0.filter_type))
-                .add(Self::field("Depth",  &self.0.filter_type)))
-            .add(1)
-            .add(Columns::new()
-                .add(Self::field("IFX",    &self.0.filter_type))
-                .add(Self::field("Type",   &self.0.filter_type))
-                .add(Self::field("Param",  &self.0.filter_type)))
+        let values = self.part.fields();
+        let mut rows = Rows::new();
+        for (y, row) in Self::FIELDS.iter().enumerate() {
+            let mut columns = Columns::new();
+            for (x, (short, label)) in row.iter().enumerate() {
+                let value = values.iter()
+                    .find(|(name, _)|name == label)
+                    .map(|(_, value)|value.clone())
+                    .unwrap_or_default();
+                let focused = self.focus == Electribe2Focus::Part && self.field == y * 3 + x;
+                columns = columns.add(Self::field(short, value, focused));
+            }
+            rows = rows.add(columns).add(1);
+        }
+        rows
     }
 
     pub fn layout_piano_roll (&self) -> laterna::PianoRoll {
-        let mut events = vec![];
-        for (index, step) in self.0.steps.iter().enumerate() {
-            events.push((index, step.note_1 as usize));
+        let mut notes = vec![];
+        for (index, step) in self.part.steps.iter().enumerate() {
+            notes.extend(step.notes().map(|note|(index, note as usize)));
+        }
+        laterna::PianoRoll {
+            notes,
+            steps:  self.part.steps.len(),
+            offset: self.offset as usize,
+            cursor: (self.focus == Electribe2Focus::Steps).then_some((self.step, self.note as usize)),
+        }
+    }
+
+    pub fn layout_step <T, U> (&self) -> Columns<T, U> {
+        let step = &self.part.steps[self.step];
+        let mut columns = Columns::new()
+            .add(Self::field("Step", format!("{}", self.step + 1), false))
+            .add(Self::field("Cursor", note_name(self.note), false));
+        for slot in 0..4 {
+            let label = if slot == self.slot { format!("[Note {}]", slot + 1) } else { format!("Note {}", slot + 1) };
+            columns = columns.add(Self::field(&label, step.note(slot).map(note_name).unwrap_or("-".into()), false));
         }
-        laterna::PianoRoll(events)
+        let gate = if step.gate >= GATE_TIE { "Tie".to_string() } else { step.gate.to_string() };
+        columns
+            .add(Self::field("Gate", gate, false))
+            .add(Self::field("Velocity", step.velocity, false))
+    }
+
+}
+
+impl Input<TUIInputEvent, bool> for Electribe2PartUI {
+
+    fn handle (&mut self, event: TUIInputEvent) -> Result<Option<bool>> {
+        Ok(if *event == key!(Alt-Up) {
+            self.transpose(1)
+        } else if *event == key!(Alt-Down) {
+            self.transpose(-1)
+        } else if *event == key!(Up) {
+            self.move_cursor(0, 1)
+        } else if *event == key!(Down) {
+            self.move_cursor(0, -1)
+        } else if *event == key!(Left) {
+            self.move_cursor(-1, 0)
+        } else if *event == key!(Right) {
+            self.move_cursor(1, 0)
+        } else if *event == key!(PageUp) {
+            self.move_cursor(0, 12)
+        } else if *event == key!(PageDown) {
+            self.move_cursor(0, -12)
+        } else if *event == key!(Enter) {
+            self.toggle_note()
+        } else if *event == key!(Delete) {
+            self.clear_slot()
+        } else if let Event::Key(KeyEvent { code: KeyCode::Char(c), .. }) = *event {
+            match c {
+                '1'..='4' => self.select_slot(c as usize - '1' as usize),
+                '+' | '=' => self.adjust_velocity(4),
+                '-'       => self.adjust_velocity(-4),
+                ']'       => self.adjust_gate(3),
+                '['       => self.adjust_gate(-3),
+                _         => false
+            }
+        } else {
+            false
+        })
     }
 
 }
@@ -366,7 +818,10 @@ impl<W: Write> Output<TUI<W>, [u16;2]> for Electribe2PartUI {
             .border(Tall, Inset)
             .add(self.layout_metadata())
             .add(1)
-            .add(self.layout_piano_roll())
+            .add(Rows::new()
+                .add(self.layout_piano_roll())
+                .add(1)
+                .add(self.layout_step()))
             .render(engine)
     }
 
@@ -404,3 +859,124 @@ impl Electribe2SamplesUI {
         self
     }
 }
+
+/// UI for cross-referencing a pattern bank with a sample bank
+#[derive(Debug, Default)]
+pub struct Electribe2UsageUI {
+    /// File explorer for selecting the pattern bank, then the sample bank
+    pub file_list: FileList,
+    /// The loaded pattern bank
+    pub patterns:  Option<Electribe2PatternBank>,
+    /// The loaded sample bank
+    pub samples:   Option<Electribe2SampleBank>,
+    /// Lines of the usage report
+    pub report:    Vec<String>,
+    /// First visible line of the report
+    pub offset:    usize,
+    /// Why the last selected file could not be imported
+    pub error:     Option<String>,
+}
+
+impl<W: Write> Output<TUI<W>, [u16;2]> for Electribe2UsageUI {
+
+    fn render (&self, engine: &mut TUI<W>) -> Result<Option<[u16;2]>> {
+        if self.samples.is_some() {
+            let max_height = engine.area.h().saturating_sub(4) as usize;
+            let mut rows = Rows::new();
+            for line in self.report.iter().skip(self.offset).take(max_height) {
+                rows = rows.add(line.clone().with(Color::White));
+            }
+            Rows::new()
+                .add(" User samples and the pattern:part pairs that play them:")
+                .add(Rows::new().border(Tall, Inset).add(rows))
+        } else {
+            Rows::new()
+                .add(if self.patterns.is_some() { " Select sample bank:" } else { " Select pattern bank:" })
+                .add(Rows::new().border(Tall, Inset).add(&self.file_list))
+                .add(format!(" {}", self.error.as_deref().unwrap_or("")).with(Color::Red))
+        }.render(engine)
+    }
+
+}
+
+impl Input<TUIInputEvent, bool> for Electribe2UsageUI {
+
+    fn handle (&mut self, event: TUIInputEvent) -> Result<Option<bool>> {
+        Ok(if self.samples.is_some() {
+            if *event == key!(Up) && self.offset > 0 {
+                self.offset -= 1;
+                true
+            } else if *event == key!(Down) && self.offset + 1 < self.report.len() {
+                self.offset += 1;
+                true
+            } else {
+                false
+            }
+        } else {
+            self.file_list.handle(event)? || if_key!(event => Enter => {
+                if let Some(FileEntry { path, is_dir, .. }) = self.file_list.selected() {
+                    if *is_dir {
+                        std::env::set_current_dir(path)?;
+                        self.update();
+                    } else {
+                        self.import(&std::path::PathBuf::from(path));
+                    }
+                    true
+                } else {
+                    false
+                }
+            })
+        })
+    }
+
+}
+
+impl Electribe2UsageUI {
+
+    pub fn new () -> Self {
+        let mut new = Self::default();
+        new.update();
+        new
+    }
+
+    /// Update the file list
+    pub fn update (&mut self) {
+        self.file_list.update();
+    }
+
+    /// Load the pattern bank, or the sample bank if the pattern bank is already loaded
+    pub fn import (&mut self, path: &std::path::Path) {
+        let data = crate::read(path);
+        if self.patterns.is_none() {
+            self.patterns = Some(Electribe2PatternBank::read(&data));
+        } else {
+            match Electribe2SampleBank::read(&data) {
+                Ok(samples) => {
+                    self.samples = Some(samples);
+                    self.error   = None;
+                    self.update_report();
+                },
+                Err(error) => {
+                    self.error = Some(format!("Could not read {path:?}: {error}"));
+                }
+            }
+        }
+    }
+
+    /// List each user sample with the patterns and parts that play it
+    pub fn update_report (&mut self) {
+        let (Some(patterns), Some(samples)) = (&self.patterns, &self.samples) else { return };
+        let usage = patterns.sample_usage();
+        let format_users = |number: u16|usage.get(&number)
+            .map(|users|format_usage(users))
+            .unwrap_or_else(||"unused".into());
+        self.report = samples.samples.iter()
+            .filter(|sample|sample.number >= USER_SAMPLES_START)
+            .map(|sample|format!(" {:<28} {}", samples.describe(sample.number), format_users(sample.number)))
+            .chain(samples.missing(patterns).into_iter()
+                .map(|number|format!(" {:<28} {}", samples.describe(number), format_users(number))))
+            .collect();
+        self.offset = 0;
+    }
+
+}
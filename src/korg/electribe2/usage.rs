@@ -0,0 +1,258 @@
+//! Cross-referencing of pattern banks with sample banks.
+
+use super::*;
+use std::collections::BTreeMap;
+
+impl Electribe2Part {
+    /// Number of the part's oscillator or sample as displayed on the device
+    pub fn sample_number (&self) -> u16 {
+        self.sample.number()
+    }
+    /// Set the oscillator or sample by its displayed number
+    pub fn set_sample_number (&mut self, number: u16) {
+        self.sample = Oscillator::from_number(number);
+    }
+    /// Whether the part plays a user sample rather than a factory oscillator
+    pub fn uses_user_sample (&self) -> bool {
+        self.sample.is_user_sample()
+    }
+}
+
+impl Electribe2PatternBank {
+
+    /// Patterns and parts (both zero-based) that play each user sample, by sample number
+    pub fn sample_usage (&self) -> BTreeMap<u16, Vec<(usize, usize)>> {
+        let mut usage: BTreeMap<u16, Vec<(usize, usize)>> = BTreeMap::new();
+        for (pattern_index, pattern) in self.patterns.iter().enumerate() {
+            for (part_index, part) in pattern.parts.iter().enumerate() {
+                if part.uses_user_sample() {
+                    usage.entry(part.sample_number()).or_default().push((pattern_index, part_index));
+                }
+            }
+        }
+        usage
+    }
+
+    /// Point all parts that play an old sample number at the new one.
+    /// Returns the number of parts that were changed.
+    pub fn renumber_samples (&mut self, renumbered: &BTreeMap<u16, u16>) -> usize {
+        let mut changed = 0;
+        for pattern in self.patterns.iter_mut() {
+            for part in pattern.parts.iter_mut() {
+                if let Some(number) = renumbered.get(&part.sample_number()) {
+                    part.set_sample_number(*number);
+                    changed += 1;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Point all parts that play a sample at the oscillator of an init part.
+    /// Returns the patterns and parts (both zero-based) that were reset.
+    pub fn reset_sample (&mut self, number: u16) -> Vec<(usize, usize)> {
+        let oscillator = Electribe2Part::init().sample;
+        let mut reset = vec![];
+        for (pattern_index, pattern) in self.patterns.iter_mut().enumerate() {
+            for (part_index, part) in pattern.parts.iter_mut().enumerate() {
+                if part.sample_number() == number {
+                    part.sample = oscillator;
+                    reset.push((pattern_index, part_index));
+                }
+            }
+        }
+        reset
+    }
+
+}
+
+impl Electribe2SampleBank {
+
+    /// Find a sample by its displayed number
+    pub fn get (&self, number: u16) -> Option<&Electribe2Sample> {
+        self.samples.iter().find(|sample|sample.number == number)
+    }
+
+    /// Describe the oscillator or sample played by a part
+    pub fn describe (&self, number: u16) -> String {
+        match self.get(number) {
+            Some(sample) => format!("{:>3} {} ({})", number, sample.name, sample.category_name()),
+            None if number >= USER_SAMPLES_START => format!("{number:>3} (missing)"),
+            None => format!("{number:>3}")
+        }
+    }
+
+    /// User samples that are not played by any part of any pattern
+    pub fn unused (&self, patterns: &Electribe2PatternBank) -> Vec<&Electribe2Sample> {
+        let usage = patterns.sample_usage();
+        self.samples.iter()
+            .filter(|sample|sample.number >= USER_SAMPLES_START && !usage.contains_key(&sample.number))
+            .collect()
+    }
+
+    /// Sample numbers that are played by some part but missing from the bank
+    pub fn missing (&self, patterns: &Electribe2PatternBank) -> Vec<u16> {
+        patterns.sample_usage().into_keys()
+            .filter(|number|self.get(*number).is_none())
+            .collect()
+    }
+
+    /// Remove a user sample. Parts that still play it must be reset separately.
+    pub fn remove (&mut self, number: u16) -> Option<Electribe2Sample> {
+        let index = self.samples.iter().position(|sample|sample.number == number)?;
+        Some(self.samples.remove(index))
+    }
+
+    /// Swap the numbers of two samples. Returns the renumbering to apply to patterns.
+    pub fn swap (&mut self, a: u16, b: u16) -> BTreeMap<u16, u16> {
+        let mut renumbered = BTreeMap::new();
+        for sample in self.samples.iter_mut() {
+            if sample.number == a {
+                sample.set_number(b);
+                renumbered.insert(a, b);
+            } else if sample.number == b {
+                sample.set_number(a);
+                renumbered.insert(b, a);
+            }
+        }
+        renumbered
+    }
+
+    /// Number the user samples consecutively from 501, keeping their order.
+    /// Returns the renumbering to apply to patterns.
+    pub fn compact (&mut self) -> BTreeMap<u16, u16> {
+        let mut renumbered = BTreeMap::new();
+        let mut user = self.samples.iter_mut()
+            .filter(|sample|sample.number >= USER_SAMPLES_START)
+            .collect::<Vec<_>>();
+        user.sort_by_key(|sample|sample.number);
+        for (index, sample) in user.into_iter().enumerate() {
+            let number = USER_SAMPLES_START + index as u16;
+            if sample.number != number {
+                renumbered.insert(sample.number, number);
+                sample.set_number(number);
+            }
+        }
+        renumbered
+    }
+
+}
+
+/// Combine two successive renumberings into one that takes each old number
+/// straight to its final number
+pub fn chain_renumbering (first: &BTreeMap<u16, u16>, then: &BTreeMap<u16, u16>) -> BTreeMap<u16, u16> {
+    let mut chained = BTreeMap::new();
+    for (old, new) in first.iter() {
+        chained.insert(*old, then.get(new).copied().unwrap_or(*new));
+    }
+    for (old, new) in then.iter() {
+        // Numbers that the first renumbering moved a sample into were not taken before it
+        if !first.contains_key(old) && !first.values().any(|number|number == old) {
+            chained.insert(*old, *new);
+        }
+    }
+    chained.retain(|old, new|old != new);
+    chained
+}
+
+/// List patterns and parts (both zero-based) as PATTERN:PART, numbered from 1
+pub fn format_usage (users: &[(usize, usize)]) -> String {
+    users.iter()
+        .map(|(pattern, part)|format!("{}:{}", pattern + 1, part + 1))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Two patterns: the first plays sample 501 on part 1 and 503 on part 2,
+    /// the second plays 503 on part 16 and oscillator 20 on part 3.
+    fn patterns () -> Electribe2PatternBank {
+        let mut bank = Electribe2PatternBank {
+            patterns: vec![Electribe2Pattern::init(), Electribe2Pattern::init()],
+            ..Electribe2PatternBank::default()
+        };
+        bank.patterns[0].parts[0].set_sample_number(501);
+        bank.patterns[0].parts[1].set_sample_number(503);
+        bank.patterns[1].parts[15].set_sample_number(503);
+        bank.patterns[1].parts[2].set_sample_number(20);
+        bank
+    }
+
+    fn samples (numbers: &[u16]) -> Electribe2SampleBank {
+        Electribe2SampleBank {
+            samples: numbers.iter().map(|number|Electribe2Sample {
+                number:   *number,
+                name:     format!("Sample {number}"),
+                category: 2,
+                ..Electribe2Sample::default()
+            }).collect()
+        }
+    }
+
+    #[test]
+    fn sample_usage () {
+        let patterns = patterns();
+        let usage = patterns.sample_usage();
+        assert_eq!(usage.keys().copied().collect::<Vec<_>>(), vec![501, 503]);
+        assert_eq!(usage[&503], vec![(0, 1), (1, 15)]);
+        assert_eq!(format_usage(&usage[&503]), "1:2 2:16");
+        let samples = samples(&[20, 501, 502]);
+        assert_eq!(samples.unused(&patterns).iter().map(|sample|sample.number).collect::<Vec<_>>(), vec![502]);
+        assert_eq!(samples.missing(&patterns), vec![503]);
+        assert_eq!(samples.describe(501), "501 Sample 501 (Kick)");
+        assert_eq!(samples.describe(503), "503 (missing)");
+        assert_eq!(samples.describe(7), "  7");
+    }
+
+    #[test]
+    fn renumber_and_reset () {
+        let mut patterns = patterns();
+        let renumbered = BTreeMap::from([(501, 503), (503, 501)]);
+        assert_eq!(patterns.renumber_samples(&renumbered), 3);
+        assert_eq!(patterns.patterns[0].parts[0].sample_number(), 503);
+        assert_eq!(patterns.patterns[1].parts[15].sample_number(), 501);
+        assert_eq!(patterns.reset_sample(501), vec![(0, 1), (1, 15)]);
+        assert_eq!(patterns.patterns[1].parts[15].sample, Electribe2Part::init().sample);
+        assert_eq!(patterns.sample_usage().len(), 1);
+    }
+
+    #[test]
+    fn swap_and_compact () {
+        let mut samples = samples(&[20, 501, 504, 510]);
+        assert_eq!(samples.swap(501, 510), BTreeMap::from([(501, 510), (510, 501)]));
+        assert_eq!(samples.get(510).unwrap().name, "Sample 501");
+        // Swapping with a free number moves the sample
+        assert_eq!(samples.swap(504, 505), BTreeMap::from([(504, 505)]));
+        assert!(samples.remove(20).is_some());
+        assert!(samples.remove(20).is_none());
+        assert_eq!(samples.compact(), BTreeMap::from([(505, 502), (510, 503)]));
+        let numbers = samples.samples.iter().map(|sample|sample.number).collect::<Vec<_>>();
+        assert_eq!(numbers, vec![503, 502, 501]);
+        assert_eq!(samples.get(503).unwrap().import_number, 503);
+        assert_eq!(samples.compact(), BTreeMap::new());
+    }
+
+    #[test]
+    fn chain_renumbering () {
+        // A swap followed by compacting
+        let swap    = BTreeMap::from([(501, 510), (510, 501)]);
+        let compact = BTreeMap::from([(510, 502)]);
+        assert_eq!(super::chain_renumbering(&swap, &compact), BTreeMap::from([(501, 502), (510, 501)]));
+        // A number that the first renumbering left alone
+        let first = BTreeMap::from([(501, 505)]);
+        let then  = BTreeMap::from([(505, 501), (507, 502)]);
+        assert_eq!(super::chain_renumbering(&first, &then), BTreeMap::from([(507, 502)]));
+        // Applying the chained renumbering equals applying both in turn
+        let mut once = patterns();
+        let mut twice = patterns();
+        let first = BTreeMap::from([(501, 503), (503, 501)]);
+        let then  = BTreeMap::from([(503, 502)]);
+        once.renumber_samples(&super::chain_renumbering(&first, &then));
+        twice.renumber_samples(&first);
+        twice.renumber_samples(&then);
+        assert_eq!(once.sample_usage(), twice.sample_usage());
+    }
+}
@@ -0,0 +1,406 @@
+use crate::triton::*;
+use crate::wav::wav_root_key;
+use std::io::Write;
+
+#[derive(clap::Subcommand, Clone)]
+pub enum Triton {
+    /// List and edit the contents of a PCG file
+    PCG {
+        /// The .PCG file
+        path: std::path::PathBuf,
+        /// Only list one kind of bank: programs, combis, drums or arps
+        #[clap(long)]
+        only: Option<TritonBankKind>,
+        /// Kind of entries to copy, swap, rename or init: programs, combis, drums or arps
+        #[clap(long, default_value = "programs")]
+        kind: TritonBankKind,
+        /// PCG file to copy entries from; entries are copied within the same file if omitted
+        #[clap(long)]
+        from: Option<std::path::PathBuf>,
+        /// Copy an entry: --copy FROM TO, e.g. --copy A000 B010
+        #[clap(long, num_args = 2)]
+        copy: Vec<TritonSlot>,
+        /// Copy the programs played by copied combis too, into slots starting from this one
+        #[clap(long)]
+        programs_to: Option<TritonSlot>,
+        /// Copy the drum kits played by copied programs too, into slots starting from this one
+        #[clap(long)]
+        drums_to: Option<TritonSlot>,
+        /// Switch the places of two entries: --swap A B
+        #[clap(long, num_args = 2)]
+        swap: Vec<TritonSlot>,
+        /// Point the references of combis to programs and of programs to drum kits at
+        /// the new places of copied and swapped entries. The offsets of the references
+        /// are not confirmed yet, see docs/korg/pcg_format.md
+        #[clap(long)]
+        remap_references: bool,
+        /// Rename an entry: --rename A000 "New Name"
+        #[clap(long, num_args = 2)]
+        rename: Vec<String>,
+        /// Clear an entry
+        #[clap(long)]
+        init: Vec<TritonSlot>,
+        /// Write the changed PCG file
+        #[clap(long)]
+        export: Option<std::path::PathBuf>,
+    },
+    /// List the songs of a SNG file and export them as Standard MIDI Files
+    SNG {
+        /// The .SNG file
+        path: std::path::PathBuf,
+        /// Only list and export one song, by number
+        #[clap(long)]
+        song: Option<usize>,
+        /// Write each song as a Standard MIDI File into this directory
+        #[clap(long)]
+        midi_out: Option<std::path::PathBuf>,
+    },
+    /// Show sample files, or convert them from and to WAV files
+    KSF {
+        /// The .KSF files
+        path: Vec<std::path::PathBuf>,
+        /// Create the .KSF file from this 16-bit WAV file
+        #[clap(long)]
+        from_wav: Option<std::path::PathBuf>,
+        /// Write the samples as WAV files into this directory
+        #[clap(long)]
+        wav_out: Option<std::path::PathBuf>,
+    },
+    /// Show a multisample, or build one from WAV files
+    KMP {
+        /// The .KMP file; its samples are read from and written to the same directory,
+        /// numbered past any KSF files that are already there
+        path: std::path::PathBuf,
+        /// Create the multisample from these 16-bit WAV files. Root keys are taken from
+        /// the WAV's smpl chunk or a note name at the end of the file name, e.g. "Piano C4.wav"
+        #[clap(long)]
+        from_wav: Vec<std::path::PathBuf>,
+        /// Name of the created multisample; defaults to the name of the .KMP file
+        #[clap(long)]
+        name: Option<String>,
+        /// Write the samples as WAV files into this directory
+        #[clap(long)]
+        wav_out: Option<std::path::PathBuf>,
+    }
+}
+
+pub(crate) fn cli (command: &Triton) {
+
+    match command {
+
+        Triton::PCG {
+            path, only, kind, from, copy, programs_to, drums_to, swap, remap_references, rename, init, export
+        } => {
+
+            let Some(mut pcg) = read_or_report(path, TritonPCG::read) else { return };
+            for bank in pcg.truncated() {
+                println!("{} bank {}: only {} of {} entries present", bank.kind, bank.name(), bank.entries.len(), bank.count);
+            }
+
+            if !copy.is_empty() {
+                let source = match from {
+                    Some(from) => {
+                        let Some(source) = read_or_report(from, TritonPCG::read) else { return };
+                        source
+                    },
+                    None => pcg.clone()
+                };
+                let slots = copy.chunks(2).map(|pair|(pair[0], pair[1])).collect::<Vec<_>>();
+                let remap = pcg.copy_from(&source, *kind, &slots, *programs_to, *drums_to, *remap_references);
+                for (from, _) in slots.iter().filter(|(from, _)|!remap.contains_key(&(*kind, *from))) {
+                    println!("No {kind} {from} to copy");
+                }
+                for ((kind, from), to) in remap.iter() {
+                    println!("Copied {kind} {from} to {to}");
+                }
+                if !*remap_references && (programs_to.is_some() || drums_to.is_some()) {
+                    println!("References of the copied entries were left as they are, use --remap-references to point them at the copies");
+                }
+                if from.is_some() && programs_to.is_none() && drums_to.is_none() {
+                    for ((kind, _), to) in remap.iter() {
+                        for (target, slot) in referenced(*kind, pcg.entry(*kind, *to).unwrap()) {
+                            if !remap.contains_key(&(target, slot)) {
+                                println!("{kind} {to} plays {target} {slot}, which was not copied");
+                            }
+                        }
+                    }
+                }
+            }
+
+            for pair in swap.chunks(2) {
+                if pcg.swap(*kind, pair[0], pair[1], *remap_references) {
+                    println!("Swapped {kind} {} and {}", pair[0], pair[1]);
+                    if !*remap_references {
+                        for slot in [pair[0], pair[1]] {
+                            for (user, from) in pcg.users(*kind, slot) {
+                                println!("{user} {from} plays {kind} {slot}, use --remap-references to follow the swap");
+                            }
+                        }
+                    }
+                } else {
+                    println!("No {kind} {} or {} to swap", pair[0], pair[1]);
+                }
+            }
+
+            for pair in rename.chunks(2) {
+                let slot = match pair[0].parse::<TritonSlot>() {
+                    Ok(slot) => slot,
+                    Err(error) => {
+                        println!("{error}");
+                        continue
+                    }
+                };
+                if pcg.rename(*kind, slot, &pair[1]) {
+                    println!("Renamed {kind} {slot} to {:?}", pair[1]);
+                } else {
+                    println!("No {kind} {slot} to rename");
+                }
+            }
+
+            for slot in init.iter() {
+                if pcg.init(*kind, *slot) {
+                    println!("Cleared {kind} {slot}");
+                } else {
+                    println!("No {kind} {slot} to clear");
+                }
+            }
+
+            let (major, minor) = pcg.version();
+            println!("Product {:#04x}, version {major}.{minor}", pcg.product());
+            println!("Chunks: {}", pcg.chunks.iter()
+                .map(|chunk|chunk.name())
+                .collect::<Vec<_>>()
+                .join(" "));
+
+            for kind in TritonBankKind::ALL {
+                if only.is_some() && *only != Some(kind) {
+                    continue
+                }
+                for bank in pcg.banks(kind) {
+                    println!("\n{kind} bank {} ({} entries of {} bytes)",
+                        bank.name(), bank.entries.len(), bank.size);
+                    for (index, name) in bank.names().iter().enumerate() {
+                        println!("{:>5} {name}", TritonSlot { bank: bank.bank, index }.to_string());
+                    }
+                }
+            }
+
+            if only.is_none() {
+                if let Some(global) = pcg.global() {
+                    println!("\nGlobal settings ({} bytes)", global.len());
+                }
+            }
+
+            if let Some(path) = export {
+                std::fs::File::create(path)
+                    .unwrap()
+                    .write_all(pcg.write().as_slice())
+                    .unwrap();
+                println!("Wrote {path:?}");
+            }
+
+        },
+
+        Triton::SNG { path, song, midi_out } => {
+
+            let Some(sng) = read_or_report(path, TritonSNG::read) else { return };
+            let selected = sng.songs.iter().enumerate()
+                .filter(|(index, _)|song.is_none() || *song == Some(index + 1))
+                .collect::<Vec<_>>();
+            if selected.is_empty() {
+                println!("No such song.");
+                return
+            }
+
+            for (index, song) in selected.iter() {
+                let bpm = song.tempo.first().map(|(_, bpm)|*bpm).unwrap_or(120.0);
+                println!("{:>3} {:<16} {:>3} measures {}/{} {bpm:>6.2} BPM",
+                    index + 1, song.name, song.measures, song.numerator, song.denominator);
+                for (number, track) in song.tracks.iter().enumerate() {
+                    if track.events.is_empty() {
+                        continue
+                    }
+                    println!("    Track {:>2} {:<16} ch{:<2} {} {:>5} events", number + 1, track.name,
+                        (track.channel & 0x0F) + 1, TritonSlot { bank: track.bank as u32, index: track.program as usize },
+                        track.events.len());
+                }
+                for (number, pattern) in song.patterns.iter().enumerate() {
+                    println!("    Pattern {:>2} {:<16} {:>2} measures", number, pattern.name, pattern.measures);
+                }
+            }
+
+            for cue_list in sng.cue_lists.iter() {
+                println!("Cue list {}: {}", cue_list.name, cue_list.steps.iter()
+                    .map(|(song, repeat)|format!("{}x{repeat}", song + 1))
+                    .collect::<Vec<_>>()
+                    .join(" "));
+            }
+
+            if let Some(dir) = midi_out {
+                std::fs::create_dir_all(dir).unwrap();
+                for (index, song) in selected.iter() {
+                    let path = dir.join(song.file_name(index + 1));
+                    let (smf, warnings) = song.to_midi();
+                    for warning in warnings {
+                        println!("{warning}");
+                    }
+                    std::fs::File::create(&path)
+                        .unwrap()
+                        .write_all(smf.write().as_slice())
+                        .unwrap();
+                    println!("Wrote {path:?}");
+                }
+            }
+
+        },
+
+        Triton::KSF { path, from_wav, wav_out } => {
+
+            if let Some(wav) = from_wav {
+                let Some(path) = path.first() else {
+                    println!("No .KSF file to write.");
+                    return
+                };
+                let name = wav.file_stem().map(|stem|stem.to_string_lossy().to_string()).unwrap_or_default();
+                let sample = match TritonKSF::from_wav(&name, &crate::read(wav)) {
+                    Ok(sample) => sample,
+                    Err(error) => {
+                        println!("Could not read {wav:?}: {error}");
+                        return
+                    }
+                };
+                std::fs::File::create(path)
+                    .unwrap()
+                    .write_all(sample.write().as_slice())
+                    .unwrap();
+                println!("Wrote {path:?}");
+            }
+
+            for path in path.iter() {
+                let Some(sample) = read_or_report(path, TritonKSF::read) else { continue };
+                println!("{}", ksf_summary(&sample));
+                if let Some(dir) = wav_out {
+                    write_wav(&dir.join(path.with_extension("wav").file_name().unwrap()), &sample, DEFAULT_ROOT_KEY);
+                }
+            }
+
+        },
+
+        Triton::KMP { path, from_wav, name, wav_out } => {
+
+            let dir = path.parent().unwrap_or(std::path::Path::new("."));
+
+            if !from_wav.is_empty() {
+                if from_wav.len() > KMP_ZONES_MAX {
+                    println!("A multisample can have at most {KMP_ZONES_MAX} samples, got {}.", from_wav.len());
+                    return
+                }
+                let mut samples = vec![];
+                for wav in from_wav.iter() {
+                    let raw  = crate::read(wav);
+                    let stem = wav.file_stem().map(|stem|stem.to_string_lossy().to_string()).unwrap_or_default();
+                    let root = wav_root_key(&raw)
+                        .or_else(||parse_root_key(&stem))
+                        .unwrap_or(DEFAULT_ROOT_KEY);
+                    match TritonKSF::from_wav(&stem, &raw) {
+                        Ok(sample) => samples.push((sample, root)),
+                        Err(error) => {
+                            println!("Could not read {wav:?}: {error}");
+                            return
+                        }
+                    }
+                }
+                let name = name.clone().unwrap_or_else(||path.file_stem()
+                    .map(|stem|stem.to_string_lossy().to_string())
+                    .unwrap_or_default());
+                // Never overwrite the samples of another multisample in the same directory
+                let (kmp, files) = match TritonKMP::from_samples(&name, samples, |file|dir.join(file).exists()) {
+                    Ok(built) => built,
+                    Err(error) => {
+                        println!("Could not build {path:?}: {error}");
+                        return
+                    }
+                };
+                for (file, sample) in files.iter() {
+                    let path = dir.join(file);
+                    std::fs::File::create(&path)
+                        .unwrap()
+                        .write_all(sample.write().as_slice())
+                        .unwrap();
+                    println!("Wrote {path:?}");
+                }
+                std::fs::File::create(path)
+                    .unwrap()
+                    .write_all(kmp.write().as_slice())
+                    .unwrap();
+                println!("Wrote {path:?}");
+            }
+
+            let Some(kmp) = read_or_report(path, TritonKMP::read) else { return };
+            println!("{} ({} zones)", kmp.long_name.as_ref().unwrap_or(&kmp.name), kmp.zones.len());
+            for (index, zone) in kmp.zones.iter().enumerate() {
+                print!("{:>3} {:>3}-{:<3} root {:<3} {:<12}",
+                    index + 1, kmp.bottom_key(index), zone.top_key, zone.root_key(), zone.file);
+                if zone.skipped() {
+                    println!();
+                    continue
+                }
+                let path = dir.join(&zone.file);
+                if !path.exists() {
+                    println!(" (missing)");
+                    continue
+                }
+                let Some(sample) = read_or_report(&path, TritonKSF::read) else { continue };
+                println!(" {}", ksf_summary(&sample));
+                if let Some(dir) = wav_out {
+                    write_wav(&dir.join(path.with_extension("wav").file_name().unwrap()), &sample, zone.root_key());
+                }
+            }
+
+        }
+
+    }
+
+}
+
+/// Describe a sample in one line
+fn ksf_summary (sample: &TritonKSF) -> String {
+    match sample.number {
+        Some(number) => format!("{:<16} sample {number} in memory", sample.name),
+        None => format!("{:<16} {:>5}Hz {} {:>6.2}s{}",
+            sample.name,
+            sample.sample_rate,
+            if sample.channels == 2 { "stereo" } else { "mono  " },
+            sample.duration(),
+            if sample.looped() { " loop" } else { "" })
+    }
+}
+
+/// Write a sample as a WAV file, creating its directory if needed
+fn write_wav (path: &std::path::Path, sample: &TritonKSF, root_key: u8) {
+    if sample.number.is_some() {
+        println!("{} has no sample data, not extracting", sample.name);
+        return
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).unwrap();
+    }
+    std::fs::File::create(path)
+        .unwrap()
+        .write_all(sample.to_wav(root_key).as_slice())
+        .unwrap();
+    println!("Wrote {path:?}");
+}
+
+/// Read a file, or report why it could not be read
+fn read_or_report <T> (
+    path: &std::path::Path, read: impl Fn(&[u8]) -> std::result::Result<T, String>
+) -> Option<T> {
+    match read(&crate::read(path)) {
+        Ok(value) => Some(value),
+        Err(error) => {
+            println!("{path:?}: {error}");
+            None
+        }
+    }
+}
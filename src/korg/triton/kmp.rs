@@ -0,0 +1,293 @@
+//! Reading and writing of Korg Triton multisample files (.KMP), which map key zones
+//! to sample files (.KSF), as documented in docs/korg/ksf_format.md.
+
+use super::*;
+
+/// Multisample parameters chunk
+pub const KMP_PARAMS_TAG: &[u8; 4] = b"MSP1";
+/// Long name chunk
+pub const KMP_NAME_TAG: &[u8; 4] = b"NAME";
+/// Key zones chunk
+pub const KMP_ZONES_TAG: &[u8; 4] = b"RLP1";
+/// Multisample number chunk
+pub const KMP_NUMBER_TAG: &[u8; 4] = b"MNO1";
+/// Size of the MSP1 chunk
+pub const KMP_PARAMS_SIZE: usize = 0x12;
+/// Length of the name in the NAME chunk
+pub const KMP_LONG_NAME_SIZE: usize = 24;
+/// Size of each key zone in the RLP1 chunk
+pub const KMP_ZONE_SIZE: usize = 0x12;
+/// Length of the sample file name of a key zone
+pub const KSF_FILE_NAME_SIZE: usize = 12;
+/// Sample file name of a key zone that plays no sample
+pub const SKIPPED_SAMPLE: &str = "SKIPPEDSAMPL";
+/// Most key zones in a multisample
+pub const KMP_ZONES_MAX: usize = 128;
+/// Number of KSF file names with one prefix: PREF0000.KSF to PREF9999.KSF
+pub const KSF_NUMBERS: usize = 10000;
+
+#[derive(Debug, Default, Clone)]
+pub struct TritonKMP {
+    /// MSP1 0x00
+    pub name:       String,
+    /// MSP1 0x11 - attribute bits, preserved as-is (0x10 is the number of key zones)
+    pub attributes: u8,
+    /// NAME - longer name, if any
+    pub long_name:  Option<String>,
+    /// RLP1 - key zones, from the lowest to the highest
+    pub zones:      Vec<TritonKeyZone>,
+    /// MNO1 - number of the multisample in memory, if any
+    pub number:     Option<u32>,
+    /// Other chunks, preserved as-is
+    pub other:      Vec<TritonChunk>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TritonKeyZone {
+    /// 0x00 - MIDI note that plays the sample at its original pitch (bit 7 preserved as-is)
+    pub original_key: u8,
+    /// 0x01 - highest MIDI note of the zone; the zone starts above the previous one
+    pub top_key:      u8,
+    /// 0x02 - in cents
+    pub tune:         i8,
+    /// 0x03
+    pub level:        i8,
+    /// 0x04 - 64 is center
+    pub pan:          u8,
+    /// 0x05
+    pub cutoff:       i8,
+    /// 0x06 - 8.3 name of the KSF file, or SKIPPEDSAMPL
+    pub file:         String,
+}
+
+impl Default for TritonKeyZone {
+    fn default () -> Self {
+        Self {
+            original_key: DEFAULT_ROOT_KEY,
+            top_key:      127,
+            tune:         0,
+            level:        0,
+            pan:          64,
+            cutoff:       0,
+            file:         SKIPPED_SAMPLE.into(),
+        }
+    }
+}
+
+impl TritonKMP {
+
+    /// Read a KMP file
+    pub fn read (raw: &[u8]) -> std::result::Result<Self, String> {
+        if raw.get(..4) != Some(KMP_PARAMS_TAG.as_slice()) {
+            return Err("not a KMP file".into())
+        }
+        let mut kmp = Self::default();
+        for chunk in TritonChunk::read_all(raw) {
+            let TritonChunkBody::Raw(data) = &chunk.body else { continue };
+            match &chunk.id {
+                KMP_PARAMS_TAG => {
+                    if data.len() < KMP_PARAMS_SIZE {
+                        return Err("MSP1 chunk is too short".into())
+                    }
+                    kmp.name       = read_name(data);
+                    kmp.attributes = data[0x11];
+                },
+                KMP_NAME_TAG => kmp.long_name = Some(
+                    String::from_utf8_lossy(data).trim_end_matches(['\0', ' ']).into()
+                ),
+                KMP_ZONES_TAG => kmp.zones = data.chunks_exact(KMP_ZONE_SIZE)
+                    .map(TritonKeyZone::read)
+                    .collect(),
+                KMP_NUMBER_TAG if data.len() >= 4 => kmp.number = Some(read_u32(data)),
+                _ => kmp.other.push(chunk)
+            }
+        }
+        Ok(kmp)
+    }
+
+    /// Write a KMP file
+    pub fn write (&self) -> Vec<u8> {
+        let mut raw = vec![];
+        let mut params = vec![0; KMP_PARAMS_SIZE];
+        write_name(&mut params, &self.name);
+        params[0x10] = self.zones.len() as u8;
+        params[0x11] = self.attributes;
+        write_chunk(&mut raw, KMP_PARAMS_TAG, &params);
+        if let Some(long_name) = &self.long_name {
+            let mut name = long_name.bytes().filter(u8::is_ascii).take(KMP_LONG_NAME_SIZE).collect::<Vec<_>>();
+            name.resize(KMP_LONG_NAME_SIZE, b' ');
+            write_chunk(&mut raw, KMP_NAME_TAG, &name);
+        }
+        let zones = self.zones.iter().flat_map(TritonKeyZone::write).collect::<Vec<_>>();
+        write_chunk(&mut raw, KMP_ZONES_TAG, &zones);
+        if let Some(number) = self.number {
+            write_chunk(&mut raw, KMP_NUMBER_TAG, &number.to_be_bytes());
+        }
+        raw.extend(TritonChunk::write_all(&self.other));
+        raw
+    }
+
+    /// Build a multisample from samples and their root keys. Each sample gets a zone
+    /// that reaches up to the note below the next sample's root key, and a KSF file name
+    /// made of the first letters of the multisample's name and a number. The numbers
+    /// continue past file names for which `taken` is true, so that multisamples whose
+    /// names start alike don't share files.
+    /// Returns the multisample and the samples with their file names, in zone order.
+    pub fn from_samples (
+        name: &str, mut samples: Vec<(TritonKSF, u8)>, taken: impl Fn(&str) -> bool
+    ) -> std::result::Result<(Self, Vec<(String, TritonKSF)>), String> {
+        if samples.len() > KMP_ZONES_MAX {
+            return Err(format!("a multisample can have at most {KMP_ZONES_MAX} samples, found {}", samples.len()))
+        }
+        samples.sort_by_key(|(_, root)|*root);
+        let prefix = ksf_prefix(name);
+        let file = |number: usize|format!("{prefix}{number:04}.KSF");
+        let first = (0..=KSF_NUMBERS - samples.len())
+            .find(|first|(*first..first + samples.len()).all(|number|!taken(&file(number))))
+            .ok_or_else(||format!("no free file names left for {prefix}nnnn.KSF"))?;
+        let mut kmp = Self {
+            name:      name.chars().take(NAME_SIZE).collect(),
+            long_name: (name.len() > NAME_SIZE).then(||name.chars().take(KMP_LONG_NAME_SIZE).collect()),
+            ..Self::default()
+        };
+        let mut files = vec![];
+        for (index, (sample, root)) in samples.iter().enumerate() {
+            let file = file(first + index);
+            let top_key = samples.get(index + 1).map(|(_, next)|next.saturating_sub(1).max(*root)).unwrap_or(127);
+            kmp.zones.push(TritonKeyZone {
+                original_key: *root,
+                top_key,
+                file: file.clone(),
+                ..TritonKeyZone::default()
+            });
+            files.push((file, sample.clone()));
+        }
+        Ok((kmp, files))
+    }
+
+    /// Lowest MIDI note of a key zone
+    pub fn bottom_key (&self, index: usize) -> u8 {
+        match index {
+            0 => 0,
+            _ => self.zones.get(index - 1).map(|zone|zone.top_key.saturating_add(1)).unwrap_or(0)
+        }
+    }
+
+}
+
+impl TritonKeyZone {
+
+    /// Read a key zone
+    pub fn read (raw: &[u8]) -> Self {
+        Self {
+            original_key: raw[0x00],
+            top_key:      raw[0x01],
+            tune:         raw[0x02] as i8,
+            level:        raw[0x03] as i8,
+            pan:          raw[0x04],
+            cutoff:       raw[0x05] as i8,
+            file:         String::from_utf8_lossy(&raw[0x06..0x06+KSF_FILE_NAME_SIZE])
+                .trim_end_matches(['\0', ' ']).into(),
+        }
+    }
+
+    /// Write a key zone
+    pub fn write (&self) -> Vec<u8> {
+        let mut raw = vec![
+            self.original_key, self.top_key, self.tune as u8, self.level as u8, self.pan, self.cutoff as u8
+        ];
+        let mut file = self.file.bytes().filter(u8::is_ascii).take(KSF_FILE_NAME_SIZE).collect::<Vec<_>>();
+        file.resize(KSF_FILE_NAME_SIZE, 0);
+        raw.extend(file);
+        raw
+    }
+
+    /// MIDI note that plays the sample at its original pitch
+    pub fn root_key (&self) -> u8 {
+        self.original_key & 0x7F
+    }
+
+    /// Whether the zone plays a sample
+    pub fn skipped (&self) -> bool {
+        self.file == SKIPPED_SAMPLE
+    }
+
+}
+
+/// The first four letters or digits of a name in upper case, padded with underscores,
+/// to start the names of the KSF files of a multisample
+pub fn ksf_prefix (name: &str) -> String {
+    let mut prefix = name.chars()
+        .filter(|c|c.is_ascii_alphanumeric())
+        .map(|c|c.to_ascii_uppercase())
+        .take(4)
+        .collect::<String>();
+    while prefix.len() < 4 {
+        prefix.push('_');
+    }
+    prefix
+}
+
+/// Find a note name like C4, F#2 or Bb-1 at the end of a file name (60 is C4)
+pub fn parse_root_key (name: &str) -> Option<u8> {
+    let name = name.trim_end();
+    for length in (2..=4).rev() {
+        let Some(start) = name.len().checked_sub(length) else { continue };
+        let Some(text) = name.get(start..) else { continue };
+        let mut chars = text.chars();
+        let base = match chars.next()?.to_ascii_uppercase() {
+            'C' => 0, 'D' => 2, 'E' => 4, 'F' => 5, 'G' => 7, 'A' => 9, 'B' => 11,
+            _ => continue
+        };
+        let rest = chars.as_str();
+        let (shift, octave) = if let Some(octave) = rest.strip_prefix('#') {
+            (1, octave)
+        } else if let Some(octave) = rest.strip_prefix('b') {
+            (-1, octave)
+        } else {
+            (0, rest)
+        };
+        let Ok(octave) = octave.parse::<i32>() else { continue };
+        let note = (octave + 1) * 12 + base + shift;
+        if (0..128).contains(&note) {
+            return Some(note as u8)
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn write_read_kmp () {
+        let samples = vec![(TritonKSF::default(), 72), (TritonKSF::default(), 48)];
+        let (mut kmp, files) = TritonKMP::from_samples("Grand Piano Stereo", samples, |_|false).unwrap();
+        kmp.number = Some(5);
+        kmp.zones[1].tune = -12;
+        kmp.zones[1].pan  = 20;
+        assert_eq!(files.iter().map(|(file, _)|file.as_str()).collect::<Vec<_>>(), vec!["GRAN0000.KSF", "GRAN0001.KSF"]);
+        let read = TritonKMP::read(&kmp.write()).unwrap();
+        assert_eq!((read.name.as_str(), read.long_name.as_deref()), ("Grand Piano Ster", Some("Grand Piano Stereo")));
+        assert_eq!(read.zones, kmp.zones);
+        assert_eq!((read.zones[0].root_key(), read.zones[0].top_key, read.bottom_key(1)), (48, 71, 72));
+        assert_eq!(read.number, Some(5));
+        assert_eq!(read.write(), kmp.write());
+        assert!(TritonKMP::read(b"MS").is_err());
+        assert!(TritonKMP::read(b"MSP1\0\0\0\x02ab").is_err());
+    }
+
+    #[test]
+    fn unique_ksf_names () {
+        let samples = vec![(TritonKSF::default(), 60), (TritonKSF::default(), 72)];
+        // Another multisample starting with "Gran" already has two samples
+        let taken = |file: &str|file == "GRAN0000.KSF" || file == "GRAN0001.KSF";
+        let (kmp, files) = TritonKMP::from_samples("Granular Pad", samples, taken).unwrap();
+        assert_eq!(files.iter().map(|(file, _)|file.as_str()).collect::<Vec<_>>(), vec!["GRAN0002.KSF", "GRAN0003.KSF"]);
+        assert_eq!(kmp.zones[0].file, "GRAN0002.KSF");
+        let too_many = vec![(TritonKSF::default(), 60); KMP_ZONES_MAX + 1];
+        assert!(TritonKMP::from_samples("Too Many", too_many, |_|false).is_err());
+    }
+
+}
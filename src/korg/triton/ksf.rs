@@ -0,0 +1,312 @@
+//! Reading and writing of Korg Triton sample files (.KSF), and their conversion
+//! from and to WAV files, as documented in docs/korg/ksf_format.md.
+
+use super::*;
+use crate::wav::{riff_chunk, smpl, wav_loop, write_riff_chunk};
+
+/// Sample parameters chunk
+pub const KSF_PARAMS_TAG: &[u8; 4] = b"SMP1";
+/// Sample data chunk
+pub const KSF_DATA_TAG: &[u8; 4] = b"SMD1";
+/// Sample number chunk, in place of the data of samples that are already in memory
+pub const KSF_NUMBER_TAG: &[u8; 4] = b"SNO1";
+/// Size of the SMP1 chunk
+pub const KSF_PARAMS_SIZE: usize = 0x20;
+/// Size of the SMD1 chunk before the sample data
+pub const KSF_DATA_HEADER: usize = 0x0C;
+/// MIDI note that a sample plays at its original pitch, when nothing else says
+pub const DEFAULT_ROOT_KEY: u8 = 60;
+/// Attribute bit of a sample that plays once instead of looping
+pub const KSF_LOOP_OFF: u8 = 0x80;
+
+#[derive(Debug, Clone)]
+pub struct TritonKSF {
+    /// SMP1 0x00
+    pub name:         String,
+    /// SMP1 0x10 - sample memory bank the sample is loaded into by default
+    pub default_bank: u8,
+    /// SMP1 0x11 - first frame to play (24 bits)
+    pub start:        u32,
+    /// SMP1 0x14 - alternative first frame
+    pub start_2:      u32,
+    /// SMP1 0x18 - first frame of the loop
+    pub loop_start:   u32,
+    /// SMP1 0x1C - last frame to play
+    pub end:          u32,
+    /// SMD1 0x00
+    pub sample_rate:  u32,
+    /// SMD1 0x04 - attribute bits: KSF_LOOP_OFF, the others preserved as-is
+    pub attributes:   u8,
+    /// SMD1 0x05 - fine tuning of the loop, in cents
+    pub loop_tune:    i8,
+    /// SMD1 0x06
+    pub channels:     u8,
+    /// SMD1 0x07 - bits per sample of the stored data, 8 or 16
+    pub bits:         u8,
+    /// SMD1 0x0C - frames, interleaved if there is more than one channel
+    pub data:         Vec<i16>,
+    /// SNO1 - number of a sample in memory, for files without sample data
+    pub number:       Option<u32>,
+    /// Other chunks, preserved as-is
+    pub other:        Vec<TritonChunk>,
+}
+
+impl Default for TritonKSF {
+    fn default () -> Self {
+        Self {
+            name:         String::new(),
+            default_bank: 0,
+            start:        0,
+            start_2:      0,
+            loop_start:   0,
+            end:          0,
+            sample_rate:  48000,
+            attributes:   0,
+            loop_tune:    0,
+            channels:     1,
+            bits:         16,
+            data:         vec![],
+            number:       None,
+            other:        vec![],
+        }
+    }
+}
+
+impl TritonKSF {
+
+    /// Read a KSF file
+    pub fn read (raw: &[u8]) -> std::result::Result<Self, String> {
+        if raw.get(..4) != Some(KSF_PARAMS_TAG.as_slice()) {
+            return Err("not a KSF file".into())
+        }
+        let mut sample = Self::default();
+        for chunk in TritonChunk::read_all(raw) {
+            let TritonChunkBody::Raw(data) = &chunk.body else { continue };
+            match &chunk.id {
+                KSF_PARAMS_TAG => sample.read_params(data)?,
+                KSF_DATA_TAG   => sample.read_data(data)?,
+                KSF_NUMBER_TAG if data.len() >= 4 => sample.number = Some(read_u32(data)),
+                _ => sample.other.push(chunk)
+            }
+        }
+        Ok(sample)
+    }
+
+    fn read_params (&mut self, data: &[u8]) -> std::result::Result<(), String> {
+        if data.len() < KSF_PARAMS_SIZE {
+            return Err("SMP1 chunk is too short".into())
+        }
+        self.name         = read_name(data);
+        self.default_bank = data[0x10];
+        self.start        = u32::from_be_bytes([0, data[0x11], data[0x12], data[0x13]]);
+        self.start_2      = read_u32(&data[0x14..]);
+        self.loop_start   = read_u32(&data[0x18..]);
+        self.end          = read_u32(&data[0x1C..]);
+        Ok(())
+    }
+
+    fn read_data (&mut self, data: &[u8]) -> std::result::Result<(), String> {
+        if data.len() < KSF_DATA_HEADER {
+            return Err("SMD1 chunk is too short".into())
+        }
+        self.sample_rate = read_u32(data);
+        self.attributes  = data[0x04];
+        self.loop_tune   = data[0x05] as i8;
+        self.channels    = data[0x06].max(1);
+        self.bits        = data[0x07];
+        let count = read_u32(&data[0x08..]) as usize * self.channels as usize;
+        let audio = &data[KSF_DATA_HEADER..];
+        self.data = match self.bits {
+            8 => audio.iter().take(count).map(|byte|(*byte as i8 as i16) << 8).collect(),
+            _ => audio.chunks_exact(2).take(count).map(|x|i16::from_be_bytes([x[0], x[1]])).collect()
+        };
+        Ok(())
+    }
+
+    /// Write a KSF file. Sample data is always written with 16 bits.
+    pub fn write (&self) -> Vec<u8> {
+        let mut raw = vec![];
+        let mut params = vec![0; KSF_PARAMS_SIZE];
+        write_name(&mut params, &self.name);
+        params[0x10] = self.default_bank;
+        params[0x11..0x14].copy_from_slice(&self.start.to_be_bytes()[1..]);
+        params[0x14..0x18].copy_from_slice(&self.start_2.to_be_bytes());
+        params[0x18..0x1C].copy_from_slice(&self.loop_start.to_be_bytes());
+        params[0x1C..0x20].copy_from_slice(&self.end.to_be_bytes());
+        write_chunk(&mut raw, KSF_PARAMS_TAG, &params);
+        if let Some(number) = self.number {
+            write_chunk(&mut raw, KSF_NUMBER_TAG, &number.to_be_bytes());
+        } else {
+            let mut data = Vec::with_capacity(KSF_DATA_HEADER + self.data.len() * 2);
+            data.extend_from_slice(&self.sample_rate.to_be_bytes());
+            data.extend_from_slice(&[self.attributes, self.loop_tune as u8, self.channels, 16]);
+            data.extend_from_slice(&(self.frames() as u32).to_be_bytes());
+            for value in self.data.iter() {
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+            write_chunk(&mut raw, KSF_DATA_TAG, &data);
+        }
+        raw.extend(TritonChunk::write_all(&self.other));
+        raw
+    }
+
+    /// Number of frames of sample data
+    pub fn frames (&self) -> usize {
+        self.data.len() / self.channels.max(1) as usize
+    }
+
+    /// Length of the sample in seconds
+    pub fn duration (&self) -> f32 {
+        self.frames() as f32 / self.sample_rate.max(1) as f32
+    }
+
+    /// Whether the sample loops: its loop is not off, and starts within the sample
+    pub fn looped (&self) -> bool {
+        self.attributes & KSF_LOOP_OFF == 0 && self.loop_start >= self.start && self.loop_start <= self.end
+    }
+
+    /// Create a sample from the contents of a 16-bit PCM WAV file.
+    /// The loop of a smpl chunk, if any, becomes the sample's loop;
+    /// without one, the sample's loop is off.
+    pub fn from_wav (name: &str, raw: &[u8]) -> std::result::Result<Self, String> {
+        if raw.len() < 12 || &raw[0..4] != b"RIFF" || &raw[8..12] != b"WAVE" {
+            return Err("not a WAV file".into())
+        }
+        let fmt = riff_chunk(raw, b"fmt ").ok_or("no fmt chunk in WAV file")?;
+        if fmt.len() < 16 {
+            return Err(format!("fmt chunk is {} bytes long, expected at least 16", fmt.len()))
+        }
+        if u16::from_le_bytes([fmt[0], fmt[1]]) != 1 {
+            return Err("uncompressed WAVs only".into())
+        }
+        let bits = u16::from_le_bytes([fmt[14], fmt[15]]);
+        if bits != 16 {
+            return Err(format!("16-bit WAVs only, this one is {bits}-bit"))
+        }
+        let channels = u16::from_le_bytes([fmt[2], fmt[3]]);
+        if channels != 1 && channels != 2 {
+            return Err(format!("mono or stereo WAVs only, this one has {channels} channels"))
+        }
+        let data = riff_chunk(raw, b"data").ok_or("no data chunk in WAV file")?;
+        let mut sample = Self {
+            name:        name.chars().filter(|c|c.is_ascii()).take(NAME_SIZE).collect(),
+            sample_rate: u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]),
+            channels:    channels as u8,
+            data:        data.chunks_exact(2).map(|x|i16::from_le_bytes([x[0], x[1]])).collect(),
+            ..Self::default()
+        };
+        sample.end = sample.frames().saturating_sub(1) as u32;
+        if let Some((start, end)) = wav_loop(raw) {
+            sample.loop_start = start.min(sample.end);
+            sample.end        = end.min(sample.end).max(sample.loop_start);
+        } else {
+            sample.attributes |= KSF_LOOP_OFF;
+        }
+        Ok(sample)
+    }
+
+    /// A 16-bit PCM WAV file with the sample data, and a smpl chunk with the root key
+    /// and, if the sample loops, the loop
+    pub fn to_wav (&self, root_key: u8) -> Vec<u8> {
+        let start = (self.start as usize * self.channels as usize).min(self.data.len());
+        let end   = ((self.end as usize + 1) * self.channels as usize).min(self.data.len()).max(start);
+        let mut data = Vec::with_capacity((end - start) * 2);
+        for value in self.data[start..end].iter() {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        let frame = 2 * self.channels as u32;
+        let mut fmt = vec![];
+        fmt.extend_from_slice(&1u16.to_le_bytes());
+        fmt.extend_from_slice(&(self.channels as u16).to_le_bytes());
+        fmt.extend_from_slice(&self.sample_rate.to_le_bytes());
+        fmt.extend_from_slice(&(self.sample_rate * frame).to_le_bytes());
+        fmt.extend_from_slice(&(frame as u16).to_le_bytes());
+        fmt.extend_from_slice(&16u16.to_le_bytes());
+        let mut wav = b"RIFF\0\0\0\0WAVE".to_vec();
+        write_riff_chunk(&mut wav, b"fmt ", &fmt);
+        write_riff_chunk(&mut wav, b"data", &data);
+        let looped = if self.looped() {
+            Some((self.loop_start - self.start, self.end - self.start))
+        } else {
+            None
+        };
+        write_riff_chunk(&mut wav, b"smpl", &smpl(self.sample_rate, root_key, looped));
+        let size = (wav.len() - 8) as u32;
+        wav[4..8].copy_from_slice(&size.to_le_bytes());
+        wav
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A mono 16-bit WAV file of 8 frames, with a loop over the whole sample if `looped`
+    fn wav (looped: bool) -> Vec<u8> {
+        let mut fmt = vec![];
+        fmt.extend_from_slice(&[1, 0, 1, 0]);
+        fmt.extend_from_slice(&44100u32.to_le_bytes());
+        fmt.extend_from_slice(&(44100u32 * 2).to_le_bytes());
+        fmt.extend_from_slice(&[2, 0, 16, 0]);
+        let data = (0..8i16).flat_map(|x|(x * 1000).to_le_bytes()).collect::<Vec<_>>();
+        let mut wav = b"RIFF\0\0\0\0WAVE".to_vec();
+        write_riff_chunk(&mut wav, b"fmt ", &fmt);
+        write_riff_chunk(&mut wav, b"data", &data);
+        if looped {
+            write_riff_chunk(&mut wav, b"smpl", &smpl(44100, 62, Some((0, 7))));
+        }
+        let size = (wav.len() - 8) as u32;
+        wav[4..8].copy_from_slice(&size.to_le_bytes());
+        wav
+    }
+
+    #[test]
+    fn write_read_ksf () {
+        let mut sample = TritonKSF::from_wav("Loop From Zero", &wav(true)).unwrap();
+        sample.loop_tune = -3;
+        sample.other.push(TritonChunk { id: *b"XTRA", body: TritonChunkBody::Raw(vec![1, 2]) });
+        let read = TritonKSF::read(&sample.write()).unwrap();
+        assert_eq!(read.name, "Loop From Zero");
+        assert_eq!((read.start, read.loop_start, read.end), (0, 0, 7));
+        assert_eq!((read.sample_rate, read.channels, read.bits, read.loop_tune), (44100, 1, 16, -3));
+        assert_eq!(read.data, sample.data);
+        assert_eq!(read.other.len(), 1);
+        assert!(read.looped());
+        assert_eq!(read.write(), sample.write());
+        assert!(TritonKSF::read(b"SMP").is_err());
+        assert!(TritonKSF::read(b"SMP1\0\0\0\x04name").is_err());
+    }
+
+    #[test]
+    fn loop_off () {
+        let sample = TritonKSF::from_wav("One Shot", &wav(false)).unwrap();
+        assert_eq!(sample.attributes & KSF_LOOP_OFF, KSF_LOOP_OFF);
+        assert!(!sample.looped());
+        let read = TritonKSF::read(&sample.write()).unwrap();
+        assert!(!read.looped());
+        assert_eq!(crate::wav::wav_loop(&read.to_wav(DEFAULT_ROOT_KEY)), None);
+        let looped = TritonKSF::from_wav("Loop", &wav(true)).unwrap();
+        assert_eq!(crate::wav::wav_loop(&looped.to_wav(62)), Some((0, 7)));
+        assert_eq!(crate::wav::wav_root_key(&looped.to_wav(62)), Some(62));
+    }
+
+    #[test]
+    fn unsupported_wav () {
+        let good = wav(false);
+        assert!(TritonKSF::from_wav("Short", &good[..10]).is_err());
+        // 24-bit
+        let mut wide = good.clone();
+        wide[12 + 8 + 14] = 24;
+        assert_eq!(TritonKSF::from_wav("Wide", &wide).unwrap_err(), "16-bit WAVs only, this one is 24-bit");
+        // Floating point
+        let mut float = good.clone();
+        float[12 + 8] = 3;
+        assert!(TritonKSF::from_wav("Float", &float).is_err());
+        // A fmt chunk too short to hold the bit depth
+        let mut short = b"RIFF\0\0\0\0WAVE".to_vec();
+        write_riff_chunk(&mut short, b"fmt ", &[1, 0, 1, 0]);
+        assert!(TritonKSF::from_wav("Short fmt", &short).is_err());
+    }
+
+}
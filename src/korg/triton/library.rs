@@ -0,0 +1,376 @@
+//! Librarian operations on PCG files: copying, swapping, renaming and initializing
+//! programs, combis, drum kits and arpeggio patterns, and on request keeping the
+//! references of combis to programs and of programs to drum kits pointed at the
+//! right entries.
+//!
+//! The offsets of those references have not been confirmed against a dump from
+//! the device, so they are only rewritten when the caller asks for it: rewriting
+//! bytes that turn out to be something else would silently corrupt the file.
+
+use super::*;
+use std::collections::BTreeMap;
+
+/// Offset of the first timbre in a combi (unconfirmed, see docs/korg/pcg_format.md)
+pub const COMBI_TIMBRES_OFFSET: usize = 0x40;
+/// Size of a timbre in a combi
+pub const COMBI_TIMBRE_SIZE: usize = 0x30;
+/// Number of timbres in a combi
+pub const COMBI_TIMBRES: usize = 8;
+/// Offset of the oscillator mode in a program (unconfirmed)
+pub const PROGRAM_OSC_MODE: usize = 0x10;
+/// Oscillator mode of a program that plays a drum kit
+pub const OSC_MODE_DRUMS: u8 = 2;
+/// Offset of the drum kit bank and number in a program (unconfirmed)
+pub const PROGRAM_DRUM_KIT: usize = 0x2a;
+/// Number of entries in a full bank
+pub const BANK_SIZE: usize = 128;
+
+/// Position of an entry: its bank, and its number within the bank
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TritonSlot {
+    pub bank:  u32,
+    pub index: usize,
+}
+
+impl TritonSlot {
+    /// The slot after this one, continuing in the next bank after the last slot
+    pub fn next (&self) -> Self {
+        if self.index + 1 >= BANK_SIZE {
+            Self { bank: self.bank + 1, index: 0 }
+        } else {
+            Self { bank: self.bank, index: self.index + 1 }
+        }
+    }
+}
+
+impl std::fmt::Display for TritonSlot {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}{:03}", bank_name(self.bank), self.index)
+    }
+}
+
+impl std::str::FromStr for TritonSlot {
+    type Err = String;
+    /// Parse a slot as shown on the device, e.g. A000 or b12, from 000 to 127
+    fn from_str (text: &str) -> std::result::Result<Self, Self::Err> {
+        let text = text.trim();
+        let mut chars = text.chars();
+        let bank = match chars.next() {
+            Some(letter) if letter.is_ascii_alphabetic() =>
+                (letter.to_ascii_uppercase() as u8 - b'A') as u32,
+            _ => return Err(format!("invalid slot {text:?}, expected e.g. A000"))
+        };
+        let index = chars.as_str().parse::<usize>()
+            .map_err(|_|format!("invalid slot {text:?}, expected e.g. A000"))?;
+        if index >= BANK_SIZE {
+            return Err(format!("invalid slot {text:?}, a bank has entries 000 to {:03}", BANK_SIZE - 1))
+        }
+        Ok(Self { bank, index })
+    }
+}
+
+/// Where each copied or moved entry went, by kind and original slot
+pub type TritonRemap = BTreeMap<(TritonBankKind, TritonSlot), TritonSlot>;
+
+/// Offsets of the references in an entry of a given kind, with the kind of entry each
+/// one points to. Each reference is a bank ID byte followed by an entry number byte.
+pub fn references (kind: TritonBankKind, data: &[u8]) -> Vec<(usize, TritonBankKind)> {
+    let mut offsets = vec![];
+    match kind {
+        TritonBankKind::Combi => for timbre in 0..COMBI_TIMBRES {
+            offsets.push((COMBI_TIMBRES_OFFSET + timbre * COMBI_TIMBRE_SIZE, TritonBankKind::Program));
+        },
+        TritonBankKind::Program if data.get(PROGRAM_OSC_MODE) == Some(&OSC_MODE_DRUMS) => {
+            offsets.push((PROGRAM_DRUM_KIT, TritonBankKind::DrumKit));
+        },
+        _ => {}
+    }
+    offsets.retain(|(offset, _)|offset + 1 < data.len());
+    offsets
+}
+
+/// The entries an entry refers to
+pub fn referenced (kind: TritonBankKind, data: &[u8]) -> Vec<(TritonBankKind, TritonSlot)> {
+    references(kind, data).into_iter().map(|(offset, target)|(target, TritonSlot {
+        bank:  data[offset] as u32,
+        index: data[offset + 1] as usize,
+    })).collect()
+}
+
+/// Point the references of an entry at the new places of the entries they refer to.
+/// Returns the number of references that were changed.
+pub fn remap_references (kind: TritonBankKind, data: &mut [u8], remap: &TritonRemap) -> usize {
+    let mut changed = 0;
+    for (offset, target) in references(kind, data) {
+        let slot = TritonSlot { bank: data[offset] as u32, index: data[offset + 1] as usize };
+        if let Some(new) = remap.get(&(target, slot)) {
+            data[offset]     = new.bank as u8;
+            data[offset + 1] = new.index as u8;
+            changed += 1;
+        }
+    }
+    changed
+}
+
+impl TritonPCG {
+
+    /// Find a bank by kind and ID
+    pub fn bank (&self, kind: TritonBankKind, bank: u32) -> Option<&TritonBank> {
+        self.banks(kind).into_iter().find(|x|x.bank == bank)
+    }
+
+    /// Find a bank by kind and ID, for editing
+    pub fn bank_mut (&mut self, kind: TritonBankKind, bank: u32) -> Option<&mut TritonBank> {
+        self.banks_mut(kind).into_iter().find(|x|x.bank == bank)
+    }
+
+    /// All banks of one kind, in file order, for editing
+    pub fn banks_mut (&mut self, kind: TritonBankKind) -> Vec<&mut TritonBank> {
+        let Some(TritonChunk { body: TritonChunkBody::Chunks(chunks), .. }) = self.chunks.iter_mut()
+            .find(|chunk|&chunk.id == kind.container()) else {
+            return vec![]
+        };
+        chunks.iter_mut().filter_map(|chunk|match &mut chunk.body {
+            TritonChunkBody::Bank(bank) if bank.kind == kind => Some(bank),
+            _ => None
+        }).collect()
+    }
+
+    /// The data of an entry
+    pub fn entry (&self, kind: TritonBankKind, slot: TritonSlot) -> Option<&Vec<u8>> {
+        self.bank(kind, slot.bank)?.entries.get(slot.index)
+    }
+
+    /// The data of an entry, for editing
+    pub fn entry_mut (&mut self, kind: TritonBankKind, slot: TritonSlot) -> Option<&mut Vec<u8>> {
+        self.bank_mut(kind, slot.bank)?.entries.get_mut(slot.index)
+    }
+
+    /// Put data into a slot, creating the bank (and its container chunk) if the file
+    /// does not have it yet. The data is padded or cut to the bank's entry size.
+    /// Slots past the end of a bank (see BANK_SIZE) are ignored.
+    pub fn set_entry (&mut self, kind: TritonBankKind, slot: TritonSlot, mut data: Vec<u8>) {
+        if slot.index >= BANK_SIZE {
+            return
+        }
+        if self.bank(kind, slot.bank).is_none() {
+            self.add_bank(kind, slot.bank, data.len() as u32);
+        }
+        let bank = self.bank_mut(kind, slot.bank).unwrap();
+        data.resize(bank.size as usize, 0);
+        while bank.entries.len() <= slot.index {
+            bank.entries.push(init_entry(kind, bank.size as usize));
+        }
+        bank.entries[slot.index] = data;
+    }
+
+    /// Add an empty bank, keeping the banks of each kind sorted by ID
+    fn add_bank (&mut self, kind: TritonBankKind, bank: u32, size: u32) {
+        let position = TritonBankKind::ALL.iter().position(|x|*x == kind).unwrap();
+        if !self.chunks.iter().any(|chunk|&chunk.id == kind.container()) {
+            // Keep the containers in the order PRG1, CMB1, DKT1, ARP1, before GLB1
+            let index = self.chunks.iter().position(|chunk|!TritonBankKind::ALL[..position]
+                .iter()
+                .any(|kind|&chunk.id == kind.container())
+            ).unwrap_or(self.chunks.len());
+            self.chunks.insert(index, TritonChunk {
+                id: *kind.container(), body: TritonChunkBody::Chunks(vec![])
+            });
+        }
+        let Some(TritonChunk { body: TritonChunkBody::Chunks(chunks), .. }) = self.chunks.iter_mut()
+            .find(|chunk|&chunk.id == kind.container()) else {
+            panic!("{} chunk is not a container", String::from_utf8_lossy(kind.container()))
+        };
+        let index = chunks.iter().position(|chunk|match &chunk.body {
+            TritonChunkBody::Bank(other) => other.bank > bank,
+            _ => false
+        }).unwrap_or(chunks.len());
+        chunks.insert(index, TritonChunk {
+            id:   *kind.bank(),
+            body: TritonChunkBody::Bank(TritonBank { kind, bank, size, count: 0, entries: vec![] })
+        });
+    }
+
+    /// Copy an entry from another (or the same) PCG file. Returns false if there is nothing
+    /// to copy. References of the copied entry are left as they are.
+    pub fn copy (&mut self, source: &Self, kind: TritonBankKind, from: TritonSlot, to: TritonSlot) -> bool {
+        let Some(data) = source.entry(kind, from) else {
+            return false
+        };
+        self.set_entry(kind, to, data.clone());
+        true
+    }
+
+    /// Copy entries from another PCG file. If `rewrite_references` is set, point the
+    /// references of the copied entries at the places where the entries they refer to
+    /// were copied. Slots with nothing to copy are left out of the returned remap.
+    ///
+    /// Programs played by copied combis, and drum kits played by copied programs, are
+    /// copied along into consecutive slots starting from `programs_to` and `drums_to`.
+    /// Without those, references to entries that were not copied are left as they are.
+    pub fn copy_from (
+        &mut self,
+        source:      &Self,
+        kind:        TritonBankKind,
+        slots:       &[(TritonSlot, TritonSlot)],
+        programs_to: Option<TritonSlot>,
+        drums_to:    Option<TritonSlot>,
+        rewrite_references: bool,
+    ) -> TritonRemap {
+        let mut remap = TritonRemap::new();
+        for (from, to) in slots.iter() {
+            if self.copy(source, kind, *from, *to) {
+                remap.insert((kind, *from), *to);
+            }
+        }
+        for (target, first) in [
+            (TritonBankKind::Program, programs_to),
+            (TritonBankKind::DrumKit, drums_to)
+        ] {
+            let Some(mut next) = first else { continue };
+            let needed = remap.keys()
+                .filter_map(|(kind, from)|source.entry(*kind, *from).map(|data|referenced(*kind, data)))
+                .flatten()
+                .filter(|(kind, _)|*kind == target)
+                .collect::<Vec<_>>();
+            for (kind, from) in needed {
+                if remap.contains_key(&(kind, from)) {
+                    continue
+                }
+                if self.copy(source, kind, from, next) {
+                    remap.insert((kind, from), next);
+                    next = next.next();
+                }
+            }
+        }
+        if rewrite_references {
+            for ((kind, _), to) in remap.iter() {
+                if let Some(data) = self.entry_mut(*kind, *to) {
+                    remap_references(*kind, data, &remap);
+                }
+            }
+        }
+        remap
+    }
+
+    /// Swap two entries of the same kind. If `rewrite_references` is set, point all
+    /// references to them at their new places. Returns false if either entry is missing.
+    pub fn swap (&mut self, kind: TritonBankKind, a: TritonSlot, b: TritonSlot, rewrite_references: bool) -> bool {
+        let (Some(data_a), Some(data_b)) = (self.entry(kind, a).cloned(), self.entry(kind, b).cloned()) else {
+            return false
+        };
+        *self.entry_mut(kind, a).unwrap() = data_b;
+        *self.entry_mut(kind, b).unwrap() = data_a;
+        if rewrite_references {
+            self.remap_all(&TritonRemap::from([((kind, a), b), ((kind, b), a)]));
+        }
+        true
+    }
+
+    /// Point the references of all entries at the new places of the entries they refer to.
+    /// Returns the number of references that were changed.
+    pub fn remap_all (&mut self, remap: &TritonRemap) -> usize {
+        let mut changed = 0;
+        for kind in TritonBankKind::ALL {
+            for bank in self.banks_mut(kind) {
+                for entry in bank.entries.iter_mut() {
+                    changed += remap_references(kind, entry, remap);
+                }
+            }
+        }
+        changed
+    }
+
+    /// Rename an entry. Returns false if the entry is missing.
+    pub fn rename (&mut self, kind: TritonBankKind, slot: TritonSlot, name: &str) -> bool {
+        let Some(data) = self.entry_mut(kind, slot) else {
+            return false
+        };
+        write_name(data, name);
+        true
+    }
+
+    /// Clear an entry and give it the default name. Returns false if the entry is missing.
+    pub fn init (&mut self, kind: TritonBankKind, slot: TritonSlot) -> bool {
+        let Some(data) = self.entry_mut(kind, slot) else {
+            return false
+        };
+        *data = init_entry(kind, data.len());
+        true
+    }
+
+    /// Entries that refer to an entry
+    pub fn users (&self, kind: TritonBankKind, slot: TritonSlot) -> Vec<(TritonBankKind, TritonSlot)> {
+        let mut users = vec![];
+        for user in TritonBankKind::ALL {
+            for bank in self.banks(user) {
+                for (index, entry) in bank.entries.iter().enumerate() {
+                    if referenced(user, entry).contains(&(kind, slot)) {
+                        users.push((user, TritonSlot { bank: bank.bank, index }));
+                    }
+                }
+            }
+        }
+        users
+    }
+
+}
+
+/// An entry with all parameters cleared and the default name
+pub fn init_entry (kind: TritonBankKind, size: usize) -> Vec<u8> {
+    let mut data = vec![0; size];
+    write_name(&mut data, match kind {
+        TritonBankKind::Program  => "Init Program",
+        TritonBankKind::Combi    => "Init Combi",
+        TritonBankKind::DrumKit  => "Init Drum Kit",
+        TritonBankKind::Arpeggio => "Init Arp",
+    });
+    data
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A PCG file with two programs and a combi whose first timbre plays the first program,
+    /// at the unconfirmed offsets of docs/korg/pcg_format.md
+    fn pcg () -> TritonPCG {
+        let mut pcg = TritonPCG::default();
+        let a000 = TritonSlot { bank: 0, index: 0 };
+        pcg.set_entry(TritonBankKind::Program, a000, init_entry(TritonBankKind::Program, 0x40));
+        pcg.set_entry(TritonBankKind::Program, a000.next(), init_entry(TritonBankKind::Program, 0x40));
+        pcg.set_entry(TritonBankKind::Combi, a000, init_entry(TritonBankKind::Combi, 0x200));
+        pcg
+    }
+
+    #[test]
+    fn parse_slot () {
+        assert_eq!("A000".parse::<TritonSlot>(), Ok(TritonSlot { bank: 0, index: 0 }));
+        assert_eq!(" b12 ".parse::<TritonSlot>(), Ok(TritonSlot { bank: 1, index: 12 }));
+        assert_eq!("C127".parse::<TritonSlot>(), Ok(TritonSlot { bank: 2, index: 127 }));
+        assert!("A128".parse::<TritonSlot>().is_err());
+        assert!("A999".parse::<TritonSlot>().is_err());
+        assert!("000".parse::<TritonSlot>().is_err());
+        assert!("A".parse::<TritonSlot>().is_err());
+    }
+
+    #[test]
+    fn swap_keeps_references () {
+        let a000 = TritonSlot { bank: 0, index: 0 };
+        let mut pcg = pcg();
+        let combi = pcg.entry(TritonBankKind::Combi, a000).unwrap().clone();
+        assert!(pcg.swap(TritonBankKind::Program, a000, a000.next(), false));
+        assert_eq!(pcg.entry(TritonBankKind::Combi, a000), Some(&combi));
+        assert_eq!(pcg.users(TritonBankKind::Program, a000), vec![(TritonBankKind::Combi, a000)]);
+    }
+
+    #[test]
+    fn swap_remaps_references () {
+        let a000 = TritonSlot { bank: 0, index: 0 };
+        let mut pcg = pcg();
+        assert!(pcg.swap(TritonBankKind::Program, a000, a000.next(), true));
+        let combi = pcg.entry(TritonBankKind::Combi, a000).unwrap();
+        assert_eq!(referenced(TritonBankKind::Combi, combi)[0], (TritonBankKind::Program, a000.next()));
+    }
+
+}
@@ -0,0 +1,412 @@
+//! Reading and writing of Korg Triton PCG files (programs, combinations, drum kits,
+//! arpeggio patterns and global settings), as documented in docs/korg/pcg_format.md.
+
+/// Tag at the start of a Korg file
+pub const KORG_TAG: &[u8; 4] = b"KORG";
+/// Chunk that holds the contents of a PCG file
+pub const PCG_TAG: &[u8; 4] = b"PCG1";
+/// Size of a chunk's ID and size fields
+pub const CHUNK_HEADER_SIZE: usize = 8;
+/// Size of the header of a bank chunk: entry count, entry size and bank ID
+pub const BANK_HEADER_SIZE: usize = 12;
+/// Length of the name at the start of each program, combi, drum kit and arpeggio pattern
+pub const NAME_SIZE: usize = 16;
+/// How far into a file to look for the chunk after the header
+const HEADER_MAX: usize = 0x40;
+
+/// The kinds of banks in a PCG file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TritonBankKind {
+    Program,
+    Combi,
+    DrumKit,
+    Arpeggio,
+}
+
+impl TritonBankKind {
+    pub const ALL: [Self; 4] = [Self::Program, Self::Combi, Self::DrumKit, Self::Arpeggio];
+    /// ID of the chunk that holds all banks of this kind
+    pub fn container (&self) -> &'static [u8; 4] {
+        match self {
+            Self::Program  => b"PRG1",
+            Self::Combi    => b"CMB1",
+            Self::DrumKit  => b"DKT1",
+            Self::Arpeggio => b"ARP1",
+        }
+    }
+    /// ID of the chunk that holds one bank of this kind
+    pub fn bank (&self) -> &'static [u8; 4] {
+        match self {
+            Self::Program  => b"PBK1",
+            Self::Combi    => b"CBK1",
+            Self::DrumKit  => b"DBK1",
+            Self::Arpeggio => b"ABK1",
+        }
+    }
+    /// Find the kind of bank by the ID of its chunk
+    pub fn from_bank_id (id: &[u8; 4]) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind|kind.bank() == id)
+    }
+}
+
+impl std::fmt::Display for TritonBankKind {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Self::Program  => "Program",
+            Self::Combi    => "Combi",
+            Self::DrumKit  => "Drum kit",
+            Self::Arpeggio => "Arpeggio",
+        })
+    }
+}
+
+impl std::str::FromStr for TritonBankKind {
+    type Err = String;
+    fn from_str (text: &str) -> std::result::Result<Self, Self::Err> {
+        match text.trim().to_lowercase().as_str() {
+            "program" | "programs" | "prog" => Ok(Self::Program),
+            "combi" | "combis" | "combination" | "combinations" => Ok(Self::Combi),
+            "drums" | "drumkit" | "drumkits" | "drum-kit" | "drum-kits" => Ok(Self::DrumKit),
+            "arp" | "arps" | "arpeggio" | "arpeggios" => Ok(Self::Arpeggio),
+            _ => Err(format!("unknown bank kind {text:?}, expected programs, combis, drums or arps"))
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TritonPCG {
+    /// Everything before the PCG1 chunk, preserved as-is
+    pub header: Vec<u8>,
+    /// The chunks inside the PCG1 chunk, in file order
+    pub chunks: Vec<TritonChunk>,
+    /// Anything after the PCG1 chunk, preserved as-is
+    pub footer: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TritonChunk {
+    /// Four-character chunk ID, e.g. PRG1
+    pub id:   [u8; 4],
+    pub body: TritonChunkBody,
+}
+
+#[derive(Debug, Clone)]
+pub enum TritonChunkBody {
+    /// Data that is not parsed further, e.g. GLB1
+    Raw(Vec<u8>),
+    /// Chunks nested in a container chunk, e.g. the PBK1 chunks of PRG1
+    Chunks(Vec<TritonChunk>),
+    /// A bank of programs, combis, drum kits or arpeggio patterns
+    Bank(TritonBank),
+}
+
+#[derive(Debug, Clone)]
+pub struct TritonBank {
+    pub kind:    TritonBankKind,
+    /// 0x08 - bank ID: 0 for bank A, 1 for bank B, etc.
+    pub bank:    u32,
+    /// 0x04 - size of each entry in bytes
+    pub size:    u32,
+    /// 0x00 - number of entries the chunk declares; more than there are
+    /// entries if the file was cut short
+    pub count:   usize,
+    /// 0x0C - the entries, each starting with its name
+    pub entries: Vec<Vec<u8>>,
+}
+
+impl TritonPCG {
+
+    /// Read a PCG file
+    pub fn read (raw: &[u8]) -> std::result::Result<Self, String> {
+        let (start, end) = find_chunk(raw, PCG_TAG)?;
+        Ok(Self {
+            header: raw[..start].to_vec(),
+            chunks: TritonChunk::read_all(&raw[start+CHUNK_HEADER_SIZE..end]),
+            footer: raw[end..].to_vec(),
+        })
+    }
+
+    /// Write a PCG file
+    pub fn write (&self) -> Vec<u8> {
+        let mut raw = self.header.clone();
+        write_chunk(&mut raw, PCG_TAG, &TritonChunk::write_all(&self.chunks));
+        raw.extend_from_slice(&self.footer);
+        raw
+    }
+
+    /// Product ID from the file header
+    pub fn product (&self) -> u8 {
+        self.header.get(4).copied().unwrap_or(0)
+    }
+
+    /// File format version from the file header, as (major, minor)
+    pub fn version (&self) -> (u8, u8) {
+        (self.header.get(6).copied().unwrap_or(0), self.header.get(7).copied().unwrap_or(0))
+    }
+
+    /// Find a top-level chunk by ID
+    pub fn chunk (&self, id: &[u8; 4]) -> Option<&TritonChunk> {
+        self.chunks.iter().find(|chunk|&chunk.id == id)
+    }
+
+    /// All banks of one kind, in file order
+    pub fn banks (&self, kind: TritonBankKind) -> Vec<&TritonBank> {
+        let Some(TritonChunk { body: TritonChunkBody::Chunks(chunks), .. }) = self.chunk(kind.container()) else {
+            return vec![]
+        };
+        chunks.iter().filter_map(|chunk|match &chunk.body {
+            TritonChunkBody::Bank(bank) if bank.kind == kind => Some(bank),
+            _ => None
+        }).collect()
+    }
+
+    /// Banks that hold fewer entries than they declare, because the file was cut short
+    pub fn truncated (&self) -> Vec<&TritonBank> {
+        TritonBankKind::ALL.iter()
+            .flat_map(|kind|self.banks(*kind))
+            .filter(|bank|bank.entries.len() < bank.count)
+            .collect()
+    }
+
+    /// Global settings, if the file has them
+    pub fn global (&self) -> Option<&[u8]> {
+        match self.chunk(b"GLB1") {
+            Some(TritonChunk { body: TritonChunkBody::Raw(data), .. }) => Some(data.as_slice()),
+            _ => None
+        }
+    }
+
+}
+
+impl TritonChunk {
+
+    /// Read consecutive chunks until the data runs out or stops looking like chunks
+    pub fn read_all (mut raw: &[u8]) -> Vec<Self> {
+        let mut chunks = vec![];
+        while raw.len() >= CHUNK_HEADER_SIZE {
+            let id: [u8; 4] = raw[..4].try_into().unwrap();
+            if !id.iter().all(|byte|byte.is_ascii_alphanumeric()) {
+                break
+            }
+            let size = (read_u32(&raw[4..]) as usize).min(raw.len() - CHUNK_HEADER_SIZE);
+            chunks.push(Self::read(id, &raw[CHUNK_HEADER_SIZE..CHUNK_HEADER_SIZE+size]));
+            raw = &raw[CHUNK_HEADER_SIZE+size..];
+        }
+        chunks
+    }
+
+    /// Read the body of a chunk
+    pub fn read (id: [u8; 4], data: &[u8]) -> Self {
+        // Bank chunks with an invalid header are kept as they are
+        let bank = TritonBankKind::from_bank_id(&id).and_then(|kind|TritonBank::read(kind, data).ok());
+        let body = if let Some(bank) = bank {
+            TritonChunkBody::Bank(bank)
+        } else if TritonBankKind::ALL.iter().any(|kind|kind.container() == &id) {
+            TritonChunkBody::Chunks(Self::read_all(data))
+        } else {
+            TritonChunkBody::Raw(data.to_vec())
+        };
+        Self { id, body }
+    }
+
+    /// Write consecutive chunks
+    pub fn write_all (chunks: &[Self]) -> Vec<u8> {
+        let mut raw = vec![];
+        for chunk in chunks.iter() {
+            write_chunk(&mut raw, &chunk.id, &chunk.write_body());
+        }
+        raw
+    }
+
+    /// Write the body of a chunk
+    pub fn write_body (&self) -> Vec<u8> {
+        match &self.body {
+            TritonChunkBody::Raw(data)      => data.clone(),
+            TritonChunkBody::Chunks(chunks) => Self::write_all(chunks),
+            TritonChunkBody::Bank(bank)     => bank.write(),
+        }
+    }
+
+    /// Chunk ID as text
+    pub fn name (&self) -> String {
+        String::from_utf8_lossy(&self.id).into()
+    }
+
+}
+
+impl TritonBank {
+
+    /// Read a bank chunk. Entries past the end of the data are left out, see `count`.
+    pub fn read (kind: TritonBankKind, data: &[u8]) -> std::result::Result<Self, String> {
+        if data.len() < BANK_HEADER_SIZE {
+            return Err(format!("bank header is {BANK_HEADER_SIZE} bytes long, found {}", data.len()))
+        }
+        let count = read_u32(&data[0..]) as usize;
+        let size  = read_u32(&data[4..]);
+        let bank  = read_u32(&data[8..]);
+        if size == 0 {
+            return Err("bank entries have a size of 0".into())
+        }
+        let available = (data.len() - BANK_HEADER_SIZE) / size as usize;
+        let entries = data[BANK_HEADER_SIZE..].chunks_exact(size as usize)
+            .take(count.min(available))
+            .map(|entry|entry.to_vec())
+            .collect();
+        Ok(Self { kind, bank, size, count, entries })
+    }
+
+    /// Write a bank chunk. Entries are padded or cut to the bank's entry size.
+    pub fn write (&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(BANK_HEADER_SIZE + self.entries.len() * self.size as usize);
+        raw.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        raw.extend_from_slice(&self.size.to_be_bytes());
+        raw.extend_from_slice(&self.bank.to_be_bytes());
+        for entry in self.entries.iter() {
+            let start = raw.len();
+            raw.extend_from_slice(&entry[..entry.len().min(self.size as usize)]);
+            raw.resize(start + self.size as usize, 0);
+        }
+        raw
+    }
+
+    /// Name of the bank as shown on the device, e.g. A
+    pub fn name (&self) -> String {
+        bank_name(self.bank)
+    }
+
+    /// Name of an entry
+    pub fn entry_name (&self, index: usize) -> Option<String> {
+        self.entries.get(index).map(|entry|read_name(entry))
+    }
+
+    /// Names of all entries
+    pub fn names (&self) -> Vec<String> {
+        self.entries.iter().map(|entry|read_name(entry)).collect()
+    }
+
+}
+
+/// Find the chunk that follows the header of a Korg file.
+/// Returns the offsets of the start of the chunk and of the end of its data.
+pub fn find_chunk (raw: &[u8], id: &[u8; 4]) -> std::result::Result<(usize, usize), String> {
+    if raw.get(..4) != Some(KORG_TAG.as_slice()) {
+        return Err("not a Korg file".into())
+    }
+    let name  = String::from_utf8_lossy(id);
+    let start = raw[..raw.len().min(HEADER_MAX)].windows(4).position(|window|window == id)
+        .ok_or_else(||format!("no {name} chunk"))?;
+    if raw.len() < start + CHUNK_HEADER_SIZE {
+        return Err(format!("{name} chunk is cut short"))
+    }
+    let size  = read_u32(&raw[start+4..]) as usize;
+    Ok((start, (start + CHUNK_HEADER_SIZE + size).min(raw.len())))
+}
+
+/// Name of a bank as shown on the device, e.g. A for bank ID 0
+pub fn bank_name (bank: u32) -> String {
+    match bank {
+        0..=25 => ((b'A' + bank as u8) as char).to_string(),
+        bank => format!("{bank:#x}")
+    }
+}
+
+/// Append a chunk with its ID and size
+pub fn write_chunk (raw: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    raw.extend_from_slice(id);
+    raw.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    raw.extend_from_slice(data);
+}
+
+/// Read a big-endian 32-bit number
+pub fn read_u32 (raw: &[u8]) -> u32 {
+    u32::from_be_bytes([raw[0], raw[1], raw[2], raw[3]])
+}
+
+/// Write a name into the start of an entry, padded with spaces
+pub fn write_name (raw: &mut [u8], name: &str) {
+    let mut bytes = name.bytes()
+        .map(|byte|if byte.is_ascii_graphic() { byte } else { b' ' })
+        .take(NAME_SIZE);
+    for byte in raw.iter_mut().take(NAME_SIZE) {
+        *byte = bytes.next().unwrap_or(b' ');
+    }
+}
+
+/// Read a name padded with spaces or zeros
+pub fn read_name (raw: &[u8]) -> String {
+    raw[..raw.len().min(NAME_SIZE)].iter()
+        .take_while(|byte|**byte != 0)
+        .map(|byte|if byte.is_ascii_graphic() { *byte as char } else { ' ' })
+        .collect::<String>()
+        .trim_end()
+        .into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A PCG file built from docs/korg/pcg_format.md, not a dump from the device:
+    /// a header, one bank of two programs and a GLB1 chunk, followed by a footer
+    fn pcg () -> Vec<u8> {
+        let mut programs = vec![];
+        programs.extend_from_slice(&2u32.to_be_bytes());
+        programs.extend_from_slice(&0x20u32.to_be_bytes());
+        programs.extend_from_slice(&1u32.to_be_bytes());
+        for (name, fill) in [(b"Piano           ", 0x11), (b"Strings         ", 0x22)] {
+            programs.extend_from_slice(name);
+            programs.extend_from_slice(&[fill; 0x10]);
+        }
+        let mut bank = vec![];
+        write_chunk(&mut bank, b"PBK1", &programs);
+        let mut contents = vec![];
+        write_chunk(&mut contents, b"PRG1", &bank);
+        write_chunk(&mut contents, b"GLB1", &[1, 2, 3, 4]);
+        let mut raw = b"KORG\x50\x00\x01\x00".to_vec();
+        raw.resize(0x20, 0);
+        write_chunk(&mut raw, PCG_TAG, &contents);
+        raw.extend_from_slice(b"TAIL");
+        raw
+    }
+
+    #[test]
+    fn read_write_pcg () {
+        let raw = pcg();
+        let pcg = TritonPCG::read(&raw).unwrap();
+        assert_eq!((pcg.product(), pcg.version()), (0x50, (1, 0)));
+        let banks = pcg.banks(TritonBankKind::Program);
+        assert_eq!(banks.len(), 1);
+        assert_eq!(banks[0].name(), "B");
+        assert_eq!(banks[0].names(), vec!["Piano", "Strings"]);
+        assert_eq!(pcg.global(), Some([1, 2, 3, 4].as_slice()));
+        assert_eq!(pcg.footer, b"TAIL");
+        assert!(pcg.truncated().is_empty());
+        assert_eq!(pcg.write(), raw);
+    }
+
+    #[test]
+    fn read_short_pcg () {
+        assert!(TritonPCG::read(b"KO").is_err());
+        assert!(TritonPCG::read(b"KORG\x50\x00\x01\x00PCG1").is_err());
+        assert!(TritonPCG::read(&[0; 0x40]).is_err());
+        // The second program is cut off: the bank keeps the first and reports it
+        let raw = pcg();
+        let pcg = TritonPCG::read(&raw[..0x20 + 8 + 8 + 8 + 12 + 0x30]).unwrap();
+        let truncated = pcg.truncated();
+        assert_eq!(truncated.len(), 1);
+        assert_eq!((truncated[0].entries.len(), truncated[0].count), (1, 2));
+    }
+
+    #[test]
+    fn read_bad_bank () {
+        let header = |count: u32, size: u32|[count.to_be_bytes(), size.to_be_bytes(), 0u32.to_be_bytes()].concat();
+        assert!(TritonBank::read(TritonBankKind::Program, &header(u32::MAX, 0)).is_err());
+        assert!(TritonBank::read(TritonBankKind::Program, &header(1, 1)[..8]).is_err());
+        // A huge count is limited to the entries that are present
+        let data = [header(u32::MAX, 2), vec![1, 2, 3, 4, 5]].concat();
+        let bank = TritonBank::read(TritonBankKind::Program, &data).unwrap();
+        assert_eq!(bank.entries, vec![vec![1, 2], vec![3, 4]]);
+        assert_eq!(bank.count, u32::MAX as usize);
+    }
+
+}
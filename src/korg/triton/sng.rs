@@ -0,0 +1,388 @@
+//! Reading of Korg Triton sequencer files (.SNG) and their export to Standard MIDI Files,
+//! as documented in docs/korg/sng_format.md.
+
+use super::*;
+use crate::midi;
+
+/// Chunk that holds the contents of a SNG file
+pub const SNG_TAG: &[u8; 4] = b"SNG1";
+/// Container of the songs
+pub const SONGS_TAG: &[u8; 4] = b"SGS1";
+/// A song
+pub const SONG_TAG: &[u8; 4] = b"SONG";
+/// A track of a song
+pub const TRACK_TAG: &[u8; 4] = b"TRK1";
+/// A pattern of a song
+pub const PATTERN_TAG: &[u8; 4] = b"PTN1";
+/// The tempo track of a song
+pub const TEMPO_TAG: &[u8; 4] = b"TMP1";
+/// A cue list
+pub const CUE_LIST_TAG: &[u8; 4] = b"CUE1";
+/// Size of the header of a song, track or pattern chunk, before its events or chunks
+pub const SNG_ITEM_HEADER: usize = 0x14;
+/// Size of an event in a track or pattern
+pub const SNG_EVENT_SIZE: usize = 8;
+/// Size of an entry in the tempo track
+pub const SNG_TEMPO_SIZE: usize = 8;
+/// Size of a step of a cue list
+pub const SNG_CUE_SIZE: usize = 2;
+/// Sequencer resolution, in ticks per quarter note
+pub const SNG_PPQN: u32 = 192;
+/// Status byte of an event that plays a pattern of the song
+pub const PATTERN_EVENT: u8 = 0xF0;
+
+#[derive(Debug, Default, Clone)]
+pub struct TritonSNG {
+    /// Everything before the SNG1 chunk, preserved as-is
+    pub header:    Vec<u8>,
+    pub songs:     Vec<TritonSong>,
+    pub cue_lists: Vec<TritonCueList>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TritonSong {
+    /// 0x00
+    pub name:        String,
+    /// 0x10 - time signature
+    pub numerator:   u8,
+    /// 0x11
+    pub denominator: u8,
+    /// 0x12 - length of the song in measures
+    pub measures:    u16,
+    /// Tempo changes, as (tick, BPM)
+    pub tempo:       Vec<(u32, f32)>,
+    pub tracks:      Vec<TritonTrack>,
+    pub patterns:    Vec<TritonPattern>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TritonTrack {
+    /// 0x00
+    pub name:    String,
+    /// 0x10 - MIDI channel, 0-15
+    pub channel: u8,
+    /// 0x11 - program bank: 0 for bank A, 1 for bank B, etc.
+    pub bank:    u8,
+    /// 0x12 - program number
+    pub program: u8,
+    /// 0x14
+    pub events:  Vec<TritonEvent>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TritonPattern {
+    /// 0x00
+    pub name:     String,
+    /// 0x10 - length of the pattern in measures
+    pub measures: u8,
+    /// 0x14
+    pub events:   Vec<TritonEvent>,
+}
+
+/// An event of a track or pattern: a MIDI channel message (without the channel),
+/// or a pattern placed in a track
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TritonEvent {
+    /// 0x00 - in ticks from the start of the song or pattern
+    pub time:   u32,
+    /// 0x04 - upper nibble of a MIDI status byte, or PATTERN_EVENT
+    pub status: u8,
+    /// 0x05 - for a pattern event: pattern number, and how many times to play it
+    pub data:   [u8; 2],
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TritonCueList {
+    /// 0x00
+    pub name:  String,
+    /// 0x10 - song number and repeat count of each step
+    pub steps: Vec<(u8, u8)>,
+}
+
+impl TritonSNG {
+
+    /// Read a SNG file
+    pub fn read (raw: &[u8]) -> std::result::Result<Self, String> {
+        let (start, end) = find_chunk(raw, SNG_TAG)?;
+        let mut sng = Self { header: raw[..start].to_vec(), ..Self::default() };
+        for chunk in TritonChunk::read_all(&raw[start+CHUNK_HEADER_SIZE..end]) {
+            let TritonChunkBody::Raw(data) = chunk.body else { continue };
+            match &chunk.id {
+                SONGS_TAG => for song in TritonChunk::read_all(&data) {
+                    if let (SONG_TAG, TritonChunkBody::Raw(data)) = (&song.id, &song.body) {
+                        let number = sng.songs.len() + 1;
+                        sng.songs.push(TritonSong::read(data).map_err(|e|format!("song {number}: {e}"))?);
+                    }
+                },
+                CUE_LIST_TAG => sng.cue_lists.push(TritonCueList::read(&data)),
+                _ => {}
+            }
+        }
+        Ok(sng)
+    }
+
+}
+
+impl TritonSong {
+
+    /// Read the data of a song chunk
+    pub fn read (data: &[u8]) -> std::result::Result<Self, String> {
+        if data.len() < SNG_ITEM_HEADER {
+            return Err(format!("song chunk is {} bytes long, expected at least {SNG_ITEM_HEADER}", data.len()))
+        }
+        let mut song = Self {
+            name:        read_name(data),
+            numerator:   data[0x10].max(1),
+            denominator: data[0x11].max(1),
+            measures:    u16::from_be_bytes([data[0x12], data[0x13]]),
+            ..Self::default()
+        };
+        for chunk in TritonChunk::read_all(&data[SNG_ITEM_HEADER..]) {
+            let TritonChunkBody::Raw(data) = chunk.body else { continue };
+            match &chunk.id {
+                TRACK_TAG   => song.tracks.push(TritonTrack::read(&data)?),
+                PATTERN_TAG => song.patterns.push(TritonPattern::read(&data)?),
+                TEMPO_TAG   => for entry in data.chunks_exact(SNG_TEMPO_SIZE) {
+                    let tempo = u16::from_be_bytes([entry[4], entry[5]]);
+                    song.tempo.push((read_u32(entry), tempo as f32 / 100.0));
+                },
+                _ => {}
+            }
+        }
+        Ok(song)
+    }
+
+    /// File name for exporting the song by its number: NNN_Name.mid
+    pub fn file_name (&self, number: usize) -> String {
+        format!("{number:03}_{}.mid", crate::filename::sanitize(&self.name))
+    }
+
+    /// Length of a measure in ticks
+    pub fn measure_ticks (&self) -> u32 {
+        self.numerator as u32 * 4 * SNG_PPQN / self.denominator as u32
+    }
+
+    /// The events of a track, with the patterns placed in it replaced by their events,
+    /// and the numbers of the patterns it places that the song does not have
+    pub fn flatten (&self, track: &TritonTrack) -> (Vec<TritonEvent>, Vec<u8>) {
+        let mut events = vec![];
+        let mut missing = vec![];
+        for event in track.events.iter() {
+            if event.status != PATTERN_EVENT {
+                events.push(*event);
+                continue
+            }
+            let Some(pattern) = self.patterns.get(event.data[0] as usize) else {
+                missing.push(event.data[0]);
+                continue
+            };
+            let length = pattern.measures.max(1) as u32 * self.measure_ticks();
+            for repeat in 0..event.data[1].max(1) as u32 {
+                for note in pattern.events.iter().filter(|note|note.status != PATTERN_EVENT) {
+                    events.push(TritonEvent {
+                        time: event.time + repeat * length + note.time,
+                        ..*note
+                    });
+                }
+            }
+        }
+        events.sort_by_key(|event|event.time);
+        (events, missing)
+    }
+
+    /// Render the song as a type-1 Standard MIDI File: a tempo track, then one track
+    /// per sequencer track that has events, starting with its program change.
+    /// Also returns a warning for each pattern that a track places but the song lacks.
+    pub fn to_midi (&self) -> (midi::SMF, Vec<String>) {
+        let mut warnings = vec![];
+        let mut smf = midi::SMF::new(SNG_PPQN as u16);
+        let tempo_track = smf.track(&self.name);
+        tempo_track.add(0, midi::Event::TimeSignature(self.numerator, self.denominator));
+        for (time, bpm) in self.tempo.iter() {
+            tempo_track.add(*time, midi::Event::tempo(*bpm));
+        }
+        for track in self.tracks.iter() {
+            let (events, missing) = self.flatten(track);
+            for pattern in missing {
+                warnings.push(format!("Track {:?} plays missing pattern {pattern}", track.name));
+            }
+            if events.is_empty() {
+                continue
+            }
+            let channel = track.channel & 0x0F;
+            let output = smf.track(&track.name);
+            output
+                .add(0, midi::Event::Control { channel, control: 0, value: 0 })
+                .add(0, midi::Event::Control { channel, control: 32, value: track.bank })
+                .add(0, midi::Event::Program { channel, program: track.program });
+            for event in events {
+                if let Some(message) = event.to_midi(channel) {
+                    output.add(event.time, message);
+                }
+            }
+        }
+        (smf, warnings)
+    }
+
+}
+
+impl TritonTrack {
+    /// Read the data of a track chunk
+    pub fn read (data: &[u8]) -> std::result::Result<Self, String> {
+        if data.len() < SNG_ITEM_HEADER {
+            return Err(format!("track chunk is {} bytes long, expected at least {SNG_ITEM_HEADER}", data.len()))
+        }
+        Ok(Self {
+            name:    read_name(data),
+            channel: data[0x10],
+            bank:    data[0x11],
+            program: data[0x12],
+            events:  TritonEvent::read_all(&data[SNG_ITEM_HEADER..]),
+        })
+    }
+}
+
+impl TritonPattern {
+    /// Read the data of a pattern chunk
+    pub fn read (data: &[u8]) -> std::result::Result<Self, String> {
+        if data.len() < SNG_ITEM_HEADER {
+            return Err(format!("pattern chunk is {} bytes long, expected at least {SNG_ITEM_HEADER}", data.len()))
+        }
+        Ok(Self {
+            name:     read_name(data),
+            measures: data[0x10],
+            events:   TritonEvent::read_all(&data[SNG_ITEM_HEADER..]),
+        })
+    }
+}
+
+impl TritonEvent {
+
+    /// Read consecutive events
+    pub fn read_all (data: &[u8]) -> Vec<Self> {
+        data.chunks_exact(SNG_EVENT_SIZE).map(|raw|Self {
+            time:   read_u32(raw),
+            status: raw[4],
+            data:   [raw[5], raw[6]],
+        }).collect()
+    }
+
+    /// The MIDI message of the event on a channel, if it is one
+    pub fn to_midi (&self, channel: u8) -> Option<midi::Event> {
+        let [a, b] = self.data;
+        Some(match self.status & 0xF0 {
+            0x80 => midi::Event::NoteOff { channel, note: a, velocity: b },
+            0x90 => midi::Event::NoteOn { channel, note: a, velocity: b },
+            0xB0 => midi::Event::Control { channel, control: a, value: b },
+            0xC0 => midi::Event::Program { channel, program: a },
+            0xD0 => midi::Event::Pressure { channel, value: a },
+            0xE0 => midi::Event::PitchBend { channel, value: a as u16 | (b as u16) << 7 },
+            _ => return None
+        })
+    }
+
+}
+
+impl TritonCueList {
+    /// Read the data of a cue list chunk
+    pub fn read (data: &[u8]) -> Self {
+        Self {
+            name:  read_name(data),
+            steps: data.get(NAME_SIZE..).unwrap_or(&[])
+                .chunks_exact(SNG_CUE_SIZE)
+                .map(|step|(step[0], step[1]))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A SNG file built from docs/korg/sng_format.md, not a file saved on the device:
+    /// one song at 4/4 and 120 BPM, with a track on channel 2 playing program B003,
+    /// a note, and a one-measure pattern placed twice after it
+    fn sng () -> Vec<u8> {
+        let event = |time: u32, status: u8, a: u8, b: u8|{
+            let mut raw = time.to_be_bytes().to_vec();
+            raw.extend_from_slice(&[status, a, b, 0]);
+            raw
+        };
+        let mut track = b"Lead/Bass       ".to_vec();
+        track.extend_from_slice(&[1, 1, 3, 0]);
+        track.extend(event(0, 0x90, 60, 100));
+        track.extend(event(96, 0x80, 60, 0));
+        track.extend(event(768, PATTERN_EVENT, 0, 2));
+        let mut pattern = b"Riff            ".to_vec();
+        pattern.extend_from_slice(&[1, 0, 0, 0]);
+        pattern.extend(event(0, 0x90, 64, 90));
+        pattern.extend(event(192, 0x80, 64, 0));
+        let mut tempo = 0u32.to_be_bytes().to_vec();
+        tempo.extend_from_slice(&12000u16.to_be_bytes());
+        tempo.extend_from_slice(&[0, 0]);
+        let mut song = b"Song/One        ".to_vec();
+        song.extend_from_slice(&[4, 4, 0, 3]);
+        write_chunk(&mut song, TRACK_TAG, &track);
+        write_chunk(&mut song, PATTERN_TAG, &pattern);
+        write_chunk(&mut song, TEMPO_TAG, &tempo);
+        let mut songs = vec![];
+        write_chunk(&mut songs, SONG_TAG, &song);
+        let mut contents = vec![];
+        write_chunk(&mut contents, SONGS_TAG, &songs);
+        let mut raw = b"KORG\x50\x01\x01\x00".to_vec();
+        raw.resize(0x20, 0);
+        write_chunk(&mut raw, SNG_TAG, &contents);
+        raw
+    }
+
+    #[test]
+    fn sng_to_midi () {
+        let sng = TritonSNG::read(&sng()).unwrap();
+        assert_eq!(sng.songs.len(), 1);
+        let song = &sng.songs[0];
+        assert_eq!((song.name.as_str(), song.measures, song.tempo.clone()), ("Song/One", 3, vec![(0, 120.0)]));
+        assert_eq!(song.file_name(1), "001_Song_One.mid");
+        let (smf, warnings) = song.to_midi();
+        assert!(warnings.is_empty());
+        let smf = midi::SMF::read(&smf.write()).unwrap();
+        assert_eq!((smf.format, smf.ppqn, smf.tracks.len()), (1, 192, 2));
+        assert!(smf.tracks[0].events.contains(&(0, midi::Event::Tempo(500_000))));
+        assert!(smf.tracks[0].events.contains(&(0, midi::Event::TimeSignature(4, 4))));
+        let track = &smf.tracks[1];
+        assert_eq!(track.name(), Some("Lead/Bass"));
+        assert!(track.events.contains(&(0, midi::Event::Control { channel: 1, control: 32, value: 1 })));
+        assert!(track.events.contains(&(0, midi::Event::Program { channel: 1, program: 3 })));
+        let notes = track.notes().iter().map(|note|(note.time, note.duration, note.note)).collect::<Vec<_>>();
+        assert_eq!(notes, vec![(0, 96, 60), (768, 192, 64), (1536, 192, 64)]);
+    }
+
+    #[test]
+    fn missing_pattern () {
+        let mut song = TritonSNG::read(&sng()).unwrap().songs.remove(0);
+        song.tracks[0].events[2].data[0] = 5;
+        let (events, missing) = song.flatten(&song.tracks[0]);
+        assert_eq!((events.len(), missing), (2, vec![5]));
+        assert_eq!(song.to_midi().1, vec!["Track \"Lead/Bass\" plays missing pattern 5"]);
+    }
+
+    #[test]
+    fn read_short_chunks () {
+        assert!(TritonSong::read(&[0; 4]).is_err());
+        assert!(TritonTrack::read(&[0; SNG_ITEM_HEADER - 1]).is_err());
+        assert!(TritonPattern::read(&[]).is_err());
+        // A song whose track chunk is cut short
+        let mut song = b"Short           ".to_vec();
+        song.extend_from_slice(&[4, 4, 0, 1]);
+        write_chunk(&mut song, TRACK_TAG, b"Track");
+        let mut songs = vec![];
+        write_chunk(&mut songs, SONG_TAG, &song);
+        let mut contents = vec![];
+        write_chunk(&mut contents, SONGS_TAG, &songs);
+        let mut raw = b"KORG\x50\x01\x01\x00".to_vec();
+        raw.resize(0x20, 0);
+        write_chunk(&mut raw, SNG_TAG, &contents);
+        assert!(TritonSNG::read(&raw).is_err());
+    }
+
+}
@@ -0,0 +1,424 @@
+use std::io::Write;
+use super::*;
+use thatsit::{
+    *,
+    layouts::*,
+    engines::tui::{
+        *,
+        crossterm::{style::Color, event::{Event, KeyEvent, KeyCode}}
+    },
+    widgets::tui::*
+};
+
+/// UI for browsing and arranging the contents of a Korg Triton PCG file
+#[derive(Debug, Default)]
+pub struct TritonUI {
+    /// File explorer for selecting a PCG file
+    pub file_list: FileList,
+    /// Path of the currently loaded PCG file
+    pub path:      Option<std::path::PathBuf>,
+    /// The currently loaded PCG file
+    pub pcg:       Option<TritonPCG>,
+    /// Which kind of entries is listed (0-3, in the order of `TritonBankKind::ALL`)
+    pub tab:       usize,
+    /// Index of the listed bank among the banks of the current kind
+    pub bank:      usize,
+    /// Selected entry of the listed bank
+    pub entry:     usize,
+    /// Entry that was marked for copying
+    pub clipboard: Option<(TritonBankKind, TritonSlot)>,
+    /// Whether the PCG file was modified since it was loaded
+    pub dirty:     bool,
+    /// Whether to ask about saving the changes before closing the PCG file
+    pub prompt:    bool,
+    /// Whether moving an entry points the references to it at its new place
+    /// (the offsets of the references are unconfirmed, see `TritonPCG::swap`)
+    pub remap:     bool,
+    /// Why the last selected file could not be imported
+    pub error:     Option<String>,
+}
+
+impl TritonUI {
+
+    /// Labels of the tabs, in the order of `TritonBankKind::ALL`
+    pub const TABS: [&'static str; 4] = ["Programs", "Combis", "Drum kits", "Arpeggios"];
+
+    /// Key bindings of the entry list
+    pub const HELP: &'static str = concat!(
+        " Up/Down: select │ Alt-Up/Down: move │ Left/Right: bank │ Tab: kind │",
+        " c: copy │ v: paste │ r: remap references │ s: save │ Esc: close"
+    );
+
+    /// Number of parameter bytes after the name shown in the detail view
+    pub const DUMP_SIZE: usize = 0x40;
+
+    /// Create a new PCG browser
+    pub fn new () -> Self {
+        let mut new = Self::default();
+        new.update();
+        new
+    }
+
+    /// Update the file list
+    pub fn update (&mut self) {
+        self.file_list.update();
+    }
+
+    /// Import a PCG file. If it can't be read, the file list stays open and shows why.
+    pub fn import (&mut self, path: &std::path::Path) {
+        match TritonPCG::read(&crate::read(path)) {
+            Ok(pcg) => {
+                self.pcg       = Some(pcg);
+                self.path      = Some(path.into());
+                self.tab       = 0;
+                self.bank      = 0;
+                self.entry     = 0;
+                self.clipboard = None;
+                self.dirty     = false;
+                self.error     = None;
+            },
+            Err(error) => {
+                self.error = Some(format!("Could not read {path:?}: {error}"));
+            }
+        }
+    }
+
+    /// Close the PCG file, discarding unsaved changes, and go back to the file list
+    pub fn close (&mut self) {
+        self.pcg       = None;
+        self.path      = None;
+        self.clipboard = None;
+        self.dirty     = false;
+        self.prompt    = false;
+        self.update();
+    }
+
+    /// Write the PCG file back to where it was loaded from
+    pub fn save (&mut self) -> Result<bool> {
+        if let (Some(pcg), Some(path)) = (&self.pcg, &self.path) {
+            std::fs::File::create(path)?.write_all(&pcg.write())?;
+            self.dirty = false;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Kind of the listed entries
+    pub fn kind (&self) -> TritonBankKind {
+        TritonBankKind::ALL[self.tab]
+    }
+
+    /// The listed bank
+    pub fn current_bank (&self) -> Option<&TritonBank> {
+        self.pcg.as_ref()?.banks(self.kind()).get(self.bank).copied()
+    }
+
+    /// Position of the selected entry
+    pub fn slot (&self) -> Option<TritonSlot> {
+        let bank = self.current_bank()?;
+        (self.entry < bank.entries.len()).then_some(TritonSlot { bank: bank.bank, index: self.entry })
+    }
+
+    /// Switch to the next kind of entries
+    pub fn cycle_tab (&mut self) -> bool {
+        self.tab   = (self.tab + 1) % Self::TABS.len();
+        self.bank  = 0;
+        self.entry = 0;
+        true
+    }
+
+    /// Switch to the previous or next bank of the current kind
+    pub fn select_bank (&mut self, delta: isize) -> bool {
+        let count = self.pcg.as_ref().map(|pcg|pcg.banks(self.kind()).len()).unwrap_or(0);
+        let bank = self.bank as isize + delta;
+        if bank < 0 || bank >= count as isize {
+            return false
+        }
+        self.bank = bank as usize;
+        let entries = self.current_bank().map(|bank|bank.entries.len()).unwrap_or(0);
+        self.entry = self.entry.min(entries.saturating_sub(1));
+        true
+    }
+
+    /// Move the selection by a number of entries
+    pub fn select (&mut self, delta: isize) -> bool {
+        let entries = self.current_bank().map(|bank|bank.entries.len()).unwrap_or(0);
+        let entry = (self.entry as isize + delta).clamp(0, entries as isize - 1).max(0) as usize;
+        if entry == self.entry {
+            return false
+        }
+        self.entry = entry;
+        true
+    }
+
+    /// Switch the places of the selected entry and the one above or below it.
+    /// References to them are only pointed at their new places if `remap` is on.
+    pub fn move_entry (&mut self, delta: isize) -> bool {
+        let kind = self.kind();
+        let Some(slot) = self.slot() else { return false };
+        let other = TritonSlot { bank: slot.bank, index: (slot.index as isize + delta).max(0) as usize };
+        if other == slot {
+            return false
+        }
+        let Some(pcg) = &mut self.pcg else { return false };
+        if pcg.swap(kind, slot, other, self.remap) {
+            self.entry = other.index;
+            self.dirty = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Turn the rewriting of references on moved entries on or off
+    pub fn toggle_remap (&mut self) -> bool {
+        self.remap = !self.remap;
+        true
+    }
+
+    /// Mark the selected entry for copying
+    pub fn copy (&mut self) -> bool {
+        self.clipboard = self.slot().map(|slot|(self.kind(), slot));
+        self.clipboard.is_some()
+    }
+
+    /// Copy the marked entry over the selected entry
+    pub fn paste (&mut self) -> bool {
+        let (Some((kind, from)), Some(to)) = (self.clipboard, self.slot()) else { return false };
+        if kind != self.kind() || from == to {
+            return false
+        }
+        let Some(pcg) = &mut self.pcg else { return false };
+        let source = pcg.clone();
+        if pcg.copy(&source, kind, from, to) {
+            self.dirty = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Name of an entry, or - if there is none
+    pub fn entry_name (pcg: &TritonPCG, kind: TritonBankKind, slot: TritonSlot) -> String {
+        pcg.entry(kind, slot).map(|data|read_name(data)).unwrap_or_else(||"-".into())
+    }
+
+    pub fn field <T, U> (
+        label: &str, value: impl std::fmt::Display
+    ) -> Fixed<T, U> {
+        let white = |s: String|s.with(Color::White).bold();
+        let green = |s: String|s.with(Color::Green);
+        Fixed::XY((26, 3), Layers::new()
+            .add(Columns::new()
+                .add((2, 1))
+                .add(label.to_string().style(&white)))
+            .add(format!(" {}", value.to_string())
+                .style(&green)
+                .border(Tall, Inset)))
+    }
+
+    pub fn layout_tabs <T, U> (&self) -> Columns<T, U> {
+        let style1 = |s: String|s.on(Color::Yellow).with(Color::Black).bold();
+        let style2 = |s: String|s.with(Color::White);
+        let mut tabs = Columns::new();
+        for (index, label) in Self::TABS.iter().enumerate() {
+            tabs = tabs
+                .add(format!(" {label} ").style(&if index == self.tab { style1 } else { style2 }))
+                .add(1);
+        }
+        tabs
+    }
+
+    pub fn layout_entries <T, U> (&self, height: usize) -> Rows<T, U> {
+        let style1 = |s: String|s.on(Color::Yellow).with(Color::Black).bold();
+        let style2 = |s: String|s.with(Color::White);
+        let Some(bank) = self.current_bank() else {
+            return Rows::new().add(format!(" No {} in this file.", Self::TABS[self.tab].to_lowercase()))
+        };
+        let mut rows = Rows::new()
+            .add(format!(" Bank {} ({} entries)", bank.name(), bank.entries.len()).with(Color::White).bold());
+        // Scroll just enough to keep the selected entry in view
+        let offset = (self.entry + 1).saturating_sub(height);
+        for (index, name) in bank.names().iter().enumerate().skip(offset).take(height) {
+            let slot = TritonSlot { bank: bank.bank, index };
+            let marked = if self.clipboard == Some((self.kind(), slot)) { "*" } else { " " };
+            rows = rows.add(format!("{marked}{slot} │ {name:<16}").style(&if index == self.entry {
+                style1
+            } else {
+                style2
+            }));
+        }
+        rows
+    }
+
+    pub fn layout_detail <T, U> (&self, pcg: &TritonPCG) -> Rows<T, U> {
+        let kind = self.kind();
+        let Some(slot) = self.slot() else {
+            return Rows::new()
+        };
+        let data = pcg.entry(kind, slot).unwrap();
+        let mut rows = Rows::new()
+            .add(Columns::new()
+                .add(Self::field(&kind.to_string(), slot))
+                .add(Self::field("Name", read_name(data)))
+                .add(Self::field("Size", format!("{} bytes", data.len()))));
+        // The parameters are not decoded, so show them as they are stored
+        let end = data.len().min(NAME_SIZE + Self::DUMP_SIZE);
+        for (row, bytes) in data[NAME_SIZE.min(end)..end].chunks(16).enumerate() {
+            rows = rows.add(format!(" {:04X} │ {}", NAME_SIZE + row * 16, bytes.iter()
+                .map(|byte|format!("{byte:02X}"))
+                .collect::<Vec<_>>()
+                .join(" ")));
+        }
+        if kind == TritonBankKind::Program || kind == TritonBankKind::Combi {
+            rows = rows.add(" References (unconfirmed offsets):");
+        }
+        match kind {
+            TritonBankKind::Program => {
+                let mode = match data.get(PROGRAM_OSC_MODE) {
+                    Some(0) => "Single".to_string(),
+                    Some(1) => "Double".to_string(),
+                    Some(&OSC_MODE_DRUMS) => "Drums".to_string(),
+                    Some(mode) => format!("{mode}"),
+                    None => "-".to_string(),
+                };
+                let mut columns = Columns::new().add(Self::field("Oscillator", mode));
+                for (target, slot) in referenced(kind, data) {
+                    columns = columns.add(Self::field(&target.to_string(),
+                        format!("{slot} {}", Self::entry_name(pcg, target, slot))));
+                }
+                rows = rows.add(columns);
+            },
+            TritonBankKind::Combi => {
+                let timbres = referenced(kind, data);
+                for (row, pair) in timbres.chunks(2).enumerate() {
+                    let mut columns = Columns::new();
+                    for (column, (target, slot)) in pair.iter().enumerate() {
+                        columns = columns.add(Self::field(&format!("Timbre {}", row * 2 + column + 1),
+                            format!("{slot} {}", Self::entry_name(pcg, *target, *slot))));
+                    }
+                    rows = rows.add(columns);
+                }
+            },
+            _ => {}
+        }
+        let users = pcg.users(kind, slot);
+        if !users.is_empty() {
+            rows = rows.add(format!(" Used by: {}", users.iter()
+                .take(8)
+                .map(|(kind, slot)|format!("{kind} {slot}"))
+                .collect::<Vec<_>>()
+                .join(", ")));
+        }
+        rows
+    }
+
+}
+
+impl<W: Write> Output<TUI<W>, [u16;2]> for TritonUI {
+
+    fn render (&self, engine: &mut TUI<W>) -> Result<Option<[u16;2]>> {
+        if let Some(pcg) = &self.pcg {
+            let height = engine.area.h().saturating_sub(7) as usize;
+            let modified = if self.dirty { " (modified)" } else { "" };
+            let remap = if self.remap { ", remapping references" } else { "" };
+            let help = if self.prompt {
+                format!(" Save changes to {:?}? y: save │ n: discard │ Esc: cancel",
+                    self.path.as_ref().map(|path|path.display().to_string()).unwrap_or_default())
+                    .on(Color::Yellow).with(Color::Black).bold()
+            } else {
+                Self::HELP.to_string().with(Color::White)
+            };
+            Rows::new()
+                .add(format!(" Contents of this file{modified}{remap}:"))
+                .add(self.layout_tabs())
+                .add(Columns::new()
+                    .border(Tall, Inset)
+                    .add(self.layout_entries(height))
+                    .add(2)
+                    .add(self.layout_detail(pcg)))
+                .add(help)
+        } else {
+            Rows::new()
+                .add(" Select PCG file:")
+                .add(Rows::new().border(Tall, Inset).add(&self.file_list))
+                .add(format!(" {}", self.error.as_deref().unwrap_or("")).with(Color::Red))
+        }.render(engine)
+    }
+
+}
+
+impl Input<TUIInputEvent, bool> for TritonUI {
+
+    fn handle (&mut self, event: TUIInputEvent) -> Result<Option<bool>> {
+        Ok(if self.pcg.is_some() {
+            if self.prompt {
+                // Nothing else happens until the question is answered
+                if let Event::Key(KeyEvent { code: KeyCode::Char('y'), .. }) = *event {
+                    self.save()?;
+                    self.close();
+                } else if let Event::Key(KeyEvent { code: KeyCode::Char('n'), .. }) = *event {
+                    self.close();
+                } else if *event == key!(Esc) {
+                    self.prompt = false;
+                }
+                true
+            } else if *event == key!(Esc) {
+                if self.dirty {
+                    self.prompt = true;
+                } else {
+                    self.close();
+                }
+                true
+            } else if let Event::Key(KeyEvent { code: KeyCode::Char('q'), .. }) = *event {
+                // Hold off quitting until the changes are saved or discarded
+                self.prompt = self.dirty;
+                self.dirty
+            } else if *event == key!(Tab) {
+                self.cycle_tab()
+            } else if *event == key!(Alt-Up) {
+                self.move_entry(-1)
+            } else if *event == key!(Alt-Down) {
+                self.move_entry(1)
+            } else if *event == key!(Up) {
+                self.select(-1)
+            } else if *event == key!(Down) {
+                self.select(1)
+            } else if *event == key!(PageUp) {
+                self.select(-16)
+            } else if *event == key!(PageDown) {
+                self.select(16)
+            } else if *event == key!(Left) {
+                self.select_bank(-1)
+            } else if *event == key!(Right) {
+                self.select_bank(1)
+            } else if let Event::Key(KeyEvent { code: KeyCode::Char('c'), .. }) = *event {
+                self.copy()
+            } else if let Event::Key(KeyEvent { code: KeyCode::Char('v'), .. }) = *event {
+                self.paste()
+            } else if let Event::Key(KeyEvent { code: KeyCode::Char('r'), .. }) = *event {
+                self.toggle_remap()
+            } else if let Event::Key(KeyEvent { code: KeyCode::Char('s'), .. }) = *event {
+                self.save()?
+            } else {
+                false
+            }
+        } else {
+            self.file_list.handle(event)? || if_key!(event => Enter => {
+                if let Some(FileEntry { path, is_dir, .. }) = self.file_list.selected() {
+                    if *is_dir {
+                        std::env::set_current_dir(path)?;
+                        self.update();
+                    } else {
+                        self.import(&std::path::PathBuf::from(path));
+                    }
+                    true
+                } else {
+                    false
+                }
+            })
+        })
+    }
+
+}
@@ -6,6 +6,9 @@ opt_mod::optional_module_flat!("tui": tui);
 pub mod akai;
 pub mod iconnectivity;
 pub mod korg;
+pub mod filename;
+pub mod midi;
+pub mod wav;
 
 pub fn main () {
     #[cfg(feature="cli")]
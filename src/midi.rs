@@ -0,0 +1,441 @@
+//! Minimal Standard MIDI File support, shared by the device modules
+//! for exchanging sequences and patterns with a DAW.
+
+/// Chunk tag of the file header
+const MTHD: [u8; 4] = [0x4D, 0x54, 0x68, 0x64];
+/// Chunk tag of a track
+const MTRK: [u8; 4] = [0x4D, 0x54, 0x72, 0x6B];
+
+/// A Standard MIDI File
+#[derive(Debug, Clone)]
+pub struct SMF {
+    /// 0 = single track, 1 = multiple simultaneous tracks
+    pub format: u16,
+    /// Ticks per quarter note
+    pub ppqn:   u16,
+    /// Tracks in this file
+    pub tracks: Vec<Track>
+}
+
+impl SMF {
+
+    /// Create an empty type-1 file
+    pub fn new (ppqn: u16) -> Self {
+        Self { format: 1, ppqn, tracks: vec![] }
+    }
+
+    /// Add a track and return a reference to it
+    pub fn track (&mut self, name: &str) -> &mut Track {
+        self.tracks.push(Track::new(name));
+        self.tracks.last_mut().unwrap()
+    }
+
+    /// Read the contents of a .mid file. System exclusive and unsupported
+    /// meta events are skipped.
+    pub fn read (raw: &[u8]) -> Result<Self, String> {
+        if raw.len() < 14 || raw[0..4] != MTHD {
+            return Err("not a MIDI file".into())
+        }
+        let header = u32::from_be_bytes([raw[4], raw[5], raw[6], raw[7]]) as usize;
+        let format = u16::from_be_bytes([raw[8], raw[9]]);
+        let count  = u16::from_be_bytes([raw[10], raw[11]]) as usize;
+        let ppqn   = u16::from_be_bytes([raw[12], raw[13]]);
+        if ppqn & 0x8000 != 0 {
+            return Err("SMPTE time division is not supported".into())
+        }
+        let mut smf = Self { format, ppqn, tracks: vec![] };
+        let mut index = 8 + header;
+        while smf.tracks.len() < count && index + 8 <= raw.len() {
+            let size = u32::from_be_bytes([raw[index+4], raw[index+5], raw[index+6], raw[index+7]]) as usize;
+            let end  = (index + 8).saturating_add(size).min(raw.len());
+            if raw[index..index+4] == MTRK {
+                let track = Track::read(&raw[index+8..end])
+                    .map_err(|e|format!("track {}: {e}", smf.tracks.len() + 1))?;
+                smf.tracks.push(track);
+            }
+            index = end;
+        }
+        Ok(smf)
+    }
+
+    /// Serialize to the contents of a .mid file
+    pub fn write (&self) -> Vec<u8> {
+        let mut raw = vec![];
+        raw.extend_from_slice(&MTHD);
+        raw.extend_from_slice(&6u32.to_be_bytes());
+        raw.extend_from_slice(&self.format.to_be_bytes());
+        raw.extend_from_slice(&(self.tracks.len() as u16).to_be_bytes());
+        raw.extend_from_slice(&self.ppqn.to_be_bytes());
+        for track in self.tracks.iter() {
+            let data = track.write();
+            raw.extend_from_slice(&MTRK);
+            raw.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            raw.extend_from_slice(&data);
+        }
+        raw
+    }
+
+}
+
+/// A track of a Standard MIDI File. Events are stored with absolute times
+/// and sorted when the track is written.
+#[derive(Debug, Clone, Default)]
+pub struct Track {
+    pub events: Vec<(u32, Event)>
+}
+
+impl Track {
+
+    /// Create a track, named if the name is not empty
+    pub fn new (name: &str) -> Self {
+        let mut track = Self::default();
+        if !name.is_empty() {
+            track.events.push((0, Event::TrackName(name.into())));
+        }
+        track
+    }
+
+    /// Read the events of a track chunk, without the chunk header
+    pub fn read (raw: &[u8]) -> Result<Self, String> {
+        let mut track   = Self::default();
+        let mut index   = 0;
+        let mut time    = 0u32;
+        let mut running = 0u8;
+        while index < raw.len() {
+            time = time.saturating_add(read_varlen(raw, &mut index));
+            let Some(mut status) = raw.get(index).copied() else {
+                return Err(format!("track ends after the delta time at {index:#x}"))
+            };
+            if status & 0x80 == 0 {
+                // Running status: reuse the previous status byte
+                if running == 0 {
+                    return Err(format!("running status without an earlier status byte at {index:#x}"))
+                }
+                status = running;
+            } else {
+                index += 1;
+            }
+            let size = match status {
+                0x80..=0xBF | 0xE0..=0xEF => 2,
+                0xC0..=0xDF | 0xFF => 1,
+                0xF0 | 0xF7 => 0,
+                _ => return Err(format!("invalid MIDI status byte 0x{status:02X} at {:#x}", index - 1))
+            };
+            if index + size > raw.len() {
+                return Err(format!("track ends in the middle of an event at {index:#x}"))
+            }
+            let channel = status & 0x0F;
+            let event = match status & 0xF0 {
+                0x80 => Some(Event::NoteOff { channel, note: raw[index], velocity: raw[index+1] }),
+                0x90 if raw[index+1] == 0 => Some(Event::NoteOff { channel, note: raw[index], velocity: 0 }),
+                0x90 => Some(Event::NoteOn { channel, note: raw[index], velocity: raw[index+1] }),
+                0xA0 => None,
+                0xB0 => Some(Event::Control { channel, control: raw[index], value: raw[index+1] }),
+                0xC0 => Some(Event::Program { channel, program: raw[index] }),
+                0xD0 => Some(Event::Pressure { channel, value: raw[index] }),
+                0xE0 => Some(Event::PitchBend { channel, value: raw[index] as u16 | (raw[index+1] as u16) << 7 }),
+                _ => None
+            };
+            match status {
+                0x80..=0xBF | 0xE0..=0xEF => { running = status; index += 2; },
+                0xC0..=0xDF => { running = status; index += 1; },
+                0xFF => {
+                    let kind = raw[index];
+                    index += 1;
+                    let length = read_varlen(raw, &mut index) as usize;
+                    let data = &raw[index..(index+length).min(raw.len())];
+                    index += length;
+                    match kind {
+                        0x03 => track.add(time, Event::TrackName(String::from_utf8_lossy(data).into())),
+                        0x06 => track.add(time, Event::Marker(String::from_utf8_lossy(data).into())),
+                        0x51 if data.len() == 3 => track.add(time, Event::Tempo(
+                            u32::from_be_bytes([0, data[0], data[1], data[2]])
+                        )),
+                        0x58 if data.len() >= 2 => track.add(time, Event::TimeSignature(
+                            data[0], 1 << data[1]
+                        )),
+                        0x2F => break,
+                        _ => &mut track
+                    };
+                },
+                _ => {
+                    // System exclusive
+                    let length = read_varlen(raw, &mut index) as usize;
+                    index += length;
+                },
+            }
+            if let Some(event) = event {
+                track.add(time, event);
+            }
+        }
+        Ok(track)
+    }
+
+    /// The name of the track, if it has one
+    pub fn name (&self) -> Option<&str> {
+        self.events.iter().find_map(|(_, event)|match event {
+            Event::TrackName(name) => Some(name.as_str()),
+            _ => None
+        })
+    }
+
+    /// Pair note ons with their note offs
+    pub fn notes (&self) -> Vec<Note> {
+        let mut events = self.events.iter().collect::<Vec<_>>();
+        events.sort_by_key(|(time, _)|*time);
+        let mut open: Vec<Note> = vec![];
+        let mut notes = vec![];
+        for (time, event) in events {
+            match event {
+                Event::NoteOn { channel, note, velocity } => {
+                    open.push(Note { time: *time, duration: 0, channel: *channel, note: *note, velocity: *velocity });
+                },
+                Event::NoteOff { channel, note, .. } => {
+                    if let Some(index) = open.iter().position(|x|x.channel == *channel && x.note == *note) {
+                        let mut done = open.remove(index);
+                        done.duration = time - done.time;
+                        notes.push(done);
+                    }
+                },
+                _ => {}
+            }
+        }
+        notes.sort_by_key(|note|note.time);
+        notes
+    }
+
+    /// Add an event at an absolute time
+    pub fn add (&mut self, time: u32, event: Event) -> &mut Self {
+        self.events.push((time, event));
+        self
+    }
+
+    /// Add a note on and the matching note off
+    pub fn note (
+        &mut self, time: u32, duration: u32, channel: u8, note: u8, velocity: u8
+    ) -> &mut Self {
+        self.add(time, Event::NoteOn { channel, note, velocity })
+            .add(time + duration.max(1), Event::NoteOff { channel, note, velocity: 0 })
+    }
+
+    /// Serialize the track's events with delta times, adding the end of track marker
+    pub fn write (&self) -> Vec<u8> {
+        let mut events = self.events.iter().collect::<Vec<_>>();
+        // Stable sort, with note offs before note ons at the same time
+        events.sort_by_key(|(time, event)|(*time, match event {
+            Event::TrackName(_) => 0,
+            Event::NoteOff { .. } => 1,
+            _ => 2
+        }));
+        let mut raw = vec![];
+        let mut now = 0;
+        for (time, event) in events {
+            write_varlen(&mut raw, time - now);
+            now = *time;
+            event.write(&mut raw);
+        }
+        write_varlen(&mut raw, 0);
+        raw.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+        raw
+    }
+
+}
+
+/// A note with its duration, as paired up by `Track::notes`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Note {
+    pub time:     u32,
+    pub duration: u32,
+    pub channel:  u8,
+    pub note:     u8,
+    pub velocity: u8,
+}
+
+/// An event in a track
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    NoteOff       { channel: u8, note: u8, velocity: u8 },
+    NoteOn        { channel: u8, note: u8, velocity: u8 },
+    Control       { channel: u8, control: u8, value: u8 },
+    Program       { channel: u8, program: u8 },
+    Pressure      { channel: u8, value: u8 },
+    PitchBend     { channel: u8, value: u16 },
+    /// Microseconds per quarter note
+    Tempo         (u32),
+    /// Numerator and denominator
+    TimeSignature (u8, u8),
+    TrackName     (String),
+    Marker        (String),
+}
+
+impl Event {
+
+    /// Tempo change event from beats per minute
+    pub fn tempo (bpm: f32) -> Self {
+        Self::Tempo((60_000_000.0 / bpm).round() as u32)
+    }
+
+    /// Append the event, without delta time, to a buffer
+    pub fn write (&self, raw: &mut Vec<u8>) {
+        match self {
+            Self::NoteOff { channel, note, velocity } =>
+                raw.extend_from_slice(&[0x80 | (channel & 0x0F), *note & 0x7F, *velocity & 0x7F]),
+            Self::NoteOn { channel, note, velocity } =>
+                raw.extend_from_slice(&[0x90 | (channel & 0x0F), *note & 0x7F, *velocity & 0x7F]),
+            Self::Control { channel, control, value } =>
+                raw.extend_from_slice(&[0xB0 | (channel & 0x0F), *control & 0x7F, *value & 0x7F]),
+            Self::Program { channel, program } =>
+                raw.extend_from_slice(&[0xC0 | (channel & 0x0F), *program & 0x7F]),
+            Self::Pressure { channel, value } =>
+                raw.extend_from_slice(&[0xD0 | (channel & 0x0F), *value & 0x7F]),
+            Self::PitchBend { channel, value } =>
+                raw.extend_from_slice(&[0xE0 | (channel & 0x0F), (value & 0x7F) as u8, ((value >> 7) & 0x7F) as u8]),
+            Self::Tempo(tempo) => {
+                let bytes = tempo.to_be_bytes();
+                raw.extend_from_slice(&[0xFF, 0x51, 0x03, bytes[1], bytes[2], bytes[3]])
+            },
+            Self::TimeSignature(numerator, denominator) => {
+                let power = (*denominator as f32).log2() as u8;
+                raw.extend_from_slice(&[0xFF, 0x58, 0x04, *numerator, power, 24, 8])
+            },
+            Self::TrackName(text) => write_text(raw, 0x03, text),
+            Self::Marker(text) => write_text(raw, 0x06, text),
+        }
+    }
+
+}
+
+/// Append a text meta event to a buffer
+fn write_text (raw: &mut Vec<u8>, kind: u8, text: &str) {
+    raw.extend_from_slice(&[0xFF, kind]);
+    write_varlen(raw, text.len() as u32);
+    raw.extend_from_slice(text.as_bytes());
+}
+
+/// Read a variable-length quantity, advancing the index past it
+pub fn read_varlen (raw: &[u8], index: &mut usize) -> u32 {
+    let mut value = 0;
+    while *index < raw.len() {
+        let byte = raw[*index];
+        *index += 1;
+        value = (value << 7) | (byte & 0x7F) as u32;
+        if byte & 0x80 == 0 {
+            break
+        }
+    }
+    value
+}
+
+/// Append a variable-length quantity to a buffer
+pub fn write_varlen (raw: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.push(0x80 | (value & 0x7F) as u8);
+        value >>= 7;
+    }
+    bytes.reverse();
+    raw.extend_from_slice(&bytes);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn varlen () {
+        // The examples of variable-length quantities from the Standard MIDI File specification
+        let examples: [(u32, &[u8]); 12] = [
+            (0x00000000, &[0x00]),
+            (0x00000040, &[0x40]),
+            (0x0000007F, &[0x7F]),
+            (0x00000080, &[0x81, 0x00]),
+            (0x00002000, &[0xC0, 0x00]),
+            (0x00003FFF, &[0xFF, 0x7F]),
+            (0x00004000, &[0x81, 0x80, 0x00]),
+            (0x00100000, &[0xC0, 0x80, 0x00]),
+            (0x001FFFFF, &[0xFF, 0xFF, 0x7F]),
+            (0x00200000, &[0x81, 0x80, 0x80, 0x00]),
+            (0x08000000, &[0xC0, 0x80, 0x80, 0x00]),
+            (0x0FFFFFFF, &[0xFF, 0xFF, 0xFF, 0x7F]),
+        ];
+        for (value, bytes) in examples {
+            let mut raw = vec![];
+            write_varlen(&mut raw, value);
+            assert_eq!(raw, bytes);
+            let mut index = 0;
+            assert_eq!(read_varlen(bytes, &mut index), value);
+            assert_eq!(index, bytes.len());
+        }
+    }
+
+    #[test]
+    fn write_read () {
+        let mut smf = SMF::new(96);
+        smf.track("Conductor")
+            .add(0, Event::tempo(120.0))
+            .add(0, Event::TimeSignature(3, 4))
+            .add(288, Event::Marker("B".into()));
+        smf.track("Bass")
+            .note(0, 48, 1, 36, 100)
+            .note(0x4000, 0x200, 1, 38, 90)
+            .add(10, Event::Control { channel: 1, control: 7, value: 64 })
+            .add(20, Event::Program { channel: 1, program: 5 })
+            .add(30, Event::Pressure { channel: 1, value: 33 })
+            .add(40, Event::PitchBend { channel: 1, value: 0x2000 });
+        let read = SMF::read(&smf.write()).unwrap();
+        assert_eq!(read.format, 1);
+        assert_eq!(read.ppqn, 96);
+        assert_eq!(read.tracks.len(), 2);
+        assert_eq!(read.tracks[0].name(), Some("Conductor"));
+        assert!(read.tracks[0].events.contains(&(0, Event::Tempo(500_000))));
+        assert!(read.tracks[0].events.contains(&(0, Event::TimeSignature(3, 4))));
+        assert!(read.tracks[0].events.contains(&(288, Event::Marker("B".into()))));
+        let bass = &read.tracks[1];
+        assert_eq!(bass.name(), Some("Bass"));
+        assert_eq!(bass.notes(), vec![
+            Note { time: 0,      duration: 48,    channel: 1, note: 36, velocity: 100 },
+            Note { time: 0x4000, duration: 0x200, channel: 1, note: 38, velocity: 90 },
+        ]);
+        assert!(bass.events.contains(&(10, Event::Control { channel: 1, control: 7, value: 64 })));
+        assert!(bass.events.contains(&(20, Event::Program { channel: 1, program: 5 })));
+        assert!(bass.events.contains(&(30, Event::Pressure { channel: 1, value: 33 })));
+        assert!(bass.events.contains(&(40, Event::PitchBend { channel: 1, value: 0x2000 })));
+        assert_eq!(read.write(), smf.write());
+    }
+
+    #[test]
+    fn running_status () {
+        let track = Track::read(&[
+            0x00, 0x90, 0x3C, 0x64,    // note on
+            0x60, 0x3C, 0x00,          // running status: note on with velocity 0
+            0x81, 0x00, 0x3E, 0x50,    // 128 ticks later, running status: note on
+            0x00, 0xF0, 0x02, 0x7E, 0xF7, // system exclusive, skipped
+            0x10, 0x80, 0x3E, 0x00,    // note off
+            0x00, 0xFF, 0x2F, 0x00,
+        ]).unwrap();
+        assert_eq!(track.notes(), vec![
+            Note { time: 0x00,  duration: 0x60, channel: 0, note: 0x3C, velocity: 0x64 },
+            Note { time: 0xE0,  duration: 0x10, channel: 0, note: 0x3E, velocity: 0x50 },
+        ]);
+    }
+
+    #[test]
+    fn read_errors () {
+        assert!(SMF::read(b"RIFF").is_err());
+        // Running status before any status byte
+        assert!(Track::read(&[0x00, 0x3C, 0x64]).is_err());
+        // Undefined status byte
+        assert!(Track::read(&[0x00, 0xF4, 0x00]).is_err());
+        // Note on without its velocity
+        assert!(Track::read(&[0x00, 0x90, 0x3C]).is_err());
+        let mut smf = SMF::new(96);
+        smf.track("Truncated").note(0, 96, 0, 60, 100);
+        let mut raw = smf.write();
+        raw.truncate(raw.len() - 5);
+        assert!(SMF::read(&raw).is_err());
+        raw[12] = 0xE7;
+        assert!(SMF::read(&raw).is_err());
+    }
+
+}
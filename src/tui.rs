@@ -48,12 +48,16 @@ impl<E, I, O> Output<E, O> for App<E, I, O> {
 }
 
 impl<E, I, O> Input<E, I> for App<E, I, O> {
+    /// Keys go to the current device first, so that it can take text input
+    /// or hold off quitting while it has unsaved changes.
     fn handle (&mut self, engine: &mut E) -> Result<Option<I>> {
-        Ok(if let Event::Key(KeyEvent { code: KeyCode::Char('q'), .. }) = engine.event {
+        Ok(if self.devices.handle(engine.event)? {
+            true
+        } else if let Event::Key(KeyEvent { code: KeyCode::Char('q'), .. }) = engine.event {
             self.exit();
             true
         } else {
-            self.devices.handle(engine.event)?
+            false
         })
     }
 }
@@ -0,0 +1,72 @@
+//! Minimal RIFF WAV support, shared by the device modules for finding chunks
+//! and for writing and reading the root key and loop of a smpl chunk.
+
+/// Find the data of a top-level chunk of a RIFF file
+pub fn riff_chunk <'a> (wav: &'a [u8], id: &[u8; 4]) -> Option<&'a [u8]> {
+    riff_subchunk(wav.get(12..)?, id)
+}
+
+/// Find the data of a chunk in a sequence of chunks
+pub fn riff_subchunk <'a> (raw: &'a [u8], id: &[u8; 4]) -> Option<&'a [u8]> {
+    let mut index = 0;
+    while index + 8 <= raw.len() {
+        let size = u32::from_le_bytes([raw[index+4], raw[index+5], raw[index+6], raw[index+7]]) as usize;
+        let end  = (index + 8 + size).min(raw.len());
+        if &raw[index..index+4] == id {
+            return Some(&raw[index+8..end])
+        }
+        // Chunks are padded to an even length
+        index = end + size % 2;
+    }
+    None
+}
+
+/// Append a chunk to a RIFF file, padded to an even length
+pub fn write_riff_chunk (raw: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    raw.extend_from_slice(id);
+    raw.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    raw.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        raw.push(0);
+    }
+}
+
+/// Contents of a smpl chunk with a root key and an optional forward loop, in frames
+pub fn smpl (sample_rate: u32, root_key: u8, looped: Option<(u32, u32)>) -> Vec<u8> {
+    let mut raw = vec![];
+    raw.extend_from_slice(&0u32.to_le_bytes()); // manufacturer
+    raw.extend_from_slice(&0u32.to_le_bytes()); // product
+    raw.extend_from_slice(&(1_000_000_000 / sample_rate.max(1)).to_le_bytes()); // sample period in ns
+    raw.extend_from_slice(&(root_key as u32).to_le_bytes()); // MIDI unity note
+    raw.extend_from_slice(&0u32.to_le_bytes()); // pitch fraction
+    raw.extend_from_slice(&0u32.to_le_bytes()); // SMPTE format
+    raw.extend_from_slice(&0u32.to_le_bytes()); // SMPTE offset
+    raw.extend_from_slice(&(looped.is_some() as u32).to_le_bytes());
+    raw.extend_from_slice(&0u32.to_le_bytes()); // sampler data
+    if let Some((start, end)) = looped {
+        raw.extend_from_slice(&0u32.to_le_bytes()); // cue point id
+        raw.extend_from_slice(&0u32.to_le_bytes()); // forward loop
+        raw.extend_from_slice(&start.to_le_bytes());
+        raw.extend_from_slice(&end.to_le_bytes());
+        raw.extend_from_slice(&0u32.to_le_bytes()); // fraction
+        raw.extend_from_slice(&0u32.to_le_bytes()); // play count, 0 = infinite
+    }
+    raw
+}
+
+/// The MIDI unity note from the smpl chunk of a WAV file
+pub fn wav_root_key (wav: &[u8]) -> Option<u8> {
+    let smpl = riff_chunk(wav, b"smpl")?;
+    let note = u32::from_le_bytes(smpl.get(12..16)?.try_into().unwrap());
+    (note < 128).then_some(note as u8)
+}
+
+/// The first loop from the smpl chunk of a WAV file, as start and end frames
+pub fn wav_loop (wav: &[u8]) -> Option<(u32, u32)> {
+    let smpl = riff_chunk(wav, b"smpl")?;
+    let u32_at = |i: usize|smpl.get(i..i+4).map(|x|u32::from_le_bytes(x.try_into().unwrap()));
+    if u32_at(28)? == 0 {
+        return None
+    }
+    Some((u32_at(44)?, u32_at(48)?))
+}